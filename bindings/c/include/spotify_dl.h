@@ -0,0 +1,61 @@
+/*
+ * C interface of the spotify-dl download engine, implemented by
+ * libspotify_dl_ffi (see bindings/c).
+ *
+ * Functions that can fail return NULL and leave a description of the error
+ * for spotify_dl_last_error(). Strings returned by the library belong to the
+ * caller and are freed with spotify_dl_string_free().
+ */
+#ifndef SPOTIFY_DL_H
+#define SPOTIFY_DL_H
+
+#include <stdbool.h>
+#include <stddef.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct SpotifyDl SpotifyDl;
+
+typedef enum SpotifyDlOutcome {
+    SPOTIFY_DL_DOWNLOADED = 0,
+    SPOTIFY_DL_SKIPPED = 1,
+    SPOTIFY_DL_FAILED = 2,
+    SPOTIFY_DL_DEFERRED = 3,
+} SpotifyDlOutcome;
+
+/* destination and format may be NULL for the current directory and mp3. */
+typedef struct SpotifyDlOptions {
+    const char *destination;
+    const char *format;
+    size_t parallel;
+    bool force;
+} SpotifyDlOptions;
+
+/* Called from the engine's worker threads as each track finishes. */
+typedef void (*SpotifyDlProgress)(const char *track_uri, SpotifyDlOutcome outcome,
+                                  void *user_data);
+
+/* Message of the last error on this thread, valid until the next call. */
+const char *spotify_dl_last_error(void);
+
+/* Logs in with the credentials cached by the CLI, or through the browser. */
+SpotifyDl *spotify_dl_connect(void);
+void spotify_dl_free(SpotifyDl *session);
+
+/* JSON array of the track URIs the inputs resolve to. */
+char *spotify_dl_resolve(const SpotifyDl *session, const char *const *inputs, size_t count);
+
+/* Downloads the inputs and returns the run summary as JSON. progress may be NULL. */
+char *spotify_dl_download(const SpotifyDl *session, const char *const *inputs, size_t count,
+                          const SpotifyDlOptions *options, SpotifyDlProgress progress,
+                          void *user_data);
+
+void spotify_dl_string_free(char *value);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* SPOTIFY_DL_H */
@@ -0,0 +1,240 @@
+//! C ABI of the spotify-dl download engine, declared in
+//! `include/spotify_dl.h`.
+//!
+//! Functions that can fail return `NULL` and leave a description of the
+//! error for `spotify_dl_last_error`. Strings returned by
+//! the library are owned by the caller and freed with
+//! `spotify_dl_string_free`.
+
+use std::cell::RefCell;
+use std::ffi::{CStr, CString, c_char, c_void};
+use std::ptr;
+
+use anyhow::Result;
+use spotify_dl::download::{DownloadOptions, OutcomeListener, TrackOutcome};
+use spotify_dl::encoder::Format;
+use spotify_dl::engine::Engine;
+
+/// A logged in session, opaque to C.
+pub struct SpotifyDl(Engine);
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpotifyDlOutcome {
+    Downloaded = 0,
+    Skipped = 1,
+    Failed = 2,
+    Deferred = 3,
+}
+
+impl From<TrackOutcome> for SpotifyDlOutcome {
+    fn from(outcome: TrackOutcome) -> Self {
+        match outcome {
+            TrackOutcome::Downloaded => SpotifyDlOutcome::Downloaded,
+            TrackOutcome::Skipped => SpotifyDlOutcome::Skipped,
+            TrackOutcome::Failed => SpotifyDlOutcome::Failed,
+            TrackOutcome::Deferred => SpotifyDlOutcome::Deferred,
+        }
+    }
+}
+
+/// Download settings. `destination` and `format` may be `NULL` for the
+/// current directory and MP3.
+#[repr(C)]
+pub struct SpotifyDlOptions {
+    pub destination: *const c_char,
+    pub format: *const c_char,
+    pub parallel: usize,
+    pub force: bool,
+}
+
+/// Called with the URI and outcome of every track once it is done, from the
+/// engine's worker threads.
+pub type SpotifyDlProgress = Option<
+    unsafe extern "C" fn(
+        track_uri: *const c_char,
+        outcome: SpotifyDlOutcome,
+        user_data: *mut c_void,
+    ),
+>;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(err: anyhow::Error) {
+    let message = CString::new(format!("{:#}", err).replace('\0', "")).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+}
+
+fn into_c_string(value: String) -> Result<*mut c_char> {
+    Ok(CString::new(value)?.into_raw())
+}
+
+/// Reads `count` C strings from `inputs`.
+///
+/// # Safety
+///
+/// `inputs` must point to `count` valid, NUL terminated strings.
+unsafe fn read_inputs(inputs: *const *const c_char, count: usize) -> Result<Vec<String>> {
+    if inputs.is_null() {
+        return Err(anyhow::anyhow!("inputs is NULL"));
+    }
+    let inputs = unsafe { std::slice::from_raw_parts(inputs, count) };
+    inputs
+        .iter()
+        .map(|&input| unsafe { read_str(input) }?.ok_or(anyhow::anyhow!("An input is NULL")))
+        .collect()
+}
+
+/// # Safety
+///
+/// `value` must be `NULL` or a valid, NUL terminated string.
+unsafe fn read_str(value: *const c_char) -> Result<Option<String>> {
+    if value.is_null() {
+        return Ok(None);
+    }
+    Ok(Some(unsafe { CStr::from_ptr(value) }.to_str()?.to_string()))
+}
+
+/// The message of the last error on this thread, or `NULL`. Valid until the
+/// next call into the library on this thread.
+#[unsafe(no_mangle)]
+pub extern "C" fn spotify_dl_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| {
+        last.borrow()
+            .as_ref()
+            .map_or(ptr::null(), |message| message.as_ptr())
+    })
+}
+
+/// Logs in with the credentials cached by the spotify-dl CLI, or through the
+/// browser when there are none yet. Returns `NULL` on failure.
+#[unsafe(no_mangle)]
+pub extern "C" fn spotify_dl_connect() -> *mut SpotifyDl {
+    match Engine::connect() {
+        Ok(engine) => Box::into_raw(Box::new(SpotifyDl(engine))),
+        Err(err) => {
+            set_last_error(err);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Closes a session returned by `spotify_dl_connect`.
+///
+/// # Safety
+///
+/// `session` must be `NULL` or a session that was not freed yet.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn spotify_dl_free(session: *mut SpotifyDl) {
+    if !session.is_null() {
+        drop(unsafe { Box::from_raw(session) });
+    }
+}
+
+/// Resolves songs, albums, playlists or podcasts to a JSON array of track
+/// URIs. Returns `NULL` on failure.
+///
+/// # Safety
+///
+/// `session` must be a live session and `inputs` must point to `count`
+/// valid, NUL terminated strings.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn spotify_dl_resolve(
+    session: *const SpotifyDl,
+    inputs: *const *const c_char,
+    count: usize,
+) -> *mut c_char {
+    let resolved = (|| {
+        let session = unsafe { session.as_ref() }.ok_or(anyhow::anyhow!("session is NULL"))?;
+        let inputs = unsafe { read_inputs(inputs, count) }?;
+        let uris = session.0.resolve(&inputs)?;
+        into_c_string(serde_json::to_string(&uris)?)
+    })();
+    resolved.unwrap_or_else(|err| {
+        set_last_error(err);
+        ptr::null_mut()
+    })
+}
+
+/// Downloads the inputs and returns the run summary as JSON, with the
+/// `downloaded` and `failed` tracks and the number of `skipped` ones,
+/// counted by reason in `skip_reasons`.
+/// `progress` may be `NULL`. Returns `NULL` when the run failed as a whole.
+///
+/// # Safety
+///
+/// `session` must be a live session, `inputs` must point to `count` valid,
+/// NUL terminated strings and `options` must be valid. `progress` must be
+/// safe to call from other threads with `user_data`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn spotify_dl_download(
+    session: *const SpotifyDl,
+    inputs: *const *const c_char,
+    count: usize,
+    options: *const SpotifyDlOptions,
+    progress: SpotifyDlProgress,
+    user_data: *mut c_void,
+) -> *mut c_char {
+    let summary = (|| {
+        let session = unsafe { session.as_ref() }.ok_or(anyhow::anyhow!("session is NULL"))?;
+        let options = unsafe { options.as_ref() }.ok_or(anyhow::anyhow!("options is NULL"))?;
+        let inputs = unsafe { read_inputs(inputs, count) }?;
+        let format = match unsafe { read_str(options.format) }? {
+            Some(format) => format.parse()?,
+            None => Format::Mp3,
+        };
+        let options = DownloadOptions::new(
+            unsafe { read_str(options.destination) }?,
+            options.parallel.max(1),
+            format,
+            options.force,
+        );
+        let listener = progress.map(|progress| progress_listener(progress, user_data));
+        let summary = session.0.download(&inputs, &options, listener)?;
+        into_c_string(serde_json::to_string(&summary)?)
+    })();
+    summary.unwrap_or_else(|err| {
+        set_last_error(err);
+        ptr::null_mut()
+    })
+}
+
+/// The caller promised `user_data` may be used from any thread.
+struct UserData(*mut c_void);
+
+unsafe impl Send for UserData {}
+unsafe impl Sync for UserData {}
+
+fn progress_listener(
+    progress: unsafe extern "C" fn(*const c_char, SpotifyDlOutcome, *mut c_void),
+    user_data: *mut c_void,
+) -> OutcomeListener {
+    let user_data = UserData(user_data);
+    Box::new(move |track, outcome| {
+        let Some(uri) = track
+            .id
+            .to_uri()
+            .ok()
+            .and_then(|uri| CString::new(uri).ok())
+        else {
+            return;
+        };
+        let user_data = &user_data;
+        unsafe { progress(uri.as_ptr(), outcome.into(), user_data.0) };
+    })
+}
+
+/// Frees a string returned by the library.
+///
+/// # Safety
+///
+/// `value` must be `NULL` or a string returned by the library that was not
+/// freed yet.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn spotify_dl_string_free(value: *mut c_char) {
+    if !value.is_null() {
+        drop(unsafe { CString::from_raw(value) });
+    }
+}
@@ -0,0 +1,101 @@
+//! Python module `spotify_dl`, a thin wrapper around the download engine:
+//!
+//! ```python
+//! import spotify_dl
+//!
+//! session = spotify_dl.Session()
+//! summary = session.download(
+//!     ["https://open.spotify.com/playlist/..."],
+//!     destination="/music",
+//!     progress=lambda uri, outcome: print(uri, outcome),
+//! )
+//! ```
+
+use engine::download::{DownloadOptions, TrackOutcome};
+use engine::encoder::Format;
+use engine::engine::Engine;
+use pyo3::exceptions::{PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+
+fn runtime_error(err: anyhow::Error) -> PyErr {
+    PyRuntimeError::new_err(format!("{:#}", err))
+}
+
+fn outcome_name(outcome: TrackOutcome) -> &'static str {
+    match outcome {
+        TrackOutcome::Downloaded => "downloaded",
+        TrackOutcome::Skipped => "skipped",
+        TrackOutcome::Failed => "failed",
+        TrackOutcome::Deferred => "deferred",
+    }
+}
+
+/// A logged in Spotify session. Uses the credentials cached by the
+/// spotify-dl CLI, or logs in through the browser when there are none yet.
+#[pyclass(name = "Session", module = "spotify_dl", frozen)]
+struct Session {
+    engine: Engine,
+}
+
+#[pymethods]
+impl Session {
+    #[new]
+    fn new(py: Python<'_>) -> PyResult<Self> {
+        let engine = py.detach(Engine::connect).map_err(runtime_error)?;
+        Ok(Session { engine })
+    }
+
+    /// The URIs of the tracks the given songs, albums, playlists or podcasts
+    /// resolve to.
+    fn resolve(&self, py: Python<'_>, inputs: Vec<String>) -> PyResult<Vec<String>> {
+        py.detach(|| self.engine.resolve(&inputs))
+            .map_err(runtime_error)
+    }
+
+    /// Downloads the inputs and returns the run summary as a dict with the
+    /// `downloaded` and `failed` tracks and the number of `skipped` ones,
+    /// counted by reason in `skip_reasons`.
+    /// `progress` is called with the URI and outcome ("downloaded",
+    /// "skipped" or "failed") of every track once it is done.
+    // The keyword arguments of the Python method.
+    #[allow(clippy::too_many_arguments)]
+    #[pyo3(signature = (inputs, destination=None, format="mp3", parallel=1, force=false, progress=None))]
+    fn download<'py>(
+        &self,
+        py: Python<'py>,
+        inputs: Vec<String>,
+        destination: Option<String>,
+        format: &str,
+        parallel: usize,
+        force: bool,
+        progress: Option<Py<PyAny>>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let format: Format = format
+            .parse()
+            .map_err(|err: anyhow::Error| PyValueError::new_err(err.to_string()))?;
+        let options = DownloadOptions::new(destination, parallel.max(1), format, force);
+        let listener = progress.map(|progress| {
+            Box::new(move |track: &engine::track::Track, outcome| {
+                let uri = track.id.to_uri().unwrap_or_default();
+                Python::attach(|py| {
+                    if let Err(err) = progress.call1(py, (uri, outcome_name(outcome))) {
+                        err.print(py);
+                    }
+                });
+            }) as engine::download::OutcomeListener
+        });
+
+        let summary = py
+            .detach(|| self.engine.download(&inputs, &options, listener))
+            .map_err(runtime_error)?;
+        let summary = serde_json::to_string(&summary)
+            .map_err(|err| PyRuntimeError::new_err(err.to_string()))?;
+        py.import("json")?.call_method1("loads", (summary,))
+    }
+}
+
+#[pymodule]
+fn spotify_dl(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<Session>()?;
+    Ok(())
+}
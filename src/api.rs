@@ -0,0 +1,174 @@
+use anyhow::Result;
+use bytes::Bytes;
+use http::{Method, Request, header};
+use librespot::core::session::Session;
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+
+const WEB_API: &str = "https://api.spotify.com/v1";
+/// Most track ids the audio features endpoint takes per request.
+const AUDIO_FEATURES_BATCH: usize = 100;
+/// Most playlist items the Web API returns per page.
+const PLAYLIST_PAGE: usize = 100;
+
+/// What the Web API knows about a playlist beyond what librespot's
+/// metadata channel returns.
+#[derive(Debug, Clone, Deserialize)]
+pub struct PlaylistInfo {
+    pub name: String,
+    /// HTML escaped, as the Web API serves it.
+    #[serde(default)]
+    pub description: Option<String>,
+    pub owner: User,
+    #[serde(default)]
+    pub followers: Option<Followers>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct User {
+    pub id: String,
+    #[serde(default)]
+    pub display_name: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Followers {
+    pub total: u64,
+}
+
+/// A track of a playlist with when and by whom it was added.
+#[derive(Debug, Clone, Deserialize)]
+pub struct PlaylistEntry {
+    /// RFC 3339, absent for very old playlists.
+    #[serde(default)]
+    pub added_at: Option<String>,
+    /// Absent for playlists generated by Spotify.
+    #[serde(default)]
+    pub added_by: Option<User>,
+    /// Absent for local files and tracks that were taken down.
+    #[serde(default)]
+    pub track: Option<EntryTrack>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct EntryTrack {
+    pub uri: String,
+}
+
+/// A page of playlist entries, as `/playlists/{id}/tracks` returns it.
+#[derive(Debug, Deserialize)]
+pub struct PlaylistPage {
+    pub items: Vec<PlaylistEntry>,
+    /// URL of the next page, if there is one.
+    #[serde(default)]
+    pub next: Option<String>,
+}
+
+/// Spotify's audio analysis summary of a track.
+#[derive(Debug, Clone, Deserialize)]
+pub struct AudioFeatures {
+    pub uri: String,
+    /// Beats per minute.
+    pub tempo: f32,
+    /// Pitch class of the key, 0 for C up to 11 for B, -1 when unknown.
+    pub key: i32,
+    /// 1 for major, 0 for minor.
+    pub mode: i32,
+    pub time_signature: i32,
+    pub danceability: f32,
+    pub energy: f32,
+    pub valence: f32,
+    pub acousticness: f32,
+    pub instrumentalness: f32,
+    pub loudness: f32,
+}
+
+/// `/audio-features` answers with `null` for tracks it has no features of.
+#[derive(Debug, Deserialize)]
+pub struct AudioFeaturesResponse {
+    pub audio_features: Vec<Option<AudioFeatures>>,
+}
+
+impl AudioFeatures {
+    /// The key in the notation tags use, e.g. `C#m` or `F`.
+    pub fn key_name(&self) -> Option<String> {
+        const NOTES: [&str; 12] = [
+            "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+        ];
+        let note = NOTES.get(usize::try_from(self.key).ok()?)?;
+        Some(match self.mode {
+            0 => format!("{note}m"),
+            _ => note.to_string(),
+        })
+    }
+}
+
+/// The playlist with the given base62 id.
+pub async fn playlist(session: &Session, id: &str) -> Result<PlaylistInfo> {
+    get(
+        session,
+        &format!(
+            "{}/playlists/{}?fields=name,description,owner(id,display_name),followers(total)",
+            WEB_API, id
+        ),
+    )
+    .await
+}
+
+/// Every entry of the playlist with the given base62 id, in order.
+pub async fn playlist_entries(session: &Session, id: &str) -> Result<Vec<PlaylistEntry>> {
+    let mut entries = Vec::new();
+    let mut url = Some(format!(
+        "{}/playlists/{}/tracks?limit={}&fields=items(added_at,added_by(id,display_name),track(uri)),next",
+        WEB_API, id, PLAYLIST_PAGE
+    ));
+    while let Some(page_url) = url {
+        let page: PlaylistPage = get(session, &page_url).await?;
+        entries.extend(page.items);
+        url = page.next;
+    }
+    Ok(entries)
+}
+
+/// The audio features of the tracks with the given base62 ids, leaving out
+/// those Spotify has none of.
+pub async fn audio_features(session: &Session, ids: &[String]) -> Result<Vec<AudioFeatures>> {
+    let mut features = Vec::new();
+    for batch in ids.chunks(AUDIO_FEATURES_BATCH) {
+        let response: AudioFeaturesResponse = get(
+            session,
+            &format!("{}/audio-features?ids={}", WEB_API, batch.join(",")),
+        )
+        .await?;
+        features.extend(response.audio_features.into_iter().flatten());
+    }
+    Ok(features)
+}
+
+/// Unescapes the entities the Web API puts into playlist descriptions.
+pub fn unescape_html(text: &str) -> String {
+    text.replace("&quot;", "\"")
+        .replace("&#x27;", "'")
+        .replace("&#39;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&#x2F;", "/")
+        .replace("&amp;", "&")
+}
+
+async fn get<T: DeserializeOwned>(session: &Session, url: &str) -> Result<T> {
+    let token = session
+        .token_provider()
+        .get_token("playlist-read-private")
+        .await?;
+    let request = Request::builder()
+        .method(Method::GET)
+        .uri(url)
+        .header(
+            header::AUTHORIZATION,
+            format!("Bearer {}", token.access_token),
+        )
+        .body(Bytes::new())?;
+    let body = session.http_client().request_body(request).await?;
+    Ok(serde_json::from_slice(&body)?)
+}
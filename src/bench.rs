@@ -0,0 +1,425 @@
+use anyhow::Result;
+use bytes::Bytes;
+use futures::StreamExt;
+use futures::TryStreamExt;
+use librespot::audio::{AudioDecrypt, AudioFile};
+use librespot::core::SpotifyId;
+use librespot::core::session::Session;
+use librespot::metadata::audio::{AudioFileFormat, AudioFiles, AudioItem};
+use librespot::playback::convert::Converter;
+use librespot::playback::decoder::{AudioDecoder, SymphoniaDecoder};
+use std::fs;
+use std::io::{Cursor, Read};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use symphonia::core::probe::Hint;
+
+use crate::encoder::tags::{self, Tags};
+use crate::encoder::{EncodedStream, Format, Samples, get_encoder};
+use crate::track::Track;
+
+/// Name of the directory in the destination where `bench` keeps the audio
+/// files of its sample set, so they can be replayed without fetching them.
+pub const BENCH_CACHE_DIR: &str = ".spotify-dl-bench";
+
+/// The Ogg files Spotify serves start with a custom header the decoder does
+/// not understand.
+const SPOTIFY_OGG_HEADER_END: usize = 0xa7;
+
+/// Same preference as the player at 320 kbps.
+const FILE_FORMATS: &[AudioFileFormat] = &[
+    AudioFileFormat::OGG_VORBIS_320,
+    AudioFileFormat::MP3_320,
+    AudioFileFormat::MP3_256,
+    AudioFileFormat::OGG_VORBIS_160,
+    AudioFileFormat::MP3_160,
+    AudioFileFormat::OGG_VORBIS_96,
+    AudioFileFormat::MP3_96,
+];
+
+const BYTES_PER_SECOND: usize = 40 * 1024;
+const SAMPLE_RATE: u64 = 44100;
+const CHANNELS: u64 = 2;
+
+/// The time one pipeline stage took for the whole sample set at one
+/// concurrency.
+#[derive(Debug, Clone)]
+pub struct StageResult {
+    pub stage: String,
+    pub turbo: usize,
+    pub tracks: usize,
+    pub elapsed: Duration,
+    /// Bytes the stage read or produced.
+    pub bytes: u64,
+    /// Length of the audio the stage processed, when it is known.
+    pub audio: Option<Duration>,
+}
+
+impl StageResult {
+    pub fn megabytes_per_second(&self) -> f64 {
+        self.bytes as f64 / 1_000_000.0 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+
+    /// Seconds of audio processed per second.
+    pub fn realtime_factor(&self) -> Option<f64> {
+        self.audio
+            .map(|audio| audio.as_secs_f64() / self.elapsed.as_secs_f64().max(f64::EPSILON))
+    }
+}
+
+/// An audio file of the sample set as Spotify serves it, decrypted.
+struct SampleFile {
+    name: String,
+    extension: &'static str,
+    data: Vec<u8>,
+}
+
+struct DecodedTrack {
+    name: String,
+    samples: Vec<i32>,
+}
+
+struct EncodedTrack {
+    name: String,
+    stream: EncodedStream,
+}
+
+/// Measures the stages of the download pipeline separately: fetching the
+/// audio files, decoding them, encoding them to each format and tagging the
+/// results, once for every concurrency in `turbo`.
+pub struct Bench {
+    pub cache_dir: PathBuf,
+    pub turbo: Vec<usize>,
+    pub formats: Vec<Format>,
+}
+
+impl Bench {
+    /// Fetches the sample set for every concurrency and caches the files for
+    /// [`Bench::replay`].
+    pub async fn run(&self, session: &Session, tracks: &[Track]) -> Result<Vec<StageResult>> {
+        let mut results = Vec::new();
+        let mut files = Vec::new();
+        for &turbo in &self.turbo {
+            let (result, fetched) = fetch_files(session, tracks, turbo).await?;
+            results.push(result);
+            if files.is_empty() {
+                store_cache(&self.cache_dir, &fetched)?;
+                files = fetched;
+            }
+            results.extend(self.run_local_stages(&files, turbo).await?);
+        }
+        Ok(results)
+    }
+
+    /// Runs the stages after fetching on the files cached by the last
+    /// [`Bench::run`].
+    pub async fn replay(&self) -> Result<Vec<StageResult>> {
+        let files = load_cache(&self.cache_dir)?;
+        if files.is_empty() {
+            return Err(anyhow::anyhow!(
+                "No cached audio in {}, run bench without --replay first",
+                self.cache_dir.display()
+            ));
+        }
+        let mut results = Vec::new();
+        for &turbo in &self.turbo {
+            results.extend(self.run_local_stages(&files, turbo).await?);
+        }
+        Ok(results)
+    }
+
+    async fn run_local_stages(
+        &self,
+        files: &[SampleFile],
+        turbo: usize,
+    ) -> Result<Vec<StageResult>> {
+        let scratch = self.cache_dir.join("scratch");
+        let mut results = Vec::new();
+        let (result, decoded) = decode_files(files, turbo).await?;
+        results.push(result);
+        for &format in &self.formats {
+            let (result, encoded) = encode_tracks(&decoded, format, turbo).await?;
+            results.push(result);
+            results.push(tag_files(&encoded, format, turbo, &scratch).await?);
+        }
+        if scratch.exists() {
+            fs::remove_dir_all(&scratch)?;
+        }
+        Ok(results)
+    }
+}
+
+async fn fetch_files(
+    session: &Session,
+    tracks: &[Track],
+    turbo: usize,
+) -> Result<(StageResult, Vec<SampleFile>)> {
+    let started = Instant::now();
+    let files: Vec<SampleFile> = futures::stream::iter(tracks)
+        .map(|track| fetch_file(session, track))
+        .buffered(turbo.max(1))
+        .filter_map(|file| async move {
+            file.inspect_err(|err| {
+                tracing::warn!(error = %err, "Leaving a track out of the sample set");
+                println!("Leaving a track out of the sample set: {}", err);
+            })
+            .ok()
+        })
+        .collect()
+        .await;
+    let result = StageResult {
+        stage: "fetch".to_string(),
+        turbo,
+        tracks: files.len(),
+        elapsed: started.elapsed(),
+        bytes: files.iter().map(|file| file.data.len() as u64).sum(),
+        audio: None,
+    };
+    if files.is_empty() {
+        return Err(anyhow::anyhow!(
+            "None of the sample tracks could be fetched"
+        ));
+    }
+    Ok((result, files))
+}
+
+async fn fetch_file(session: &Session, track: &Track) -> Result<SampleFile> {
+    let item = AudioItem::get_file(session, track.id.clone()).await?;
+    let (format, file_id) = FILE_FORMATS
+        .iter()
+        .find_map(|format| item.files.get(format).map(|file_id| (*format, *file_id)))
+        .ok_or(anyhow::anyhow!(
+            "{} is not available in a supported format",
+            track.id
+        ))?;
+
+    let file = AudioFile::open(session, file_id, BYTES_PER_SECOND).await?;
+    // Not every file is encrypted, like in the player.
+    let key = session
+        .audio_key()
+        .request(SpotifyId::try_from(&track.id)?, file_id)
+        .await
+        .ok();
+    let data = tokio::task::spawn_blocking(move || -> Result<Vec<u8>> {
+        let mut data = Vec::new();
+        AudioDecrypt::new(key, file).read_to_end(&mut data)?;
+        Ok(data)
+    })
+    .await??;
+
+    let (extension, data) = if AudioFiles::is_ogg_vorbis(format) {
+        let data = data.get(SPOTIFY_OGG_HEADER_END..).unwrap_or_default();
+        ("ogg", data.to_vec())
+    } else {
+        ("mp3", data)
+    };
+    Ok(SampleFile {
+        name: track.id.to_id()?,
+        extension,
+        data,
+    })
+}
+
+async fn decode_files(
+    files: &[SampleFile],
+    turbo: usize,
+) -> Result<(StageResult, Vec<DecodedTrack>)> {
+    let started = Instant::now();
+    let decoded: Vec<DecodedTrack> = futures::stream::iter(files)
+        .map(|file| {
+            let name = file.name.clone();
+            let extension = file.extension;
+            let data = file.data.clone();
+            async move {
+                let samples =
+                    tokio::task::spawn_blocking(move || decode(data, extension)).await??;
+                Ok::<_, anyhow::Error>(DecodedTrack { name, samples })
+            }
+        })
+        .buffered(turbo.max(1))
+        .try_collect()
+        .await?;
+    let result = StageResult {
+        stage: "decode".to_string(),
+        turbo,
+        tracks: decoded.len(),
+        elapsed: started.elapsed(),
+        bytes: files.iter().map(|file| file.data.len() as u64).sum(),
+        audio: Some(audio_length(&decoded)),
+    };
+    Ok((result, decoded))
+}
+
+/// Decodes like the player and converts like the sink the downloader reads
+/// from, so the samples are the ones a download would encode.
+fn decode(data: Vec<u8>, extension: &str) -> Result<Vec<i32>> {
+    let mut hint = Hint::new();
+    hint.with_extension(extension);
+    let mut decoder = SymphoniaDecoder::new(Cursor::new(data), hint)
+        .map_err(|e| anyhow::anyhow!("Failed to open decoder: {}", e))?;
+    let mut converter = Converter::new(None);
+    let mut samples = Vec::new();
+    while let Some((_, packet)) = decoder
+        .next_packet()
+        .map_err(|e| anyhow::anyhow!("Failed to decode: {}", e))?
+    {
+        let packet = packet
+            .samples()
+            .map_err(|e| anyhow::anyhow!("Failed to decode: {}", e))?;
+        samples.extend(converter.f64_to_s32(packet));
+    }
+    Ok(samples)
+}
+
+async fn encode_tracks(
+    decoded: &[DecodedTrack],
+    format: Format,
+    turbo: usize,
+) -> Result<(StageResult, Vec<EncodedTrack>)> {
+    // Copied up front, the encoders take ownership of the samples.
+    let inputs: Vec<(String, Samples)> = decoded
+        .iter()
+        .map(|track| {
+            let samples = Samples {
+                samples: track.samples.clone(),
+                ..Default::default()
+            };
+            (track.name.clone(), samples)
+        })
+        .collect();
+
+    let started = Instant::now();
+    let encoded: Vec<EncodedTrack> = futures::stream::iter(inputs)
+        .map(|(name, samples)| async move {
+            let stream = get_encoder(format).encode(samples).await?;
+            Ok::<_, anyhow::Error>(EncodedTrack { name, stream })
+        })
+        .buffered(turbo.max(1))
+        .try_collect()
+        .await?;
+    let result = StageResult {
+        stage: format!("encode {}", format.extension()),
+        turbo,
+        tracks: encoded.len(),
+        elapsed: started.elapsed(),
+        bytes: encoded
+            .iter()
+            .map(|track| track.stream.stream.len() as u64)
+            .sum(),
+        audio: Some(audio_length(decoded)),
+    };
+    Ok((result, encoded))
+}
+
+async fn tag_files(
+    encoded: &[EncodedTrack],
+    format: Format,
+    turbo: usize,
+    scratch: &Path,
+) -> Result<StageResult> {
+    let cover = sample_cover()?;
+    let mut paths = Vec::new();
+    for track in encoded {
+        let path = scratch.join(format!("{}.{}", track.name, format.extension()));
+        if path.exists() {
+            fs::remove_file(&path)?;
+        }
+        track.stream.write_to_file(&path).await?;
+        paths.push((track.name.clone(), path));
+    }
+
+    let started = Instant::now();
+    futures::stream::iter(paths)
+        .map(|(name, path)| {
+            let cover = cover.clone();
+            // Tagging blocks on file I/O, so each file gets a task of its own.
+            tokio::spawn(async move {
+                let tags = Tags {
+                    title: name,
+                    artists: vec!["spotify-dl bench".to_string()],
+                    album_title: "spotify-dl bench".to_string(),
+                    album_cover: Some(cover),
+                    spotify_uri: None,
+                    isrc: None,
+                    track_number: Some(1),
+                    disc_number: Some(1),
+                    disc_count: Some(1),
+                    chapters: Vec::new(),
+                    genre: None,
+                };
+                tags::store_tags(&path, &tags, format).await
+            })
+        })
+        .buffered(turbo.max(1))
+        .map(|tagged| tagged?)
+        .try_collect::<Vec<_>>()
+        .await?;
+    Ok(StageResult {
+        stage: format!("tag {}", format.extension()),
+        turbo,
+        tracks: encoded.len(),
+        elapsed: started.elapsed(),
+        bytes: encoded
+            .iter()
+            .map(|track| track.stream.stream.len() as u64)
+            .sum(),
+        audio: None,
+    })
+}
+
+/// A JPEG of the size Spotify serves album covers in.
+fn sample_cover() -> Result<Bytes> {
+    let image = image::RgbImage::from_fn(640, 640, |x, y| {
+        image::Rgb([(x % 256) as u8, (y % 256) as u8, ((x + y) % 256) as u8])
+    });
+    let mut jpeg = Vec::new();
+    image::codecs::jpeg::JpegEncoder::new_with_quality(&mut jpeg, 90).encode_image(&image)?;
+    Ok(Bytes::from(jpeg))
+}
+
+fn audio_length(tracks: &[DecodedTrack]) -> Duration {
+    let samples: u64 = tracks.iter().map(|track| track.samples.len() as u64).sum();
+    Duration::from_secs_f64(samples as f64 / (SAMPLE_RATE * CHANNELS) as f64)
+}
+
+fn store_cache(dir: &Path, files: &[SampleFile]) -> Result<()> {
+    if dir.exists() {
+        fs::remove_dir_all(dir)?;
+    }
+    fs::create_dir_all(dir)?;
+    for file in files {
+        fs::write(
+            dir.join(format!("{}.{}", file.name, file.extension)),
+            &file.data,
+        )?;
+    }
+    Ok(())
+}
+
+fn load_cache(dir: &Path) -> Result<Vec<SampleFile>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let mut files = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        let extension = match path.extension().and_then(|extension| extension.to_str()) {
+            Some("ogg") => "ogg",
+            Some("mp3") => "mp3",
+            _ => continue,
+        };
+        let name = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default();
+        files.push(SampleFile {
+            name,
+            extension,
+            data: fs::read(&path)?,
+        });
+    }
+    files.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(files)
+}
@@ -0,0 +1,39 @@
+use std::time::Duration;
+
+use anyhow::Result;
+
+use crate::stream::REQUESTED_BITRATE;
+
+/// A size as bytes, or a number followed by `K`, `M`, `G` or `T` for
+/// thousands, millions, billions or trillions of bytes, optionally with a
+/// trailing `B`, e.g. `2G` or `500MB`.
+pub fn parse_size(s: &str) -> Result<u64> {
+    let s = s.trim();
+    let s = s.strip_suffix(['B', 'b']).unwrap_or(s);
+    let (number, unit) = match s.find(|c: char| !c.is_ascii_digit() && c != '.') {
+        Some(index) => s.split_at(index),
+        None => (s, ""),
+    };
+    let multiplier: f64 = match unit.to_ascii_uppercase().as_str() {
+        "" => 1.0,
+        "K" => 1e3,
+        "M" => 1e6,
+        "G" => 1e9,
+        "T" => 1e12,
+        _ => 0.0,
+    };
+    number
+        .parse::<f64>()
+        .ok()
+        .map(|number| (number * multiplier) as u64)
+        .filter(|&size| size > 0)
+        .ok_or(anyhow::anyhow!(
+            "Unsupported size, expected e.g. 500M or 2G"
+        ))
+}
+
+/// About what streaming `duration` of audio transfers: the Ogg Vorbis
+/// stream at the requested bitrate.
+pub fn streamed_bytes(duration: Duration) -> u64 {
+    (duration.as_secs_f64() * REQUESTED_BITRATE as f64 * 1000.0 / 8.0) as u64
+}
@@ -0,0 +1,88 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::Result;
+use lazy_static::lazy_static;
+use regex::Regex;
+
+/// Extension of the chapter list written next to MP3 files.
+pub const SIDECAR_EXTENSION: &str = "chapters.txt";
+
+lazy_static! {
+    /// A line starting with a timestamp like `00:00`, `1:02:03` or `[12:34]`,
+    /// possibly after a bullet and followed by a separator, then the chapter
+    /// title.
+    static ref CHAPTER_LINE_REGEX: Regex = Regex::new(
+        r"^\s*(?:[-•*]\s*)?[\[(]?(?:(\d{1,2}):)?(\d{1,2}):(\d{2})[\])]?\s*(?:[-–—|:.]\s*)?(\S.*?)\s*$"
+    )
+    .unwrap();
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Chapter {
+    pub start: Duration,
+    pub title: String,
+}
+
+/// Reads the chapters of a podcast episode from its description. Only lists
+/// of at least two timestamped lines in increasing order count, so a stray
+/// "see you at 9:30" doesn't turn into a chapter.
+pub fn parse_chapters(description: &str) -> Vec<Chapter> {
+    let chapters: Vec<Chapter> = description
+        .lines()
+        .filter_map(|line| {
+            let captures = CHAPTER_LINE_REGEX.captures(line)?;
+            let number = |index| {
+                captures
+                    .get(index)
+                    .map_or(Some(0), |part| part.as_str().parse::<u64>().ok())
+            };
+            let (hours, minutes, seconds) = (number(1)?, number(2)?, number(3)?);
+            if seconds >= 60 || (captures.get(1).is_some() && minutes >= 60) {
+                return None;
+            }
+            Some(Chapter {
+                start: Duration::from_secs(hours * 3600 + minutes * 60 + seconds),
+                title: captures[4].to_string(),
+            })
+        })
+        .collect();
+
+    let increasing = chapters
+        .windows(2)
+        .all(|pair| pair[0].start < pair[1].start);
+    if chapters.len() < 2 || !increasing {
+        return Vec::new();
+    }
+    chapters
+}
+
+/// The start of a chapter as `HH:MM:SS.mmm`, the format of Vorbis comment
+/// chapters and of the sidecar files.
+pub fn format_timestamp(start: Duration) -> String {
+    let seconds = start.as_secs();
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60,
+        start.subsec_millis()
+    )
+}
+
+/// Where the chapter list of an audio file goes, e.g. `Episode.chapters.txt`
+/// for `Episode.mp3`.
+pub fn sidecar_path(audio: &Path) -> PathBuf {
+    audio.with_extension(SIDECAR_EXTENSION)
+}
+
+/// Writes the chapters next to an audio file, one `HH:MM:SS.mmm Title` line
+/// each.
+pub async fn write_sidecar(audio: &Path, chapters: &[Chapter]) -> Result<()> {
+    let contents: String = chapters
+        .iter()
+        .map(|chapter| format!("{} {}\n", format_timestamp(chapter.start), chapter.title))
+        .collect();
+    tokio::fs::write(sidecar_path(audio), contents).await?;
+    Ok(())
+}
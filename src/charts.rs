@@ -0,0 +1,164 @@
+use anyhow::Result;
+use librespot::core::SpotifyUri;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::library::LibraryIndex;
+
+/// Name of the file in each market folder that remembers when its tracks
+/// were last seen on the chart.
+pub const CHART_STATE_FILE: &str = ".spotify-dl-charts.json";
+
+const WEEK_SECS: u64 = 7 * 24 * 60 * 60;
+
+/// Spotify's "Top 50" playlist of each market. Other markets can be given
+/// as `CODE=<playlist uri or url>`.
+const TOP_50: &[(&str, &str)] = &[
+    ("GLOBAL", "37i9dQZEVXbMDoHDwVN2tF"),
+    ("AU", "37i9dQZEVXbJPcfkRz0wJ0"),
+    ("BR", "37i9dQZEVXbMXbN3EUUhlg"),
+    ("CA", "37i9dQZEVXbKj23U1GF4IR"),
+    ("DE", "37i9dQZEVXbJiZcmkrIHGU"),
+    ("ES", "37i9dQZEVXbNFJfN1Vw8d9"),
+    ("FR", "37i9dQZEVXbIPWwFssbupI"),
+    ("GB", "37i9dQZEVXbLnolsZ8PSNw"),
+    ("IT", "37i9dQZEVXbIQnj7RRhdSX"),
+    ("JP", "37i9dQZEVXbKXQ4mDTEBXq"),
+    ("MX", "37i9dQZEVXbO3qyFxbkOE1"),
+    ("US", "37i9dQZEVXbLRQDuF5jeBp"),
+];
+
+/// A market to follow: its code, which names its folder, and its chart
+/// playlist.
+#[derive(Debug, Clone)]
+pub struct Market {
+    pub code: String,
+    pub playlist: String,
+}
+
+impl Market {
+    /// Parses `US` or `US=spotify:playlist:...`.
+    pub fn parse(spec: &str) -> Result<Self> {
+        let (code, playlist) = match spec.split_once('=') {
+            Some((code, playlist)) => (code.trim().to_ascii_uppercase(), playlist.to_string()),
+            None => {
+                let code = spec.trim().to_ascii_uppercase();
+                let id = TOP_50
+                    .iter()
+                    .find(|(market, _)| *market == code)
+                    .map(|(_, id)| *id)
+                    .ok_or(anyhow::anyhow!(
+                        "No chart playlist is known for market {}, pass it as {}=<playlist>",
+                        code,
+                        code
+                    ))?;
+                (code, format!("spotify:playlist:{}", id))
+            }
+        };
+        if code.is_empty() || !code.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(anyhow::anyhow!("Invalid market code: {}", spec));
+        }
+        Ok(Market { code, playlist })
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ChartEntry {
+    first_seen: u64,
+    last_seen: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+struct StoredChart {
+    tracks: HashMap<String, ChartEntry>,
+}
+
+/// When each track of a market folder was first and last seen on its chart.
+pub struct ChartState {
+    path: PathBuf,
+    data: StoredChart,
+}
+
+impl ChartState {
+    pub fn load(path: PathBuf) -> Self {
+        let data = fs::read_to_string(&path)
+            .ok()
+            .and_then(|contents| serde_json::from_str(&contents).ok())
+            .unwrap_or_default();
+
+        ChartState { path, data }
+    }
+
+    /// Marks the tracks currently on the chart as seen now. Returns how many
+    /// of them entered the chart since the last refresh.
+    pub fn record_refresh(&mut self, tracks: &[SpotifyUri]) -> Result<usize> {
+        let now = now();
+        let mut entered = 0;
+        for uri in tracks.iter().filter_map(|track| track.to_uri().ok()) {
+            let entry = self.data.tracks.entry(uri).or_insert_with(|| {
+                entered += 1;
+                ChartEntry {
+                    first_seen: now,
+                    last_seen: now,
+                }
+            });
+            entry.last_seen = now;
+        }
+        self.persist()?;
+        Ok(entered)
+    }
+
+    /// Deletes the files of tracks that have not been on the chart for more
+    /// than `retention_weeks` and forgets them. Returns the deleted files.
+    pub fn prune(
+        &mut self,
+        library: &mut LibraryIndex,
+        retention_weeks: u64,
+    ) -> Result<Vec<PathBuf>> {
+        let cutoff = now().saturating_sub(retention_weeks * WEEK_SECS);
+        let expired: Vec<String> = self
+            .data
+            .tracks
+            .iter()
+            .filter(|(_, entry)| entry.last_seen < cutoff)
+            .map(|(uri, _)| uri.clone())
+            .collect();
+
+        let mut deleted = Vec::new();
+        for uri in &expired {
+            if let Ok(track) = SpotifyUri::from_uri(uri) {
+                for path in library.remove(&track)? {
+                    match fs::remove_file(&path) {
+                        Ok(()) => deleted.push(path),
+                        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                        Err(e) => return Err(e.into()),
+                    }
+                }
+            }
+            self.data.tracks.remove(uri);
+        }
+        if !expired.is_empty() {
+            self.persist()?;
+        }
+        Ok(deleted)
+    }
+
+    fn persist(&self) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let serialized = serde_json::to_string_pretty(&self.data)?;
+        fs::write(&self.path, serialized)?;
+        Ok(())
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default()
+}
@@ -0,0 +1,121 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Mutex;
+
+use anyhow::Result;
+
+use crate::utils::hash_file;
+
+const SUMS_FILE: &str = "SHA256SUMS";
+const SIDECAR_EXTENSION: &str = "sha256";
+
+// Serializes updates of the per folder SHA256SUMS files between tasks.
+static SUMS_LOCK: Mutex<()> = Mutex::new(());
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ChecksumStyle {
+    /// A `<file>.sha256` next to every file.
+    Sidecar,
+    /// A single `SHA256SUMS` per folder.
+    Sums,
+}
+
+impl FromStr for ChecksumStyle {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "sidecar" => Ok(ChecksumStyle::Sidecar),
+            "sums" => Ok(ChecksumStyle::Sums),
+            _ => Err(anyhow::anyhow!("Unsupported checksum style")),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct VerifySummary {
+    pub ok: usize,
+    pub failed: Vec<PathBuf>,
+    pub missing: Vec<PathBuf>,
+}
+
+/// Writes the checksum of `path` in the `sha256sum` format.
+pub fn write_checksum(path: &Path, style: ChecksumStyle) -> Result<()> {
+    let hash = hash_file(path)?;
+    let name = path
+        .file_name()
+        .ok_or(anyhow::anyhow!("Invalid file name"))?
+        .to_string_lossy()
+        .to_string();
+
+    match style {
+        ChecksumStyle::Sidecar => {
+            let mut sidecar = path.as_os_str().to_owned();
+            sidecar.push(".");
+            sidecar.push(SIDECAR_EXTENSION);
+            fs::write(sidecar, format!("{hash}  {name}\n"))?;
+        }
+        ChecksumStyle::Sums => {
+            let _guard = SUMS_LOCK.lock().unwrap();
+            let sums = path.with_file_name(SUMS_FILE);
+            let mut entries: Vec<(String, String)> = fs::read_to_string(&sums)
+                .map(|contents| parse_sums(&contents))
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|(_, file)| *file != name)
+                .collect();
+            entries.push((hash, name));
+            entries.sort_by(|(_, a), (_, b)| a.cmp(b));
+            let contents: String = entries
+                .iter()
+                .map(|(hash, file)| format!("{hash}  {file}\n"))
+                .collect();
+            fs::write(sums, contents)?;
+        }
+    }
+    Ok(())
+}
+
+/// Checks every file listed in the sidecars and `SHA256SUMS` files below `root`.
+pub fn verify_checksums(root: &Path) -> Result<VerifySummary> {
+    let mut summary = VerifySummary::default();
+    let mut pending = vec![root.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        for entry in fs::read_dir(&dir)?.flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                pending.push(path);
+                continue;
+            }
+            let is_checksum_file = path.file_name().is_some_and(|name| name == SUMS_FILE)
+                || path
+                    .extension()
+                    .is_some_and(|extension| extension == SIDECAR_EXTENSION);
+            if !is_checksum_file {
+                continue;
+            }
+            for (hash, file) in parse_sums(&fs::read_to_string(&path)?) {
+                let file = dir.join(file);
+                match hash_file(&file) {
+                    Ok(actual) if actual == hash => summary.ok += 1,
+                    Ok(_) => summary.failed.push(file),
+                    Err(_) => summary.missing.push(file),
+                }
+            }
+        }
+    }
+    Ok(summary)
+}
+
+fn parse_sums(contents: &str) -> Vec<(String, String)> {
+    contents
+        .lines()
+        .filter_map(|line| {
+            let (hash, file) = line.split_once(' ')?;
+            // Binary mode entries are marked with a leading '*'.
+            let file = file.trim_start_matches([' ', '*']);
+            Some((hash.to_lowercase(), file.to_string()))
+        })
+        .collect()
+}
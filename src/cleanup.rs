@@ -0,0 +1,60 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use anyhow::Result;
+
+/// `spotify-dl clean` leaves `.part` files younger than this by default, as
+/// a run going on at the same time may still be writing or staging them.
+pub const DEFAULT_STALE_AFTER: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[derive(Debug, Default)]
+pub struct CleanSummary {
+    pub removed: Vec<PathBuf>,
+    pub bytes: u64,
+    /// Files that are stale but could not be removed, with the error.
+    pub failed: Vec<(PathBuf, String)>,
+}
+
+/// Whether `path` is a file spotify-dl writes before moving it into place.
+pub fn is_part_file(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension == "part")
+}
+
+/// Removes the `.part` files below the `roots` that were last written more
+/// than `older_than` ago, left behind by runs that crashed or were killed.
+/// Roots that don't exist are skipped.
+pub fn remove_stale_parts(roots: &[PathBuf], older_than: Duration) -> Result<CleanSummary> {
+    let mut summary = CleanSummary::default();
+    let now = SystemTime::now();
+    let mut pending: Vec<PathBuf> = roots.iter().filter(|root| root.is_dir()).cloned().collect();
+    while let Some(dir) = pending.pop() {
+        for entry in fs::read_dir(&dir)?.flatten() {
+            let path = entry.path();
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+            if metadata.is_dir() {
+                pending.push(path);
+                continue;
+            }
+            let stale = metadata
+                .modified()
+                .ok()
+                .and_then(|modified| now.duration_since(modified).ok())
+                .is_some_and(|age| age >= older_than);
+            if !is_part_file(&path) || !stale {
+                continue;
+            }
+            match fs::remove_file(&path) {
+                Ok(()) => {
+                    summary.bytes += metadata.len();
+                    summary.removed.push(path);
+                }
+                Err(err) => summary.failed.push((path, err.to_string())),
+            }
+        }
+    }
+    Ok(summary)
+}
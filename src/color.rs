@@ -0,0 +1,50 @@
+use anyhow::Result;
+use std::io::{self, IsTerminal};
+use std::str::FromStr;
+
+/// Whether output is styled with colors: `auto` colors terminals unless
+/// `NO_COLOR` is set, `always` and `never` override that.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColorChoice {
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl FromStr for ColorChoice {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "auto" => Ok(ColorChoice::Auto),
+            "always" => Ok(ColorChoice::Always),
+            "never" => Ok(ColorChoice::Never),
+            _ => Err(anyhow::anyhow!("Unsupported color choice")),
+        }
+    }
+}
+
+impl ColorChoice {
+    /// Sets the policy for everything styled through `console`, which
+    /// includes the progress bars. Returns whether stderr is colored, for the
+    /// console log.
+    pub fn apply(self) -> bool {
+        let stdout = self.enabled(io::stdout().is_terminal());
+        let stderr = self.enabled(io::stderr().is_terminal());
+        console::set_colors_enabled(stdout);
+        console::set_colors_enabled_stderr(stderr);
+        stderr
+    }
+
+    fn enabled(self, terminal: bool) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            // https://no-color.org: set and not empty disables colors.
+            ColorChoice::Auto => {
+                terminal && std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+            }
+        }
+    }
+}
@@ -0,0 +1,88 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::encoder::Format;
+use crate::utils::get_dot_path;
+
+/// Defaults for the command line options, written by `spotify-dl init`.
+/// Options given on the command line always win.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Config {
+    #[serde(default)]
+    pub destination: Option<PathBuf>,
+    #[serde(default)]
+    pub format: Option<String>,
+    #[serde(default)]
+    pub template: Option<String>,
+    #[serde(default)]
+    pub parallel: Option<usize>,
+    #[serde(default)]
+    pub match_by_tag: bool,
+    #[serde(default)]
+    pub verify: bool,
+    /// Tracks get their duration times this to finish streaming.
+    #[serde(default)]
+    pub timeout_factor: Option<f64>,
+    /// Destinations by format name, used instead of `destination` for
+    /// downloads in that format.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub format_destinations: HashMap<String, PathBuf>,
+}
+
+impl Config {
+    pub fn path() -> Result<PathBuf> {
+        Ok(get_dot_path()?.join("config.json"))
+    }
+
+    /// Returns `None` when no config file was written yet.
+    pub fn load() -> Result<Option<Self>> {
+        match fs::read_to_string(Self::path()?) {
+            Ok(contents) => Ok(Some(serde_json::from_str(&contents)?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Where downloads in `format` go: its own destination if one is set,
+    /// the general one otherwise.
+    pub fn destination_for(&self, format: Format) -> Option<&PathBuf> {
+        self.format_destinations
+            .get(format.name())
+            .or(self.destination.as_ref())
+    }
+
+    pub fn save(&self) -> Result<PathBuf> {
+        let path = Self::path()?;
+        fs::write(&path, serde_json::to_string_pretty(self)?)?;
+        Ok(path)
+    }
+}
+
+/// A destination for downloads in one format, given as `flac=/archive`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FormatDestination {
+    pub format: Format,
+    pub destination: PathBuf,
+}
+
+impl FromStr for FormatDestination {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (format, destination) = s
+            .split_once('=')
+            .filter(|(_, destination)| !destination.is_empty())
+            .ok_or(anyhow::anyhow!(
+                "Unsupported format destination, expected format=directory"
+            ))?;
+        Ok(FormatDestination {
+            format: format.parse()?,
+            destination: PathBuf::from(destination),
+        })
+    }
+}
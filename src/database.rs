@@ -0,0 +1,130 @@
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::Result;
+use rusqlite::{Connection, params};
+
+/// A single downloaded file as stored in the `downloads` table.
+#[derive(Debug, Clone)]
+pub struct DownloadRecord {
+    pub uri: String,
+    pub path: String,
+    pub format: String,
+    pub bitrate: Option<u32>,
+    pub playlist: Option<String>,
+    pub first_downloaded_at: u64,
+    pub downloaded_at: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct RecordFilter {
+    pub uri: Option<String>,
+    pub playlist: Option<String>,
+    pub format: Option<String>,
+    pub path: Option<String>,
+    pub limit: Option<usize>,
+}
+
+pub struct Database {
+    connection: Connection,
+}
+
+impl Database {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        if let Some(parent) = path.as_ref().parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let connection = Connection::open(path)?;
+        connection.execute_batch(
+            "CREATE TABLE IF NOT EXISTS downloads (
+                uri TEXT NOT NULL,
+                path TEXT NOT NULL,
+                format TEXT NOT NULL,
+                bitrate INTEGER,
+                playlist TEXT,
+                first_downloaded_at INTEGER NOT NULL,
+                downloaded_at INTEGER NOT NULL,
+                PRIMARY KEY (uri, path)
+            );
+            CREATE INDEX IF NOT EXISTS downloads_playlist ON downloads (playlist);",
+        )?;
+        Ok(Database { connection })
+    }
+
+    pub fn record(
+        &self,
+        uri: &str,
+        path: &str,
+        format: &str,
+        bitrate: Option<u32>,
+        playlist: Option<&str>,
+    ) -> Result<()> {
+        self.connection.execute(
+            "INSERT INTO downloads
+                (uri, path, format, bitrate, playlist, first_downloaded_at, downloaded_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?6)
+             ON CONFLICT (uri, path) DO UPDATE SET
+                format = excluded.format,
+                bitrate = excluded.bitrate,
+                playlist = COALESCE(excluded.playlist, downloads.playlist),
+                downloaded_at = excluded.downloaded_at",
+            params![uri, path, format, bitrate, playlist, now()],
+        )?;
+        Ok(())
+    }
+
+    pub fn query(&self, filter: &RecordFilter) -> Result<Vec<DownloadRecord>> {
+        let mut statement = self.connection.prepare(
+            "SELECT uri, path, format, bitrate, playlist, first_downloaded_at, downloaded_at
+             FROM downloads
+             WHERE (?1 IS NULL OR uri = ?1)
+               AND (?2 IS NULL OR playlist = ?2)
+               AND (?3 IS NULL OR format = ?3)
+               AND (?4 IS NULL OR path LIKE '%' || ?4 || '%')
+             ORDER BY downloaded_at DESC
+             LIMIT ?5",
+        )?;
+        let limit = filter.limit.map_or(-1, |limit| limit as i64);
+        let records = statement
+            .query_map(
+                params![
+                    filter.uri,
+                    filter.playlist,
+                    filter.format,
+                    filter.path,
+                    limit
+                ],
+                |row| {
+                    Ok(DownloadRecord {
+                        uri: row.get(0)?,
+                        path: row.get(1)?,
+                        format: row.get(2)?,
+                        bitrate: row.get(3)?,
+                        playlist: row.get(4)?,
+                        first_downloaded_at: row.get(5)?,
+                        downloaded_at: row.get(6)?,
+                    })
+                },
+            )?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(records)
+    }
+
+    /// Number of recorded files per format.
+    pub fn stats(&self) -> Result<Vec<(String, u64)>> {
+        let mut statement = self.connection.prepare(
+            "SELECT format, COUNT(*) FROM downloads GROUP BY format ORDER BY format",
+        )?;
+        let stats = statement
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(stats)
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
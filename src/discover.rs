@@ -0,0 +1,96 @@
+use anyhow::Result;
+use bytes::Bytes;
+use http::{Method, Request, header};
+use librespot::core::session::Session;
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+
+const WEB_API: &str = "https://api.spotify.com/v1";
+
+/// A playlist listed in one of Spotify's browse categories.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CategoryPlaylist {
+    pub uri: String,
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub tracks: Option<TrackCount>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TrackCount {
+    pub total: u64,
+}
+
+#[derive(Deserialize)]
+struct Category {
+    id: String,
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct Page<T> {
+    items: Vec<Option<T>>,
+}
+
+#[derive(Deserialize)]
+struct CategoriesResponse {
+    categories: Page<Category>,
+}
+
+#[derive(Deserialize)]
+struct PlaylistsResponse {
+    playlists: Page<CategoryPlaylist>,
+}
+
+/// Returns up to `limit` playlists of the browse category whose id or name
+/// is `category`, e.g. `jazz`.
+pub async fn category_playlists(
+    session: &Session,
+    category: &str,
+    limit: usize,
+) -> Result<Vec<CategoryPlaylist>> {
+    let categories: CategoriesResponse = get(session, "/browse/categories?limit=50").await?;
+    let categories: Vec<Category> = categories.categories.items.into_iter().flatten().collect();
+    let id = categories
+        .iter()
+        .find(|known| known.id == category || known.name.eq_ignore_ascii_case(category))
+        .map(|known| known.id.clone())
+        .ok_or_else(|| {
+            let names: Vec<&str> = categories.iter().map(|known| known.name.as_str()).collect();
+            anyhow::anyhow!(
+                "Unknown category {}, available categories: {}",
+                category,
+                names.join(", ")
+            )
+        })?;
+
+    let playlists: PlaylistsResponse = get(
+        session,
+        &format!(
+            "/browse/categories/{}/playlists?limit={}",
+            id,
+            limit.clamp(1, 50)
+        ),
+    )
+    .await?;
+    Ok(playlists.playlists.items.into_iter().flatten().collect())
+}
+
+async fn get<T: DeserializeOwned>(session: &Session, path: &str) -> Result<T> {
+    let token = session
+        .token_provider()
+        .get_token("playlist-read-private")
+        .await?;
+    let request = Request::builder()
+        .method(Method::GET)
+        .uri(format!("{}{}", WEB_API, path))
+        .header(
+            header::AUTHORIZATION,
+            format!("Bearer {}", token.access_token),
+        )
+        .body(Bytes::new())?;
+    let body = session.http_client().request_body(request).await?;
+    Ok(serde_json::from_slice(&body)?)
+}
@@ -1,32 +1,284 @@
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fmt;
 use std::fmt::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use tokio::time::{timeout, Duration};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::SystemTime;
+use tokio::time::{timeout_at, Duration, Instant};
 
 use anyhow::Result;
+use bytes::Bytes;
 use futures::StreamExt;
 use futures::TryStreamExt;
+use http::{Method, Request};
 use indicatif::MultiProgress;
 use indicatif::ProgressBar;
 use indicatif::ProgressState;
 use indicatif::ProgressStyle;
 use librespot::core::session::Session;
+use librespot::core::SpotifyUri;
+use serde::{Deserialize, Serialize};
 use tokio::sync::Mutex;
 
-use crate::encoder;
+use crate::budget::streamed_bytes;
+use crate::chapters::{self, Chapter};
+use crate::checksums::{self, ChecksumStyle};
+#[cfg(feature = "sqlite")]
+use crate::database::Database;
+use crate::encoder::EncodedStream;
 use crate::encoder::Format;
 use crate::encoder::Samples;
+use crate::encoder::tags::{CoverSize, DEFAULT_COVER_QUALITY};
+use crate::encoder::verify;
+use crate::error_log::{ErrorLog, Level, Phase};
 use crate::history::PlaylistHistory;
+use crate::library::{LibraryIndex, find_loosely_named};
+use crate::mtime::{self, FileMtime};
+use crate::overrides::Overrides;
+use crate::profile;
+use crate::progress::{DEFAULT_LABEL_WIDTH, fit_label};
+use crate::schedule::ScheduleWindow;
+use crate::split;
+use crate::stream::REQUESTED_BITRATE;
 use crate::stream::Stream;
 use crate::stream::StreamEvent;
 use crate::stream::StreamEventChannel;
+#[cfg(feature = "simulate")]
+use crate::stream::simulate::Simulation;
+use crate::tagging::{CoverOptions, TagJob, TagPool};
+use crate::track::Playlist;
 use crate::track::Track;
 use crate::track::TrackMetadata;
+use crate::utils::{copy_file, move_file};
+use crate::xattrs;
 
 pub struct Downloader {
     session: Session,
     progress_bar: MultiProgress,
     history: Option<Arc<Mutex<PlaylistHistory>>>,
+    library: Arc<Mutex<LibraryIndex>>,
+    #[cfg(feature = "sqlite")]
+    database: Option<Arc<Mutex<Database>>>,
+    in_flight: std::sync::Mutex<HashSet<PathBuf>>,
+    album_stages: Mutex<HashMap<SpotifyUri, AlbumStage>>,
+    /// The files of every track done in this run, for its duplicates.
+    files: std::sync::Mutex<HashMap<SpotifyUri, Vec<PathBuf>>>,
+    /// Why tracks of the run were skipped, until their task looked at it.
+    skip_reasons: std::sync::Mutex<HashMap<SpotifyUri, SkipReason>>,
+    summary: std::sync::Mutex<RunSummary>,
+    error_log: Option<Arc<ErrorLog>>,
+    outcome_listener: Option<OutcomeListener>,
+    /// Held while waiting for the schedule window, so the parallel tasks
+    /// pause behind a single announcement.
+    schedule_gate: Mutex<()>,
+    /// When the last track started, for `tracks_per_minute`.
+    last_start: Mutex<Option<Instant>>,
+    /// Bytes streamed or reserved for streams in flight, for `--data-budget`.
+    /// Shared by the downloaders of a run that uses several.
+    data_used: Arc<AtomicU64>,
+    tag_pool: TagPool,
+    /// Display columns of progress bar messages, from the options of the
+    /// run.
+    label_width: usize,
+    /// When the tracks of each playlist were added, for `--file-mtime`,
+    /// fetched once per playlist.
+    added_dates: Mutex<HashMap<SpotifyUri, Arc<HashMap<SpotifyUri, SystemTime>>>>,
+    #[cfg(feature = "simulate")]
+    simulation: Option<Simulation>,
+}
+
+/// By default, a stream that delivers nothing for this long has stalled.
+pub const DEFAULT_STALL_TIMEOUT: Duration = Duration::from_secs(30);
+/// The least time any track gets to finish streaming.
+const MIN_TRACK_TIMEOUT: Duration = Duration::from_secs(60);
+/// Tracks get this many times their duration to finish streaming.
+pub const DEFAULT_TIMEOUT_FACTOR: f64 = 2.0;
+
+/// By default, one track at a time pauses for a fifth of each track.
+pub const DEFAULT_DELAY_FACTOR: f64 = 0.2;
+/// By default, loading a track is retried after 10, 20 and 30 seconds.
+pub const DEFAULT_RETRY_BACKOFF: Duration = Duration::from_secs(10);
+
+/// Files tagged at the same time, on blocking threads of their own.
+const TAG_THREADS: usize = 4;
+
+/// Spotify serves previews as MP3, whatever the requested format.
+const PREVIEW_FORMAT: Format = Format::Mp3;
+
+/// Existing files smaller than this are leftovers of a failed run rather than
+/// a track, and get replaced by a new download.
+const MIN_EXISTING_SIZE: u64 = 16 * 1024;
+/// How far the length of an existing file may be off the track's before it
+/// counts as broken.
+const DURATION_TOLERANCE: Duration = Duration::from_secs(2);
+
+/// Called with every track of the queue once it is done.
+pub type OutcomeListener = Box<dyn Fn(&Track, TrackOutcome) + Send + Sync>;
+
+/// What happened to the queue of a run.
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct RunSummary {
+    pub downloaded: Vec<TrackReport>,
+    pub skipped: usize,
+    pub skip_reasons: SkipCounts,
+    pub failed: Vec<TrackReport>,
+    pub replicas: Vec<ReplicaReport>,
+    /// Tracks left for the next run because the data budget was used up.
+    pub deferred: usize,
+}
+
+impl RunSummary {
+    pub fn record_skip(&mut self, reason: SkipReason, count: usize) {
+        self.skipped += count;
+        self.skip_reasons.add(reason, count);
+    }
+
+    /// Fails when any track of the run failed or was deferred, for
+    /// `--strict`.
+    pub fn check_complete(&self) -> Result<()> {
+        if self.failed.is_empty() && self.deferred == 0 {
+            return Ok(());
+        }
+        Err(anyhow::anyhow!(
+            "The run is incomplete: {} tracks failed, {} were deferred",
+            self.failed.len(),
+            self.deferred
+        ))
+    }
+}
+
+/// Why a track was not downloaded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SkipReason {
+    /// Its file is already in the destination.
+    Exists,
+    /// The download history has it, or it was deleted on purpose.
+    Archive,
+    /// Left out by `--released-after`, `--min-popularity` or a per-artist or
+    /// per-album cap.
+    Filtered,
+    /// Spotify has no metadata, or no preview, for it.
+    Unavailable,
+}
+
+impl fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SkipReason::Exists => "already exists",
+            SkipReason::Archive => "in archive",
+            SkipReason::Filtered => "filtered",
+            SkipReason::Unavailable => "unavailable",
+        })
+    }
+}
+
+/// The skipped tracks of a run by reason.
+#[derive(Debug, Default, Clone, Copy, Serialize)]
+pub struct SkipCounts {
+    pub exists: usize,
+    pub archive: usize,
+    pub filtered: usize,
+    pub unavailable: usize,
+}
+
+impl SkipCounts {
+    pub fn add(&mut self, reason: SkipReason, count: usize) {
+        *match reason {
+            SkipReason::Exists => &mut self.exists,
+            SkipReason::Archive => &mut self.archive,
+            SkipReason::Filtered => &mut self.filtered,
+            SkipReason::Unavailable => &mut self.unavailable,
+        } += count;
+    }
+
+    pub fn by_reason(&self) -> [(SkipReason, usize); 4] {
+        [
+            (SkipReason::Exists, self.exists),
+            (SkipReason::Archive, self.archive),
+            (SkipReason::Filtered, self.filtered),
+            (SkipReason::Unavailable, self.unavailable),
+        ]
+    }
+}
+
+impl fmt::Display for SkipCounts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let counts = self
+            .by_reason()
+            .into_iter()
+            .filter(|(_, count)| *count > 0)
+            .map(|(reason, count)| format!("{}: {}", reason, count))
+            .collect::<Vec<_>>();
+        f.write_str(&counts.join(", "))
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TrackReport {
+    pub uri: String,
+    pub name: String,
+    pub album: Option<String>,
+    pub cover_url: Option<String>,
+    pub error: Option<String>,
+}
+
+/// The copies made to one `--replicate` destination.
+#[derive(Debug, Clone, Serialize)]
+pub struct ReplicaReport {
+    pub destination: PathBuf,
+    pub copied: usize,
+    pub failed: Vec<ReplicaFailure>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ReplicaFailure {
+    pub file: PathBuf,
+    pub error: String,
+}
+
+/// A written and tagged file waiting in its `.part` location.
+struct StagedFile {
+    track: Track,
+    isrc: Option<String>,
+    chapters: Vec<Chapter>,
+    part: PathBuf,
+    target: PathBuf,
+    /// What `--file-mtime` sets the modification time to.
+    mtime: Option<SystemTime>,
+    /// The bitrate the audio was streamed at in kbps, if it was looked up.
+    #[cfg_attr(not(feature = "sqlite"), allow(dead_code))]
+    streamed_bitrate: Option<u32>,
+    /// A broken copy of the track elsewhere, removed once `target` is in place.
+    replaces: Option<PathBuf>,
+}
+
+/// A downloaded track of a staged album. It is only reported once the album
+/// has been moved into place, or has failed to.
+struct StagedTrack {
+    track: Track,
+    duplicates: Vec<Track>,
+    report: TrackReport,
+    files: Vec<StagedFile>,
+}
+
+#[derive(Default)]
+struct AlbumStage {
+    remaining: usize,
+    failed: bool,
+    tracks: Vec<StagedTrack>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TrackOutcome {
+    Downloaded,
+    Skipped,
+    Failed,
+    /// Not started because the run's data budget was used up.
+    Deferred,
 }
 
 #[derive(Debug, Clone)]
@@ -35,6 +287,84 @@ pub struct DownloadOptions {
     pub parallel: usize,
     pub format: Format,
     pub force: bool,
+    pub verify: bool,
+    pub checksums: Option<ChecksumStyle>,
+    pub temp_dir: Option<PathBuf>,
+    pub stage_albums: bool,
+    /// Treat encoding, writing, tagging and moving errors as a failure of the
+    /// track instead of aborting the run.
+    pub ignore_errors: bool,
+    /// With `ignore_errors`, keep files that could not be tagged.
+    pub keep_untagged: bool,
+    /// How often a track whose download stalls is streamed again before it
+    /// is counted as failed.
+    pub retries: usize,
+    /// File name template with `{artists}`, `{artist}`, `{title}`, `{album}`,
+    /// `{track}`, `{disc}`, `{discs}`, `{disc_folder}` and `{position}`
+    /// placeholders; `/` separates folders.
+    pub template: Option<String>,
+    /// Save the tracks' Canvas loop videos next to the audio files.
+    pub write_canvas: bool,
+    /// Re-encode PNG covers as JPEG before embedding them.
+    pub convert_png_covers: bool,
+    /// Scale covers to this size before embedding them.
+    pub cover_size: Option<CoverSize>,
+    /// JPEG quality of resized covers, 1 to 100.
+    pub cover_quality: u8,
+    /// Download the 30 second preview clips instead of the full tracks.
+    pub preview: bool,
+    /// Also record each file's Spotify URI and playlist in extended
+    /// attributes.
+    pub write_xattrs: bool,
+    /// A track that streams for longer than its duration times this is
+    /// restarted like a stalled one.
+    pub timeout_factor: f64,
+    /// A track whose stream delivers no audio for this long is restarted
+    /// right away instead of holding its slot until the track timeout.
+    pub stall_timeout: Duration,
+    /// Only start tracks during this daily window, pausing the queue
+    /// outside of it.
+    pub schedule_window: Option<ScheduleWindow>,
+    /// More destinations every finished file is copied to, keeping its path
+    /// relative to `destination`.
+    pub replicas: Vec<PathBuf>,
+    /// Metadata corrections applied before files are named and tagged.
+    pub overrides: Option<Arc<Overrides>>,
+    /// Fail tracks that can't be streamed at the requested bitrate instead
+    /// of downloading them at the best one available.
+    pub require_quality: bool,
+    /// Also take a file whose name only differs from the track's in case,
+    /// accents or Unicode normalization for the track.
+    pub loose_names: bool,
+    /// Cut tracks longer than this into several files.
+    pub split_longer_than: Option<Duration>,
+    /// Length of the parts of split tracks, give or take the search for a
+    /// quiet spot to cut at.
+    pub split_every: Duration,
+    /// Bytes the run may stream before it leaves the rest of the queue for
+    /// the next one.
+    pub data_budget: Option<u64>,
+    /// What the modification time of finished files is set to, left as
+    /// written when `None`.
+    pub file_mtime: Option<FileMtime>,
+    /// Count every track that didn't end up downloaded in full, verified
+    /// and tagged as failed, rather than skipping it or keeping it with
+    /// warnings.
+    pub strict: bool,
+    /// Display columns progress bar messages are fit into.
+    pub label_width: usize,
+    /// Pause after each track for this fraction of its duration, like the
+    /// Spotify client would while playing it.
+    pub delay_factor: f64,
+    /// How far each pause randomly differs from `delay_factor`, as a
+    /// fraction of it.
+    pub delay_jitter: f64,
+    /// The first wait before loading a track is retried, doubling up to
+    /// three times this.
+    pub retry_backoff: Duration,
+    /// Tracks started per minute at most, which bounds the metadata and
+    /// storage requests of a run.
+    pub tracks_per_minute: Option<u32>,
 }
 
 impl DownloadOptions {
@@ -46,140 +376,1111 @@ impl DownloadOptions {
             parallel,
             format,
             force,
+            verify: false,
+            checksums: None,
+            temp_dir: None,
+            stage_albums: false,
+            ignore_errors: false,
+            keep_untagged: false,
+            retries: 3,
+            template: None,
+            write_canvas: false,
+            convert_png_covers: false,
+            cover_size: None,
+            cover_quality: DEFAULT_COVER_QUALITY,
+            preview: false,
+            write_xattrs: false,
+            timeout_factor: DEFAULT_TIMEOUT_FACTOR,
+            stall_timeout: DEFAULT_STALL_TIMEOUT,
+            schedule_window: None,
+            replicas: Vec::new(),
+            overrides: None,
+            require_quality: false,
+            loose_names: false,
+            split_longer_than: None,
+            split_every: split::DEFAULT_SPLIT_EVERY,
+            data_budget: None,
+            file_mtime: None,
+            strict: false,
+            label_width: DEFAULT_LABEL_WIDTH,
+            // Parallel downloads don't pretend to be played anyway.
+            delay_factor: if parallel == 1 {
+                DEFAULT_DELAY_FACTOR
+            } else {
+                0.0
+            },
+            delay_jitter: 0.0,
+            retry_backoff: DEFAULT_RETRY_BACKOFF,
+            tracks_per_minute: None,
+        }
+    }
+
+    /// How long streaming a track of the given duration may take in total.
+    /// Long DJ sets get hours, short songs still fail within a minute or two.
+    pub fn track_timeout(&self, duration: Duration) -> Duration {
+        duration.mul_f64(self.timeout_factor).max(MIN_TRACK_TIMEOUT)
+    }
+
+    /// Where a file is written, verified and tagged before it is moved to
+    /// `target`: a `.part` file next to the target, or in the temp dir under
+    /// the same folders the target has below the destination, so files of
+    /// the same name in different folders don't share a `.part` file.
+    pub fn part_path(&self, target: &Path) -> PathBuf {
+        let mut name = target.file_name().unwrap_or_default().to_owned();
+        name.push(".part");
+        match &self.temp_dir {
+            Some(temp_dir) => {
+                let relative = target.strip_prefix(&self.destination).unwrap_or(target);
+                let folders: PathBuf = relative
+                    .parent()
+                    .into_iter()
+                    .flat_map(Path::components)
+                    .filter(|component| matches!(component, std::path::Component::Normal(_)))
+                    .collect();
+                temp_dir.join(folders).join(name)
+            }
+            None => target.with_file_name(name),
         }
     }
 }
 
 impl Downloader {
-    pub fn new(session: Session, history: Option<Arc<Mutex<PlaylistHistory>>>) -> Self {
+    pub fn new(
+        session: Session,
+        history: Option<Arc<Mutex<PlaylistHistory>>>,
+        library: Arc<Mutex<LibraryIndex>>,
+    ) -> Self {
         Downloader {
             session,
             progress_bar: MultiProgress::new(),
             history,
+            library,
+            #[cfg(feature = "sqlite")]
+            database: None,
+            in_flight: std::sync::Mutex::new(HashSet::new()),
+            album_stages: Mutex::new(HashMap::new()),
+            files: std::sync::Mutex::new(HashMap::new()),
+            skip_reasons: std::sync::Mutex::new(HashMap::new()),
+            summary: std::sync::Mutex::new(RunSummary::default()),
+            error_log: None,
+            outcome_listener: None,
+            schedule_gate: Mutex::new(()),
+            last_start: Mutex::new(None),
+            data_used: Arc::new(AtomicU64::new(0)),
+            tag_pool: TagPool::new(TAG_THREADS),
+            label_width: DEFAULT_LABEL_WIDTH,
+            added_dates: Mutex::new(HashMap::new()),
+            #[cfg(feature = "simulate")]
+            simulation: None,
         }
     }
 
+    #[cfg(feature = "sqlite")]
+    pub fn with_database(mut self, database: Database) -> Self {
+        self.database = Some(Arc::new(Mutex::new(database)));
+        self
+    }
+
+    pub fn with_error_log(mut self, error_log: ErrorLog) -> Self {
+        self.error_log = Some(Arc::new(error_log));
+        self
+    }
+
+    pub fn with_outcome_listener(mut self, listener: OutcomeListener) -> Self {
+        self.outcome_listener = Some(listener);
+        self
+    }
+
+    /// Counts the data streamed against `--data-budget` in `data_used`, so
+    /// the budget holds for the whole run across several downloaders.
+    pub fn with_data_used(mut self, data_used: Arc<AtomicU64>) -> Self {
+        self.data_used = data_used;
+        self
+    }
+
+    /// Streams synthetic audio with made-up metadata instead of fetching
+    /// either from Spotify.
+    #[cfg(feature = "simulate")]
+    pub fn with_simulation(mut self, simulation: Simulation) -> Self {
+        self.simulation = Some(simulation);
+        self
+    }
+
     pub async fn download_tracks(
-        self,
+        mut self,
         tracks: Vec<Track>,
         options: &DownloadOptions,
-    ) -> Result<()> {
-        futures::stream::iter(tracks)
-            .map(|track| self.download_track(track, options))
+    ) -> Result<RunSummary> {
+        self.label_width = options.label_width;
+        let queue = Self::group_duplicates(tracks);
+        self.summary.lock().unwrap().replicas = options
+            .replicas
+            .iter()
+            .map(|destination| ReplicaReport {
+                destination: destination.clone(),
+                copied: 0,
+                failed: Vec::new(),
+            })
+            .collect();
+        if options.stage_albums {
+            let mut stages = self.album_stages.lock().await;
+            for album in queue.iter().filter_map(|(track, _)| track.album()) {
+                stages.entry(album).or_default().remaining += 1;
+            }
+        }
+
+        let downloader = &self;
+        futures::stream::iter(queue)
+            .map(|(track, duplicates)| async move {
+                if let Some(window) = &options.schedule_window {
+                    let _gate = downloader.schedule_gate.lock().await;
+                    window.wait().await;
+                }
+                if let Some(per_minute) = options.tracks_per_minute {
+                    downloader.wait_for_start(per_minute).await;
+                }
+                let album = track.album().filter(|_| options.stage_albums);
+                let outcome = downloader.download_track(track.clone(), options).await;
+                let skip_reason = downloader.take_skip_reason(&track.id);
+                // A track skipped because its file is there already counts
+                // as in place, any other skip does not.
+                let in_place = match outcome {
+                    Ok(TrackOutcome::Downloaded) => true,
+                    Ok(TrackOutcome::Skipped) => {
+                        matches!(skip_reason, Some(SkipReason::Exists | SkipReason::Archive))
+                    }
+                    _ => false,
+                };
+                let staged = match &album {
+                    Some(album) => {
+                        downloader
+                            .finish_album_track(album, &track, &duplicates, in_place, options)
+                            .await
+                    }
+                    None => false,
+                };
+                let outcome = outcome?;
+                if !staged {
+                    downloader
+                        .report_outcome(&track, &duplicates, outcome, in_place, options)
+                        .await;
+                }
+                Ok::<_, anyhow::Error>(outcome)
+            })
             .buffer_unordered(options.parallel)
             .try_collect::<Vec<_>>()
             .await?;
 
-        Ok(())
+        if let Err(err) = self.library.lock().await.flush() {
+            tracing::warn!(error = %err, "Failed to write the library index");
+        }
+        Ok(self.summary.into_inner().unwrap())
+    }
+
+    /// Records the duplicates of a track whose file is in place for their own
+    /// playlists, and passes the outcome of all of them to the listener.
+    async fn report_outcome(
+        &self,
+        track: &Track,
+        duplicates: &[Track],
+        outcome: TrackOutcome,
+        in_place: bool,
+        options: &DownloadOptions,
+    ) {
+        if in_place && !options.preview {
+            let files = self.files_of(&track.id);
+            for duplicate in duplicates {
+                self.mark_downloaded(duplicate, &files).await;
+            }
+        }
+        if let Some(listener) = &self.outcome_listener {
+            for track in std::iter::once(track).chain(duplicates) {
+                listener(track, outcome);
+            }
+        }
+    }
+
+    /// Collapses tracks that appear in several inputs into a single download.
+    /// The other occurrences are returned alongside it so they can be recorded
+    /// for their own playlists once the file is in place.
+    fn group_duplicates(tracks: Vec<Track>) -> Vec<(Track, Vec<Track>)> {
+        let mut grouped: Vec<(Track, Vec<Track>)> = Vec::new();
+        let mut positions: HashMap<SpotifyUri, usize> = HashMap::new();
+        for track in tracks {
+            match positions.get(&track.id) {
+                Some(&position) => {
+                    tracing::info!(
+                        "Track {} appears in several inputs, downloading it once",
+                        track.id
+                    );
+                    grouped[position].1.push(track);
+                }
+                None => {
+                    positions.insert(track.id.clone(), grouped.len());
+                    grouped.push((track, Vec::new()));
+                }
+            }
+        }
+        grouped
     }
 
     #[tracing::instrument(name = "download_track", skip(self))]
-    async fn download_track(&self, track: Track, options: &DownloadOptions) -> Result<()> {
-        if !options.force && self.should_skip_track(&track).await {
-            println!(
-                "Skipping track {} - already downloaded from playlist history",
-                track.id
-            );
-            return Ok(());
+    async fn download_track(
+        &self,
+        track: Track,
+        options: &DownloadOptions,
+    ) -> Result<TrackOutcome> {
+        if !options.force && !options.preview && self.should_skip_track(&track).await {
+            return Ok(self.skip(
+                &track.id,
+                SkipReason::Archive,
+                format!("{} was downloaded from this playlist before", track.id),
+            ));
         }
 
-        let metadata = match track.metadata(&self.session).await {
+        let mut metadata = match self.track_metadata(&track).await {
             Ok(metadata) => metadata,
+            Err(err) if options.strict => {
+                tracing::error!(error = %err, "Failing track because metadata could not be loaded");
+                self.log_error(Level::Error, &track.id, None, Phase::Metadata, 0, &err);
+                println!(
+                    "Failed ({}): {}: {}",
+                    SkipReason::Unavailable,
+                    track.id,
+                    err
+                );
+                self.summary
+                    .lock()
+                    .unwrap()
+                    .failed
+                    .push(TrackReport::unavailable(&track.id, err.to_string()));
+                return Ok(TrackOutcome::Failed);
+            }
             Err(err) => {
                 tracing::warn!(error = %err, "Skipping track because metadata could not be loaded");
-                println!("Skipping track {:?}: {}", track.id, err);
-                return Ok(());
+                self.log_error(Level::Warning, &track.id, None, Phase::Metadata, 0, &err);
+                return Ok(self.skip(
+                    &track.id,
+                    SkipReason::Unavailable,
+                    format!("{}: {}", track.id, err),
+                ));
             }
         };
+        if let Some(overrides) = &options.overrides {
+            overrides.apply(&mut metadata);
+        }
+        let position = self.playlist_position(&track).await;
+        if options.preview {
+            return self
+                .download_preview(&track.id, &metadata, position, options)
+                .await;
+        }
         tracing::info!("Downloading track: {:?}", metadata.track_name);
 
-        let file_stem = self.get_file_name(&metadata);
+        let file_stem = match &options.template {
+            Some(template) => self.render_template(template, &metadata, position),
+            None => self.get_file_name(&metadata),
+        };
         let mut target_path = options.destination.join(&file_stem);
         target_path.set_extension(options.format.extension());
+        let duration = Duration::from_millis(metadata.duration.max(0) as u64);
+        let split_count = options
+            .split_longer_than
+            .filter(|&longer_than| duration > longer_than)
+            .map(|_| split::part_count(duration, options.split_every));
+        let targets: Vec<(String, PathBuf)> = match split_count {
+            Some(count) => (1..=count)
+                .map(|part| {
+                    let stem = split::part_stem(&file_stem, part, count);
+                    let mut target = options.destination.join(&stem);
+                    target.set_extension(options.format.extension());
+                    (stem, target)
+                })
+                .collect(),
+            None => vec![(file_stem.clone(), target_path.clone())],
+        };
 
-        if !options.force {
-            if target_path.exists() {
-                println!(
-                    "File already exists, skipping: {}",
-                    target_path.display()
-                );
-                self.mark_downloaded(&track).await;
-                return Ok(());
+        // Parts are shorter than the track, so they are not checked against
+        // its length like a whole file.
+        if !options.force
+            && split_count.is_some()
+            && targets.iter().all(|(_, target)| target.exists())
+        {
+            let files: Vec<PathBuf> = targets.iter().map(|(_, target)| target.clone()).collect();
+            self.mark_downloaded(&track, &files).await;
+            return Ok(self.skip(&track.id, SkipReason::Exists, targets[0].1.display()));
+        }
+        // A broken copy is kept until its replacement has been moved into
+        // place, so a failed or interrupted download does not lose it.
+        let mut replaces = None;
+        if !options.force && split_count.is_none() {
+            let mut candidates = Vec::new();
+            if let Some(existing) = self.library.lock().await.path_of(&track.id) {
+                candidates.push(existing.to_path_buf());
             }
-
-            if let Some(legacy) = self.legacy_file_name(&metadata) {
+            candidates.push(target_path.clone());
+            if options.template.is_none()
+                && let Some(legacy) = self.legacy_file_name(&metadata)
+            {
                 let mut legacy_path = options.destination.join(&legacy);
                 legacy_path.set_extension(options.format.extension());
-                if legacy_path.exists() {
-                    println!(
-                        "File already exists, skipping: {}",
-                        legacy_path.display()
-                    );
-                    self.mark_downloaded(&track).await;
-                    return Ok(());
+                candidates.push(legacy_path);
+            }
+            if options.loose_names
+                && !candidates.iter().any(|candidate| candidate.exists())
+                && let Some(similar) = find_loosely_named(&target_path)
+            {
+                candidates.push(similar);
+            }
+
+            for existing in candidates {
+                if !existing.exists() {
+                    continue;
+                }
+                match Self::existing_file_intact(&existing, &metadata).await {
+                    Some(false) => {
+                        println!(
+                            "Existing file looks broken, downloading it again: {}",
+                            existing.display()
+                        );
+                        replaces.get_or_insert(existing);
+                    }
+                    intact => {
+                        if intact.is_none() {
+                            tracing::warn!(
+                                "Could not read the length of {}, keeping it",
+                                existing.display()
+                            );
+                        }
+                        self.mark_downloaded(&track, std::slice::from_ref(&existing))
+                            .await;
+                        return Ok(self.skip(&track.id, SkipReason::Exists, existing.display()));
+                    }
                 }
             }
         }
 
-        let path = target_path
-            .to_str()
-            .ok_or(anyhow::anyhow!("Could not set the output path"))?
-            .to_string();
+        let Some(_claim) = PathClaim::new(&self.in_flight, &target_path) else {
+            return Ok(self.skip(
+                &track.id,
+                SkipReason::Exists,
+                format!(
+                    "{} is being downloaded by another task",
+                    target_path.display()
+                ),
+            ));
+        };
+
+        let mut part_claims = Vec::with_capacity(targets.len());
+        for (_, target) in &targets {
+            let part = options.part_path(target);
+            let Some(claim) = PathClaim::new(&self.in_flight, &part) else {
+                return Ok(self.skip(
+                    &track.id,
+                    SkipReason::Exists,
+                    format!("{} is being written by another task", part.display()),
+                ));
+            };
+            part_claims.push(claim);
+        }
 
+        let part_path = options.part_path(&target_path);
+        if let Some(parent) = part_path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        let estimated = streamed_bytes(duration);
+        if let Some(budget) = options.data_budget
+            && !self.reserve_data(estimated, budget)
+        {
+            return Ok(self.defer(&file_stem));
+        }
         let pb = self.add_progress_bar(&metadata, &file_stem);
+        let mut guard = PartGuard::new(&pb, self.fit(&format!("Cancelled {}", file_stem)));
 
-        let stream = Stream::new(self.session.clone());
-        let channel = match stream.stream(track.clone()).await {
-            Ok(channel) => channel,
+        let streamed_bitrate = match self.check_quality(&track, &file_stem, options).await {
+            Ok(bitrate) => bitrate,
             Err(e) => {
-                self.fail_with_error(&pb, &file_stem, e.to_string());
-                return Ok(());
+                self.fail_with_error(&pb, &metadata, &file_stem, Phase::Stream, 0, e);
+                return Ok(TrackOutcome::Failed);
             }
         };
 
-        let samples = match self.buffer_track(channel, &pb, &file_stem).await {
-            Ok(Some(samples)) => samples,
-            Ok(None) => {
-                tracing::warn!("Skipping {}, song download timed out", file_stem);
-                pb.finish_with_message(format!("Skipped {}", file_stem));
-                return Ok(());
+        let track_timeout = options.track_timeout(duration);
+        let mut retries = 0;
+        let mut restarts = 0;
+        // A stalled stream is dropped, which stops its player, and the track
+        // is streamed again from the start with a fresh one: librespot can
+        // only seek to whole milliseconds, so resuming would not line up with
+        // the samples kept.
+        let samples = loop {
+            let stream = self.new_stream(options);
+            let channel = match stream.stream(track.clone()).await {
+                Ok(channel) => channel,
+                Err(e) => {
+                    self.fail_with_error(&pb, &metadata, &file_stem, Phase::Stream, retries, e);
+                    return Ok(TrackOutcome::Failed);
+                }
+            };
+
+            match self
+                .buffer_track(
+                    channel,
+                    &pb,
+                    &file_stem,
+                    track_timeout,
+                    options.stall_timeout,
+                    &mut retries,
+                )
+                .await
+            {
+                Ok(Some(samples)) => break samples,
+                Ok(None) if restarts < options.retries => {
+                    restarts += 1;
+                    retries += 1;
+                    // The stream starts over, so the track is transferred
+                    // again.
+                    self.data_used.fetch_add(estimated, Ordering::Relaxed);
+                    tracing::warn!(
+                        "Song download of {} stalled, restarting ({}/{})",
+                        file_stem,
+                        restarts,
+                        options.retries
+                    );
+                    self.log_error(
+                        Level::Warning,
+                        &track.id,
+                        Some(&file_stem),
+                        Phase::Download,
+                        retries,
+                        &anyhow::anyhow!("Song download stalled"),
+                    );
+                    pb.set_position(0);
+                    pb.set_message(self.fit(&format!(
+                        "Stalled, restarting ({}/{}) {}",
+                        restarts, options.retries, file_stem
+                    )));
+                }
+                Ok(None) => {
+                    let e = anyhow::anyhow!(
+                        "Song download stalled and {} restarts did not help",
+                        restarts
+                    );
+                    self.fail_with_error(&pb, &metadata, &file_stem, Phase::Download, retries, e);
+                    return Ok(TrackOutcome::Failed);
+                }
+                Err(e) => {
+                    self.fail_with_error(&pb, &metadata, &file_stem, Phase::Download, retries, e);
+                    return Ok(TrackOutcome::Failed);
+                }
             }
-            Err(e) => {
-                self.fail_with_error(&pb, &file_stem, e.to_string());
-                return Ok(());
+        };
+
+        if options.strict
+            && let Err(err) = Self::check_streamed_length(&samples, duration)
+        {
+            self.fail_with_error(&pb, &metadata, &file_stem, Phase::Download, retries, err);
+            return Ok(TrackOutcome::Failed);
+        }
+
+        // Tracks over --split-longer-than are cut into parts, each written
+        // and tagged as a file of its own.
+        let pieces = match split_count {
+            Some(count) => split::split_samples(samples, options.split_every, count)
+                .into_iter()
+                .enumerate()
+                .map(|(index, samples)| (samples, Some((index + 1, count))))
+                .collect(),
+            None => vec![(samples, None)],
+        };
+        let mtime = self.file_mtime(&track, &metadata, options).await;
+        let mut files: Vec<StagedFile> = Vec::new();
+        let mut tag_warning = None;
+        for ((samples, part), (stem, target)) in pieces.into_iter().zip(&targets) {
+            let part_path = options.part_path(target);
+            guard.watch(&part_path);
+            match self
+                .write_file(
+                    &track, &metadata, samples, part, stem, &part_path, &pb, options,
+                )
+                .await
+            {
+                Ok(warning) => tag_warning = tag_warning.or(warning),
+                Err((phase, err)) => {
+                    if matches!(phase, Phase::Verify) {
+                        self.fail_with_error(&pb, &metadata, stem, phase, 0, err);
+                        return Ok(TrackOutcome::Failed);
+                    }
+                    return self.fail_or_abort(&pb, &metadata, stem, phase, err, options);
+                }
+            }
+            files.push(StagedFile {
+                track: track.clone(),
+                isrc: metadata.isrc.clone(),
+                chapters: metadata.chapters.clone(),
+                part: part_path,
+                target: target.clone(),
+                mtime,
+                streamed_bitrate,
+                replaces: replaces.take().filter(|replaced| replaced != target),
+            });
+        }
+
+        // Staged tracks are reported once their album has been moved.
+        let staged = match track.album().filter(|_| options.stage_albums) {
+            Some(album) => {
+                pb.set_message(self.fit(&format!("Staged {}", file_stem)));
+                if let Some(stage) = self.album_stages.lock().await.get_mut(&album) {
+                    for file in &files {
+                        guard.release(&file.part);
+                    }
+                    stage.tracks.push(StagedTrack {
+                        track: track.clone(),
+                        duplicates: Vec::new(),
+                        report: TrackReport::new(&metadata, tag_warning.clone()),
+                        files,
+                    });
+                }
+                true
+            }
+            None => {
+                pb.set_message(self.fit(&format!("Moving {}", file_stem)));
+                for file in &files {
+                    if let Err(err) = self.finalize(file, options).await {
+                        return self.fail_or_abort(
+                            &pb,
+                            &metadata,
+                            &file_stem,
+                            Phase::Move,
+                            err,
+                            options,
+                        );
+                    }
+                    guard.release(&file.part);
+                }
+                false
             }
         };
+        guard.done();
 
+        let delay = profile::track_delay(
+            duration,
+            options.delay_factor,
+            options.delay_jitter,
+            fastrand::f64(),
+        );
+        if !delay.is_zero() {
+            pb.set_message(self.fit(&format!(
+                "Downloaded {}. Delaying next song by {}s",
+                file_stem,
+                delay.as_secs()
+            )));
+            tokio::time::sleep(delay).await;
+            pb.finish_with_message(self.fit(&format!("Completed {}", file_stem)));
+        } else {
+            pb.finish_with_message(self.fit(&format!("Downloaded {}", file_stem)));
+        }
+
+        if !staged {
+            self.summary
+                .lock()
+                .unwrap()
+                .downloaded
+                .push(TrackReport::new(&metadata, tag_warning));
+        }
+        Ok(TrackOutcome::Downloaded)
+    }
+
+    /// Saves the track's preview clip as a tagged MP3 suffixed `(preview)`.
+    /// Previews are not recorded anywhere, so the full track is still
+    /// downloaded by a later run.
+    async fn download_preview(
+        &self,
+        track: &SpotifyUri,
+        metadata: &TrackMetadata,
+        position: Option<usize>,
+        options: &DownloadOptions,
+    ) -> Result<TrackOutcome> {
+        let file_stem = match &options.template {
+            Some(template) => self.render_template(template, metadata, position),
+            None => self.get_file_name(metadata),
+        } + " (preview)";
+        let mut target_path = options.destination.join(&file_stem);
+        target_path.set_extension(PREVIEW_FORMAT.extension());
+
+        if !options.force && target_path.exists() {
+            return Ok(self.skip(track, SkipReason::Exists, target_path.display()));
+        }
+        let Some(preview) = metadata.preview else {
+            if options.strict {
+                self.summary.lock().unwrap().failed.push(TrackReport::new(
+                    metadata,
+                    Some("the track has no preview".to_string()),
+                ));
+                println!(
+                    "Failed ({}): {} has no preview",
+                    SkipReason::Unavailable,
+                    file_stem
+                );
+                return Ok(TrackOutcome::Failed);
+            }
+            return Ok(self.skip(
+                track,
+                SkipReason::Unavailable,
+                format!("{} has no preview", file_stem),
+            ));
+        };
+
+        let part_path = options.part_path(&target_path);
+        let (Some(_claim), Some(_part_claim)) = (
+            PathClaim::new(&self.in_flight, &target_path),
+            PathClaim::new(&self.in_flight, &part_path),
+        ) else {
+            return Ok(self.skip(
+                track,
+                SkipReason::Exists,
+                format!(
+                    "{} is being downloaded by another task",
+                    target_path.display()
+                ),
+            ));
+        };
+
+        let pb = self.add_progress_bar(metadata, &file_stem);
+        let mut guard = PartGuard::new(&pb, self.fit(&format!("Cancelled {}", file_stem)));
+        tracing::info!("Downloading preview: {}", file_stem);
+        let clip = match self.session.spclient().get_audio_preview(&preview).await {
+            Ok(clip) => clip,
+            Err(err) => {
+                self.fail_with_error(&pb, metadata, &file_stem, Phase::Download, 0, err.into());
+                return Ok(TrackOutcome::Failed);
+            }
+        };
+        pb.set_position(pb.length().unwrap_or_default());
+
+        if let Some(parent) = part_path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        guard.watch(&part_path);
+        if let Err(err) = tokio::fs::write(&part_path, &clip).await {
+            return self.fail_or_abort(
+                &pb,
+                metadata,
+                &file_stem,
+                Phase::Write,
+                err.into(),
+                options,
+            );
+        }
+        let tagged = self
+            .tag_pool
+            .tag(TagJob {
+                metadata: metadata.clone(),
+                title: None,
+                path: part_path.clone(),
+                format: PREVIEW_FORMAT,
+                covers: CoverOptions::default(),
+            })
+            .await;
+        let moved = match tagged {
+            Ok(_) => move_file(&part_path, &target_path)
+                .await
+                .map_err(|err| (Phase::Move, err)),
+            Err(err) => Err((Phase::Tag, err)),
+        };
+        if let Err((phase, err)) = moved {
+            return self.fail_or_abort(&pb, metadata, &file_stem, phase, err, options);
+        }
+        guard.done();
+
+        pb.finish_with_message(self.fit(&format!("Downloaded {}", file_stem)));
+        self.summary
+            .lock()
+            .unwrap()
+            .downloaded
+            .push(TrackReport::new(metadata, None));
+        Ok(TrackOutcome::Downloaded)
+    }
+
+    /// Encodes, writes, verifies and tags a track, or one `part` of it as
+    /// `(part, count)`, at `part_path`. Returns the warning when its tags
+    /// didn't read back, or the phase that failed. The caller's
+    /// [`PartGuard`] removes the file on failure.
+    #[allow(clippy::too_many_arguments)]
+    async fn write_file(
+        &self,
+        track: &Track,
+        metadata: &TrackMetadata,
+        samples: Samples,
+        part: Option<(usize, usize)>,
+        file_stem: &str,
+        part_path: &Path,
+        pb: &ProgressBar,
+        options: &DownloadOptions,
+    ) -> Result<Option<String>, (Phase, anyhow::Error)> {
         tracing::info!("Encoding track: {}", file_stem);
-        pb.set_message(format!("Encoding {}", file_stem));
+        pb.set_message(self.fit(&format!("Encoding {}", file_stem)));
 
         let encoder = crate::encoder::get_encoder(options.format);
-        let stream = encoder.encode(samples).await?;
-
-        pb.set_message(format!("Writing {}", file_stem));
-        tracing::info!("Writing track: {:?} to file: {}", file_stem, &path);
-        stream.write_to_file(&path).await?;
+        let stream = encoder
+            .encode(samples)
+            .await
+            .map_err(|err| (Phase::Encode, err))?;
 
-        let tags = metadata.tags().await?;
-        encoder::tags::store_tags(path, &tags, options.format).await?;
+        pb.set_message(self.fit(&format!("Writing {}", file_stem)));
+        tracing::info!(
+            "Writing track: {:?} to file: {}",
+            file_stem,
+            part_path.display()
+        );
+        stream
+            .write_to_file(part_path)
+            .await
+            .map_err(|err| (Phase::Write, err))?;
 
-        if options.parallel == 1 {
-            let delay_before_next_download = (metadata.duration.max(0) as u64) / 5;
-            pb.set_message(format!(
-                "Downloaded {}. Delaying next song by {}s",
-                file_stem,
-                delay_before_next_download / 1000
+        if options.verify
+            && !self
+                .verify_written_file(&stream, part_path, options.format, pb)
+                .await
+        {
+            return Err((
+                Phase::Verify,
+                anyhow::anyhow!("the written file failed verification"),
             ));
-            tokio::time::sleep(Duration::from_millis(delay_before_next_download)).await;
-            pb.finish_with_message(format!("Completed {}", file_stem));
+        }
+
+        pb.set_message(self.fit(&format!("Tagging {}", file_stem)));
+        let tagged = self
+            .tag_pool
+            .tag(TagJob {
+                metadata: metadata.clone(),
+                title: part.map(|(part, count)| {
+                    format!("{} (part {} of {})", metadata.track_name, part, count)
+                }),
+                path: part_path.to_path_buf(),
+                format: options.format,
+                covers: CoverOptions {
+                    convert_png: options.convert_png_covers,
+                    size: options.cover_size,
+                    quality: options.cover_quality,
+                },
+            })
+            .await;
+        match tagged {
+            Ok(mismatched) if !mismatched.is_empty() && options.strict => Err((
+                Phase::Tag,
+                anyhow::anyhow!("the {} did not read back", mismatched.join(", ")),
+            )),
+            Ok(mismatched) if !mismatched.is_empty() => {
+                let err = anyhow::anyhow!("the {} did not read back", mismatched.join(", "));
+                tracing::warn!(error = %err, "Tagged {} with warnings", file_stem);
+                println!("Tagged {} with warnings: {}", file_stem, err);
+                self.log_error(
+                    Level::Warning,
+                    &track.id,
+                    Some(file_stem),
+                    Phase::Tag,
+                    0,
+                    &err,
+                );
+                Ok(Some(format!("tagged with warnings: {}", err)))
+            }
+            Ok(_) => Ok(None),
+            Err(err) if options.ignore_errors && options.keep_untagged && !options.strict => {
+                tracing::warn!(error = %err, "Keeping {} untagged", file_stem);
+                println!("Failed to tag {}, keeping it untagged: {}", file_stem, err);
+                self.log_error(
+                    Level::Warning,
+                    &track.id,
+                    Some(file_stem),
+                    Phase::Tag,
+                    0,
+                    &err,
+                );
+                Ok(None)
+            }
+            Err(err) => Err((Phase::Tag, err)),
+        }
+    }
+
+    /// Moves a written file into place and records it.
+    async fn finalize(&self, file: &StagedFile, options: &DownloadOptions) -> Result<()> {
+        move_file(&file.part, &file.target).await?;
+        let path = file.target.to_string_lossy().to_string();
+
+        if let Some(replaced) = &file.replaces
+            && let Err(err) = tokio::fs::remove_file(replaced).await
+        {
+            tracing::warn!(error = %err, "Could not remove broken file {}", replaced.display());
+        }
+
+        if let Some(time) = file.mtime
+            && let Err(err) = mtime::set_mtime(&file.target, time)
+        {
+            tracing::warn!(error = %err, "Failed to set the modification time of {}", path);
+            self.log_error(Level::Warning, &file.track.id, None, Phase::Record, 0, &err);
+        }
+
+        if let Err(err) =
+            self.library
+                .lock()
+                .await
+                .record(&file.target, &file.track.id, file.isrc.clone())
+        {
+            tracing::warn!(error = %err, "Failed to record file in library index");
+            self.log_error(Level::Warning, &file.track.id, None, Phase::Record, 0, &err);
+        }
+
+        if let Some(style) = options.checksums
+            && let Err(err) = checksums::write_checksum(&file.target, style)
+        {
+            tracing::warn!(error = %err, "Failed to write checksum for {}", path);
+            self.log_error(Level::Warning, &file.track.id, None, Phase::Record, 0, &err);
+        }
+
+        // MP3 has no chapter support players agree on, so they go next to it.
+        if options.format == Format::Mp3
+            && !file.chapters.is_empty()
+            && let Err(err) = chapters::write_sidecar(&file.target, &file.chapters).await
+        {
+            tracing::warn!(error = %err, "Failed to write the chapters of {}", path);
+            self.log_error(Level::Warning, &file.track.id, None, Phase::Record, 0, &err);
+        }
+
+        if options.write_xattrs
+            && let Err(err) = self.write_xattrs(&file.track, &file.target)
+        {
+            tracing::warn!(error = %err, "Failed to write extended attributes of {}", path);
+            self.log_error(Level::Warning, &file.track.id, None, Phase::Record, 0, &err);
+        }
+
+        if options.write_canvas
+            && let Err(err) = self.write_canvas(&file.track, &file.target).await
+        {
+            tracing::warn!(error = %err, "Failed to save the Canvas video of {}", path);
+            self.log_error(Level::Warning, &file.track.id, None, Phase::Canvas, 0, &err);
+        }
+
+        #[cfg(feature = "sqlite")]
+        self.record_in_database(file, &path, options).await;
+
+        for replica in &options.replicas {
+            self.replicate(file, replica, options).await;
+        }
+
+        self.mark_downloaded(&file.track, std::slice::from_ref(&file.target))
+            .await;
+        Ok(())
+    }
+
+    /// Copies a finished file to a replica destination and counts the
+    /// outcome in the summary. A failed copy doesn't fail the track, it is
+    /// in the destination after all.
+    async fn replicate(&self, file: &StagedFile, replica: &Path, options: &DownloadOptions) {
+        let relative = file
+            .target
+            .strip_prefix(&options.destination)
+            .unwrap_or(Path::new(file.target.file_name().unwrap_or_default()));
+        let copy = replica.join(relative);
+        let copied = copy_file(&file.target, &copy)
+            .await
+            .and_then(|()| match file.mtime {
+                Some(time) => mtime::set_mtime(&copy, time),
+                None => Ok(()),
+            });
+        if let Err(err) = &copied {
+            let (from, to) = (file.target.display(), copy.display());
+            tracing::warn!(error = %err, "Failed to copy {} to {}", from, to);
+            println!("Failed to copy {} to {}: {}", from, to, err);
+            self.log_error(
+                Level::Warning,
+                &file.track.id,
+                None,
+                Phase::Replicate,
+                0,
+                err,
+            );
+        }
+
+        let mut summary = self.summary.lock().unwrap();
+        let Some(report) = summary
+            .replicas
+            .iter_mut()
+            .find(|report| report.destination == replica)
+        else {
+            return;
+        };
+        match copied {
+            Ok(()) => report.copied += 1,
+            Err(err) => report.failed.push(ReplicaFailure {
+                file: copy,
+                error: err.to_string(),
+            }),
+        }
+    }
+
+    fn write_xattrs(&self, track: &Track, target: &Path) -> Result<()> {
+        let uri = track.id.to_uri()?;
+        let playlist = track
+            .playlist()
+            .map(|playlist| playlist.to_uri())
+            .transpose()?;
+        xattrs::write_provenance(target, &uri, playlist.as_deref())
+    }
+
+    /// Saves the track's Canvas video as an `.mp4` next to its audio file.
+    /// Most tracks have none, which is not an error.
+    async fn write_canvas(&self, track: &Track, target: &Path) -> Result<()> {
+        let Some(url) = track.canvas_url(&self.session).await? else {
+            return Ok(());
+        };
+        let request = Request::builder()
+            .method(Method::GET)
+            .uri(url)
+            .body(Bytes::new())?;
+        let video = self.session.http_client().request_body(request).await?;
+        tokio::fs::write(target.with_extension("mp4"), video).await?;
+        Ok(())
+    }
+
+    /// Counts one finished track of a staged album. Once the album's last
+    /// track is done, its files are moved into place together, or left in the
+    /// staging location if any track failed or was skipped for another
+    /// reason than its file being there already. Its staged tracks are then
+    /// reported as downloaded, or as failed if they were not moved. Returns
+    /// whether `track` is one of them, so it is not reported again.
+    async fn finish_album_track(
+        &self,
+        album: &SpotifyUri,
+        track: &Track,
+        duplicates: &[Track],
+        in_place: bool,
+        options: &DownloadOptions,
+    ) -> bool {
+        let (staged, stage) = {
+            let mut stages = self.album_stages.lock().await;
+            let Some(stage) = stages.get_mut(album) else {
+                return false;
+            };
+            let staged = match stage
+                .tracks
+                .iter_mut()
+                .find(|staged| staged.track.id == track.id)
+            {
+                Some(staged) => {
+                    staged.duplicates = duplicates.to_vec();
+                    true
+                }
+                None => false,
+            };
+            stage.remaining = stage.remaining.saturating_sub(1);
+            stage.failed |= !in_place;
+            if stage.remaining > 0 {
+                return staged;
+            }
+            (staged, stages.remove(album))
+        };
+        let Some(stage) = stage else {
+            return staged;
+        };
+
+        if stage.failed {
+            println!(
+                "Album {} is incomplete, leaving its {} downloaded tracks staged",
+                album,
+                stage.tracks.len()
+            );
         } else {
-            pb.finish_with_message(format!("Downloaded {}", file_stem));
+            tracing::info!(
+                "Moving {} staged tracks of album {}",
+                stage.tracks.len(),
+                album
+            );
+        }
+        for mut staged in stage.tracks {
+            let result = if stage.failed {
+                Err(anyhow::anyhow!(
+                    "album {} is incomplete, the track was left staged",
+                    album
+                ))
+            } else {
+                self.finalize_staged(&staged.files, options).await
+            };
+            let outcome = match result {
+                Ok(()) => {
+                    self.summary.lock().unwrap().downloaded.push(staged.report);
+                    TrackOutcome::Downloaded
+                }
+                Err(err) => {
+                    staged.report.error = Some(err.to_string());
+                    self.summary.lock().unwrap().failed.push(staged.report);
+                    TrackOutcome::Failed
+                }
+            };
+            self.report_outcome(
+                &staged.track,
+                &staged.duplicates,
+                outcome,
+                outcome == TrackOutcome::Downloaded,
+                options,
+            )
+            .await;
         }
+        staged
+    }
 
-        self.mark_downloaded(&track).await;
+    /// Moves the files of a staged track into place, stopping at the first
+    /// that fails.
+    async fn finalize_staged(&self, files: &[StagedFile], options: &DownloadOptions) -> Result<()> {
+        for file in files {
+            if let Err(err) = self.finalize(file, options).await {
+                tracing::error!(error = %err, "Failed to move {} into place", file.part.display());
+                self.log_error(Level::Error, &file.track.id, None, Phase::Move, 0, &err);
+                println!("Failed to move {} into place: {}", file.part.display(), err);
+                return Err(err);
+            }
+        }
         Ok(())
     }
 
+    /// Verifies the written file, writing it again from the encoded stream when
+    /// it turns out to be corrupt. Returns whether an intact copy is on disk.
+    async fn verify_written_file(
+        &self,
+        stream: &EncodedStream,
+        path: &Path,
+        format: Format,
+        pb: &ProgressBar,
+    ) -> bool {
+        const MAX_ATTEMPTS: usize = 3;
+        for attempt in 1..=MAX_ATTEMPTS {
+            pb.set_message(self.fit(&format!("Verifying {}", path.display())));
+            let verify_path = path.to_path_buf();
+            let result =
+                tokio::task::spawn_blocking(move || verify::verify_file(verify_path, format))
+                    .await
+                    .map_err(anyhow::Error::from)
+                    .and_then(|result| result);
+            match result {
+                Ok(()) => return true,
+                Err(err) => {
+                    tracing::warn!(
+                        error = %err,
+                        "Verification of {} failed (attempt {} of {})",
+                        path.display(),
+                        attempt,
+                        MAX_ATTEMPTS
+                    );
+                    if attempt < MAX_ATTEMPTS
+                        && let Err(err) = stream.write_to_file(path).await
+                    {
+                        tracing::warn!(error = %err, "Failed to write {} again", path.display());
+                    }
+                }
+            }
+        }
+        false
+    }
+
     fn add_progress_bar(&self, track: &TrackMetadata, label: &str) -> ProgressBar {
         let pb = self
             .progress_bar
@@ -190,20 +1491,29 @@ impl Downloader {
             .unwrap()
             .with_key("eta", |state: &ProgressState, w: &mut dyn Write| write!(w, "{:.1}s", state.eta().as_secs_f64()).unwrap())
             .progress_chars("#>-") );
-        pb.set_message(label.to_string());
+        pb.set_message(self.fit(label));
         pb
     }
 
+    /// A progress bar message fit into `--label-width`.
+    fn fit(&self, message: &str) -> String {
+        fit_label(message, self.label_width)
+    }
+
     async fn buffer_track(
         &self,
         mut rx: StreamEventChannel,
         pb: &ProgressBar,
         label: &str,
+        track_timeout: Duration,
+        stall_timeout: Duration,
+        retries: &mut usize,
     ) -> Result<Option<Samples>> {
         let mut samples = Vec::<i32>::new();
-        let timeout_duration = Duration::from_secs(30);
+        let deadline = Instant::now() + track_timeout;
         loop {
-            match timeout(timeout_duration, rx.recv()).await {
+            let wait_until = deadline.min(Instant::now() + stall_timeout);
+            match timeout_at(wait_until, rx.recv()).await {
                 Ok(Some(event)) => match event {
                     StreamEvent::Write {
                         bytes,
@@ -226,23 +1536,28 @@ impl Downloader {
                         attempt,
                         max_attempts,
                     } => {
+                        *retries += 1;
                         tracing::warn!(
                             "Retrying download, attempt {} of {}: {}",
                             attempt,
                             max_attempts,
                             label
                         );
-                        pb.set_message(format!(
+                        pb.set_message(self.fit(&format!(
                             "Retrying ({}/{}) {}",
                             attempt,
                             max_attempts,
                             label
-                        ));
+                        )));
                     }
                 },
                 Ok(None) => break,
+                Err(_) if Instant::now() >= deadline => {
+                    tracing::warn!("Not finished within {:?}: {}", track_timeout, label);
+                    return Ok(None);
+                }
                 Err(_) => {
-                    println!("Song download timed out. Skipping.");
+                    tracing::warn!("No data received for {:?}: {}", stall_timeout, label);
                     return Ok(None);
                 }
             }
@@ -254,39 +1569,289 @@ impl Downloader {
         }))
     }
 
+    /// Takes `bytes` of the `budget` for a stream, unless that would
+    /// exceed it.
+    fn reserve_data(&self, bytes: u64, budget: u64) -> bool {
+        self.data_used
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used| {
+                (used + bytes <= budget).then_some(used + bytes)
+            })
+            .is_ok()
+    }
+
+    fn defer(&self, name: &str) -> TrackOutcome {
+        tracing::info!("Data budget used up, leaving {} for the next run", name);
+        self.summary.lock().unwrap().deferred += 1;
+        TrackOutcome::Deferred
+    }
+
+    fn skip(
+        &self,
+        track: &SpotifyUri,
+        reason: SkipReason,
+        detail: impl fmt::Display,
+    ) -> TrackOutcome {
+        println!("Skipped ({}): {}", reason, detail);
+        self.summary.lock().unwrap().record_skip(reason, 1);
+        self.skip_reasons
+            .lock()
+            .unwrap()
+            .insert(track.clone(), reason);
+        TrackOutcome::Skipped
+    }
+
+    /// Why the track was skipped in this run, taken out of the record.
+    fn take_skip_reason(&self, track: &SpotifyUri) -> Option<SkipReason> {
+        self.skip_reasons.lock().unwrap().remove(track)
+    }
+
     async fn should_skip_track(&self, track: &Track) -> bool {
-        if let Some(history_handle) = &self.history {
-            if let Some(playlist) = track.playlist() {
-                let history = history_handle.lock().await;
-                return history.has_downloaded(&playlist, &track.id);
-            }
+        if let Some(history_handle) = &self.history
+            && let Some(playlist) = track.playlist()
+        {
+            let history = history_handle.lock().await;
+            return history.has_downloaded(&playlist, &track.id);
         }
         false
     }
 
-    async fn mark_downloaded(&self, track: &Track) {
-        if let Some(history_handle) = &self.history {
-            if let Some(playlist) = track.playlist() {
-                let mut history = history_handle.lock().await;
-                if let Err(err) = history.record_download(&playlist, &track.id) {
-                    tracing::warn!(error = %err, "Failed to record download history");
-                }
+    /// The modification time `--file-mtime` asks for, or `None` to leave
+    /// the file's own, also when the date isn't known.
+    async fn file_mtime(
+        &self,
+        track: &Track,
+        metadata: &TrackMetadata,
+        options: &DownloadOptions,
+    ) -> Option<SystemTime> {
+        match options.file_mtime? {
+            FileMtime::ReleaseDate => metadata.album.released,
+            FileMtime::AddedDate => self.added_date(track).await,
+            FileMtime::Now => Some(SystemTime::now()),
+        }
+    }
+
+    async fn added_date(&self, track: &Track) -> Option<SystemTime> {
+        let playlist = track.playlist()?;
+        let mut added_dates = self.added_dates.lock().await;
+        let dates = match added_dates.get(&playlist) {
+            Some(dates) => Arc::clone(dates),
+            None => {
+                let dates = Playlist::from_id(playlist.clone())
+                    .added_dates(&self.session)
+                    .await
+                    .unwrap_or_else(|err| {
+                        tracing::warn!(error = %err, "Failed to get the added dates of {:?}", playlist);
+                        HashMap::new()
+                    });
+                let dates = Arc::new(dates);
+                added_dates.insert(playlist, Arc::clone(&dates));
+                dates
+            }
+        };
+        dates.get(&track.id).copied()
+    }
+
+    async fn playlist_position(&self, track: &Track) -> Option<usize> {
+        let history = self.history.as_ref()?.lock().await;
+        history.position(&track.playlist()?, &track.id)
+    }
+
+    /// Records the track as downloaded to `files` for its playlist.
+    async fn mark_downloaded(&self, track: &Track, files: &[PathBuf]) {
+        self.files
+            .lock()
+            .unwrap()
+            .entry(track.id.clone())
+            .or_default()
+            .extend(files.iter().cloned());
+        if let Some(history_handle) = &self.history
+            && let Some(playlist) = track.playlist()
+        {
+            let mut history = history_handle.lock().await;
+            let recorded = history
+                .record_download(&playlist, &track.id)
+                .and_then(|()| {
+                    files
+                        .iter()
+                        .try_for_each(|file| history.record_file(&playlist, &track.id, file))
+                });
+            if let Err(err) = recorded {
+                tracing::warn!(error = %err, "Failed to record download history");
+                self.log_error(Level::Warning, &track.id, None, Phase::Record, 0, &err);
             }
         }
     }
 
-    fn fail_with_error<S>(&self, pb: &ProgressBar, name: &str, e: S)
-    where
-        S: Into<String>,
-    {
-        tracing::error!("Failed to download {}: {}", name, e.into());
+    /// The files the track was downloaded to in this run, or found at.
+    fn files_of(&self, track: &SpotifyUri) -> Vec<PathBuf> {
+        self.files
+            .lock()
+            .unwrap()
+            .get(track)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    #[cfg(feature = "sqlite")]
+    async fn record_in_database(&self, file: &StagedFile, path: &str, options: &DownloadOptions) {
+        let Some(database) = &self.database else {
+            return;
+        };
+        let track = &file.track;
+        let Ok(uri) = track.id.to_uri() else {
+            return;
+        };
+        let playlist = track.playlist().and_then(|playlist| playlist.to_uri().ok());
+        // A lossy file holds no more than was streamed into it, a lossless
+        // one exactly that.
+        let bitrate = match (options.format.bitrate(), file.streamed_bitrate) {
+            (Some(encoded), Some(streamed)) => Some(encoded.min(streamed)),
+            (encoded, streamed) => streamed.or(encoded),
+        };
+        if let Err(err) = database.lock().await.record(
+            &uri,
+            path,
+            options.format.extension(),
+            bitrate,
+            playlist.as_deref(),
+        ) {
+            tracing::warn!(error = %err, "Failed to record download in database");
+            self.log_error(Level::Warning, &track.id, None, Phase::Record, 0, &err);
+        }
+    }
+
+    /// The track's metadata, from the simulation when there is one.
+    async fn track_metadata(&self, track: &Track) -> Result<TrackMetadata> {
+        #[cfg(feature = "simulate")]
+        if let Some(simulation) = &self.simulation {
+            return Ok(simulation.metadata(track));
+        }
+        track.metadata(&self.session).await
+    }
+
+    /// A stream of the session, or of the simulation when there is one.
+    fn new_stream(&self, options: &DownloadOptions) -> Stream {
+        #[cfg(feature = "simulate")]
+        if let Some(simulation) = &self.simulation {
+            return Stream::simulated(self.session.clone(), simulation.clone());
+        }
+        Stream::new(self.session.clone()).with_retry_backoff(options.retry_backoff)
+    }
+
+    /// Waits until a track may start without going over `per_minute`
+    /// starts, evenly spaced.
+    async fn wait_for_start(&self, per_minute: u32) {
+        let mut last_start = self.last_start.lock().await;
+        let spacing = Duration::from_secs(60) / per_minute.max(1);
+        if let Some(last) = *last_start {
+            tokio::time::sleep_until(last + spacing).await;
+        }
+        *last_start = Some(Instant::now());
+    }
+
+    /// Looks up which bitrate the track can be streamed at before streaming
+    /// it, so a free account or a track without high quality files gets a
+    /// clear message rather than an opaque stream error. Below the requested
+    /// bitrate the best file available is downloaded, unless
+    /// `require_quality` is set. Returns the bitrate, if it could be looked up.
+    async fn check_quality(
+        &self,
+        track: &Track,
+        name: &str,
+        options: &DownloadOptions,
+    ) -> Result<Option<u32>> {
+        #[cfg(feature = "simulate")]
+        if self.simulation.is_some() {
+            return Ok(None);
+        }
+        match track.streamed_bitrate(&self.session).await {
+            Ok(Some(bitrate)) if bitrate >= REQUESTED_BITRATE => Ok(Some(bitrate)),
+            Ok(Some(bitrate)) if options.require_quality => Err(anyhow::anyhow!(
+                "Only available at {} kbps, {} kbps is required",
+                bitrate,
+                REQUESTED_BITRATE
+            )),
+            Ok(Some(bitrate)) => {
+                tracing::warn!("{} is only available at {} kbps", name, bitrate);
+                self.progress_bar.println(format!(
+                    "{} is only available at {} kbps, downloading that",
+                    name, bitrate
+                ))?;
+                Ok(Some(bitrate))
+            }
+            Ok(None) => Err(anyhow::anyhow!(
+                "Not available in any format that can be streamed, \
+                 the account may need Premium or the track is restricted"
+            )),
+            Err(err) => {
+                tracing::warn!(error = %err, "Could not check the bitrate of {}", name);
+                Ok(None)
+            }
+        }
+    }
+
+    fn fail_with_error(
+        &self,
+        pb: &ProgressBar,
+        metadata: &TrackMetadata,
+        name: &str,
+        phase: Phase,
+        retries: usize,
+        e: anyhow::Error,
+    ) {
+        tracing::error!("Failed to download {}: {}", name, e);
+        self.log_error(Level::Error, &metadata.id, Some(name), phase, retries, &e);
+        self.summary
+            .lock()
+            .unwrap()
+            .failed
+            .push(TrackReport::new(metadata, Some(e.to_string())));
+        self.finish_failed(pb, name);
+    }
+
+    fn finish_failed(&self, pb: &ProgressBar, name: &str) {
         pb.finish_with_message(
-            console::style(format!("Failed! {}", name))
+            console::style(self.fit(&format!("Failed! {}", name)))
+                .for_stderr()
                 .red()
                 .to_string(),
         );
     }
 
+    fn log_error(
+        &self,
+        level: Level,
+        track: &SpotifyUri,
+        name: Option<&str>,
+        phase: Phase,
+        retries: usize,
+        err: &anyhow::Error,
+    ) {
+        if let Some(error_log) = &self.error_log {
+            error_log.record(level, track, name, phase, retries, err);
+        }
+    }
+
+    /// Records a failed track and goes on with the queue under
+    /// `--ignore-errors`, or aborts the whole run otherwise.
+    fn fail_or_abort(
+        &self,
+        pb: &ProgressBar,
+        metadata: &TrackMetadata,
+        name: &str,
+        phase: Phase,
+        err: anyhow::Error,
+        options: &DownloadOptions,
+    ) -> Result<TrackOutcome> {
+        if options.ignore_errors {
+            self.fail_with_error(pb, metadata, name, phase, 0, err);
+            return Ok(TrackOutcome::Failed);
+        }
+        self.log_error(Level::Error, &metadata.id, Some(name), phase, 0, &err);
+        self.finish_failed(pb, name);
+        Err(err)
+    }
+
     fn get_file_name(&self, metadata: &TrackMetadata) -> String {
         if metadata.artists.len() > 3 {
             let artists_name = metadata
@@ -311,6 +1876,94 @@ impl Downloader {
         self.clean_file_name(format!("{} - {}", artists_name, metadata.track_name))
     }
 
+    /// `position` is the track's number in its playlist, `{position}` is left
+    /// empty for tracks that weren't requested through one.
+    fn render_template(
+        &self,
+        template: &str,
+        metadata: &TrackMetadata,
+        position: Option<usize>,
+    ) -> String {
+        let artists = metadata
+            .artists
+            .iter()
+            .map(|artist| artist.name.clone())
+            .collect::<Vec<String>>();
+        // Only albums with several discs get a folder per disc.
+        let disc_folder = if metadata.album.disc_count > 1 {
+            format!("Disc {}", metadata.disc_number)
+        } else {
+            String::new()
+        };
+        let position = position.map_or_else(String::new, |position| format!("{:03}", position));
+        template
+            .split('/')
+            .map(|segment| {
+                self.clean_file_name(
+                    segment
+                        .replace("{artists}", &artists.join(", "))
+                        .replace("{artist}", artists.first().map_or("", String::as_str))
+                        .replace("{title}", &metadata.track_name)
+                        .replace("{album}", &metadata.album.name)
+                        .replace("{track}", &format!("{:02}", metadata.track_number))
+                        .replace("{discs}", &metadata.album.disc_count.to_string())
+                        .replace("{disc_folder}", &disc_folder)
+                        .replace("{disc}", &metadata.disc_number.to_string())
+                        .replace("{position}", &position),
+                )
+            })
+            .filter(|segment| !segment.trim().is_empty())
+            .collect::<Vec<String>>()
+            .join("/")
+    }
+
+    /// Whether a file already in place is worth keeping: big enough to hold
+    /// audio and, where its length can be read, within `DURATION_TOLERANCE`
+    /// of the track's. `None` when the file could not be read or its length
+    /// not parsed, which is no reason to replace it.
+    async fn existing_file_intact(path: &Path, metadata: &TrackMetadata) -> Option<bool> {
+        match tokio::fs::metadata(path).await {
+            Ok(file) if file.len() < MIN_EXISTING_SIZE => return Some(false),
+            Ok(_) => {}
+            Err(_) => return None,
+        }
+        let Some(format) = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .and_then(Format::from_extension)
+        else {
+            return Some(true);
+        };
+        if metadata.duration <= 0 {
+            return Some(true);
+        }
+
+        let expected = Duration::from_millis(metadata.duration as u64);
+        let path = path.to_path_buf();
+        let actual =
+            tokio::task::spawn_blocking(move || verify::audio_duration(path, format)).await;
+        match actual {
+            Ok(Ok(Some(actual))) => Some(actual.abs_diff(expected) <= DURATION_TOLERANCE),
+            Ok(Ok(None)) => Some(true),
+            _ => None,
+        }
+    }
+
+    /// Fails a stream that ended without an error but more than
+    /// `DURATION_TOLERANCE` short of the track's length.
+    fn check_streamed_length(samples: &Samples, expected: Duration) -> Result<()> {
+        let frames = samples.samples.len() / samples.channels.max(1) as usize;
+        let streamed = Duration::from_secs_f64(frames as f64 / samples.sample_rate.max(1) as f64);
+        if expected.saturating_sub(streamed) > DURATION_TOLERANCE {
+            return Err(anyhow::anyhow!(
+                "the stream ended after {}s of {}s",
+                streamed.as_secs(),
+                expected.as_secs()
+            ));
+        }
+        Ok(())
+    }
+
     fn legacy_file_name(&self, metadata: &TrackMetadata) -> Option<String> {
         if metadata.artists.len() > 3 {
             let artists_name = metadata
@@ -342,3 +1995,114 @@ impl Downloader {
         clean
     }
 }
+
+impl TrackReport {
+    fn new(metadata: &TrackMetadata, error: Option<String>) -> Self {
+        TrackReport {
+            uri: metadata.id.to_uri().unwrap_or_default(),
+            name: metadata.to_string(),
+            album: Some(metadata.album.name.clone()),
+            cover_url: metadata
+                .album
+                .cover
+                .as_ref()
+                .and_then(|cover| cover.id.to_base16().ok())
+                .map(|id| format!("https://i.scdn.co/image/{}", id)),
+            error,
+        }
+    }
+
+    /// A track whose metadata couldn't be loaded, named by its URI.
+    fn unavailable(id: &SpotifyUri, error: String) -> Self {
+        let uri = id.to_uri().unwrap_or_default();
+        TrackReport {
+            name: uri.clone(),
+            uri,
+            album: None,
+            cover_url: None,
+            error: Some(error),
+        }
+    }
+}
+
+/// Marks an output path as being written for as long as it is alive, so two
+/// tasks resolving to the same file never write it at the same time.
+struct PathClaim<'a> {
+    in_flight: &'a std::sync::Mutex<HashSet<PathBuf>>,
+    path: PathBuf,
+}
+
+impl<'a> PathClaim<'a> {
+    fn new(
+        in_flight: &'a std::sync::Mutex<HashSet<PathBuf>>,
+        path: &std::path::Path,
+    ) -> Option<Self> {
+        let mut paths = in_flight.lock().unwrap();
+        if !paths.insert(path.to_path_buf()) {
+            return None;
+        }
+        Some(PathClaim {
+            in_flight,
+            path: path.to_path_buf(),
+        })
+    }
+}
+
+impl Drop for PathClaim<'_> {
+    fn drop(&mut self) {
+        self.in_flight.lock().unwrap().remove(&self.path);
+    }
+}
+
+/// Removes the `.part` files of a download and stops its progress bar when
+/// the download ends without handing them on: on a failure, an early return
+/// or when an aborted run drops it half way.
+struct PartGuard {
+    parts: Vec<PathBuf>,
+    pb: Option<ProgressBar>,
+    /// Shown on the progress bar when the download is dropped.
+    cancelled: String,
+}
+
+impl PartGuard {
+    fn new(pb: &ProgressBar, cancelled: String) -> Self {
+        PartGuard {
+            parts: Vec::new(),
+            pb: Some(pb.clone()),
+            cancelled,
+        }
+    }
+
+    /// Removes `part` unless it is released.
+    fn watch(&mut self, part: &Path) {
+        self.parts.push(part.to_path_buf());
+    }
+
+    /// Leaves `part` alone, once it was moved into place or staged.
+    fn release(&mut self, part: &Path) {
+        self.parts.retain(|watched| watched != part);
+    }
+
+    /// Leaves all files and the progress bar to the finished download.
+    fn done(mut self) {
+        self.parts.clear();
+        self.pb = None;
+    }
+}
+
+impl Drop for PartGuard {
+    fn drop(&mut self) {
+        for part in &self.parts {
+            if let Err(err) = std::fs::remove_file(part)
+                && err.kind() != std::io::ErrorKind::NotFound
+            {
+                tracing::warn!(error = %err, "Could not remove {}", part.display());
+            }
+        }
+        if let Some(pb) = &self.pb
+            && !pb.is_finished()
+        {
+            pb.abandon_with_message(std::mem::take(&mut self.cancelled));
+        }
+    }
+}
@@ -0,0 +1,100 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use futures::StreamExt;
+use librespot::core::SpotifyUri;
+use librespot::core::session::Session;
+
+use crate::track::{CONCURRENT_METADATA_REQUESTS, Playlist, Track, get_tracks};
+
+/// A playlist's name and the tracks in it, in playlist order.
+pub struct PlaylistTracks {
+    pub name: String,
+    pub tracks: Vec<SpotifyUri>,
+}
+
+impl PlaylistTracks {
+    pub async fn load(session: &Session, playlist: &str) -> Result<Self> {
+        let name = Playlist::new(playlist)?.name(session).await?;
+        let tracks = get_tracks(vec![playlist.to_string()], session)
+            .await?
+            .into_iter()
+            .map(|track| track.id)
+            .collect();
+        Ok(PlaylistTracks { name, tracks })
+    }
+}
+
+/// A track that is in more than one of the compared playlists.
+pub struct SharedTrack {
+    pub id: SpotifyUri,
+    /// Indices of the playlists the track is in.
+    pub playlists: Vec<usize>,
+    /// `Artists - Title`, once loaded.
+    pub name: Option<String>,
+}
+
+/// How some playlists overlap, from their track lists alone.
+pub struct DupesReport {
+    /// Every track of the playlists once, in order of first appearance.
+    pub tracks: Vec<SpotifyUri>,
+    pub shared: Vec<SharedTrack>,
+}
+
+impl DupesReport {
+    pub fn of(playlists: &[PlaylistTracks]) -> Self {
+        let mut tracks = Vec::new();
+        let mut found_in: HashMap<&SpotifyUri, Vec<usize>> = HashMap::new();
+        for (index, playlist) in playlists.iter().enumerate() {
+            for track in &playlist.tracks {
+                let indices = found_in.entry(track).or_insert_with(|| {
+                    tracks.push(track.clone());
+                    Vec::new()
+                });
+                if !indices.contains(&index) {
+                    indices.push(index);
+                }
+            }
+        }
+        let shared = tracks
+            .iter()
+            .filter(|track| found_in[track].len() > 1)
+            .map(|track| SharedTrack {
+                id: track.clone(),
+                playlists: found_in[track].clone(),
+                name: None,
+            })
+            .collect();
+        DupesReport { tracks, shared }
+    }
+
+    /// Fetches the names of the shared tracks. Tracks whose metadata can't
+    /// be fetched keep no name and are shown by URI.
+    pub async fn load_names(&mut self, session: &Session) {
+        let names: Vec<Option<String>> = futures::stream::iter(&self.shared)
+            .map(|track| async move {
+                let metadata = Track::from_id(track.id.clone()).metadata(session).await;
+                match metadata {
+                    Ok(metadata) => {
+                        let artists = metadata
+                            .artists
+                            .iter()
+                            .map(|artist| artist.name.as_str())
+                            .collect::<Vec<_>>()
+                            .join(", ");
+                        Some(format!("{} - {}", artists, metadata.track_name))
+                    }
+                    Err(err) => {
+                        tracing::warn!(error = %err, "Could not load the name of {}", track.id);
+                        None
+                    }
+                }
+            })
+            .buffered(CONCURRENT_METADATA_REQUESTS)
+            .collect()
+            .await;
+        for (track, name) in self.shared.iter_mut().zip(names) {
+            track.name = name;
+        }
+    }
+}
@@ -1,18 +1,99 @@
 use flacenc::bitsink::ByteSink;
-use flacenc::component::BitRepr;
-use flacenc::error::Verify;
+use flacenc::component::{BitRepr, Stream, StreamInfo};
+use flacenc::error::{Verified, Verify};
+use flacenc::source::{FrameBuf, MemSource, Seekable, Source};
+use md5::{Digest, Md5};
 
 use super::EncodedStream;
 use super::Encoder;
 use super::Samples;
+use super::StreamEncoder;
+
+/// Offset of the minimum block size in STREAMINFO, after the `fLaC` marker
+/// and the metadata block header.
+const MIN_BLOCK_SIZE_OFFSET: usize = 8;
+/// Offset of the minimum and maximum frame sizes in STREAMINFO, three bytes
+/// each.
+const FRAME_SIZES_OFFSET: usize = 12;
 
 #[derive(Debug)]
 pub struct FlacEncoder;
 
+impl FlacEncoder {
+    /// Encodes with a fixed block size like `flacenc::encode_with_fixed_block_size`,
+    /// except that the last frame only holds the samples that are left. flacenc
+    /// fills it up to the full block size, which decoders play back as silence
+    /// at the end of the track.
+    fn encode_stream(
+        config: &Verified<flacenc::config::Encoder>,
+        mut source: MemSource,
+    ) -> anyhow::Result<Vec<u8>> {
+        let block_size = config.block_size;
+        let channels = source.channels();
+        let total_samples = source.len();
+
+        // The signature covers exactly the samples in the stream, without
+        // the zeros flacenc would add for a full last block.
+        let bytes_per_sample = source.bits_per_sample().div_ceil(8);
+        let mut md5 = Md5::new();
+        for sample in source.as_slice() {
+            md5.update(&sample.to_le_bytes()[..bytes_per_sample]);
+        }
+
+        let mut stream_info =
+            StreamInfo::new(source.sample_rate(), channels, source.bits_per_sample())
+                .map_err(|e| anyhow::anyhow!("Failed to create flac stream: {:?}", e))?;
+        stream_info.set_md5_digest(&md5.finalize().into());
+        // Adding the frames sums up their lengths into the total sample count.
+        let mut stream = Stream::with_stream_info(stream_info);
+        let mut framebuf = FrameBuf::with_size(channels, block_size)
+            .map_err(|e| anyhow::anyhow!("Failed to create flac frame buffer: {:?}", e))?;
+
+        let mut encoded_samples = 0;
+        let mut frame_number = 0;
+        while encoded_samples < total_samples {
+            let frame_size = block_size.min(total_samples - encoded_samples);
+            if frame_size != framebuf.size() {
+                framebuf.resize(frame_size);
+            }
+            let read = source
+                .read_samples(frame_size, &mut framebuf)
+                .map_err(|e| anyhow::anyhow!("Failed to read samples: {:?}", e))?;
+            if read == 0 {
+                break;
+            }
+            let frame = flacenc::encode_fixed_size_frame(
+                config,
+                &framebuf,
+                frame_number,
+                stream.stream_info(),
+            )
+            .map_err(|e| anyhow::anyhow!("Failed to encode flac: {:?}", e))?;
+            stream.add_frame(frame);
+            encoded_samples += read;
+            frame_number += 1;
+        }
+
+        let mut byte_sink = ByteSink::new();
+        stream
+            .write(&mut byte_sink)
+            .map_err(|e| anyhow::anyhow!("Failed to write flac stream: {:?}", e))?;
+
+        // The shorter last frame lowered the minimum block size, but that
+        // field excludes the last block. Declaring the regular block size
+        // keeps this a fixed block size stream.
+        let mut bytes = byte_sink.into_inner();
+        let max_block_size = stream.stream_info().max_block_size() as u16;
+        bytes[MIN_BLOCK_SIZE_OFFSET..MIN_BLOCK_SIZE_OFFSET + 2]
+            .copy_from_slice(&max_block_size.to_be_bytes());
+        Ok(bytes)
+    }
+}
+
 #[async_trait::async_trait]
 impl Encoder for FlacEncoder {
     async fn encode(&self, samples: Samples) -> anyhow::Result<EncodedStream> {
-        let source = flacenc::source::MemSource::from_samples(
+        let source = MemSource::from_samples(
             &samples.to_s24(),
             samples.channels as usize,
             flacenc::constant::MAX_BITS_PER_SAMPLE,
@@ -25,19 +106,117 @@ impl Encoder for FlacEncoder {
 
         let byte_sink: Vec<u8> =
             tokio::task::spawn_blocking(move || -> Result<Vec<u8>, anyhow::Error> {
-                let flac_stream =
-                    flacenc::encode_with_fixed_block_size(&config, source, config.block_size)
-                        .map_err(|e| anyhow::anyhow!("Failed to encode flac: {:?}", e))?;
-
-                let mut byte_sink = ByteSink::new();
-                flac_stream
-                    .write(&mut byte_sink)
-                    .map_err(|e| anyhow::anyhow!("Failed to write flac stream: {:?}", e))?;
-
-                Ok(byte_sink.into_inner())
+                Self::encode_stream(&config, source)
             })
             .await??;
 
         Ok(EncodedStream::new(byte_sink))
     }
 }
+
+/// Encodes FLAC frames as samples come in, a block at a time. The stream
+/// header goes out with the first frame, before the length of the track is
+/// known, so it declares the total sample count, the frame sizes and the MD5
+/// signature as unknown, as the format allows for streams.
+pub struct FlacStreamEncoder {
+    config: Verified<flacenc::config::Encoder>,
+    stream_info: StreamInfo,
+    framebuf: FrameBuf,
+    /// 24 bit samples that don't fill a block yet.
+    pending: Vec<i32>,
+    frame_number: usize,
+    header_written: bool,
+}
+
+impl FlacStreamEncoder {
+    pub fn new(sample_rate: u32, channels: u32) -> anyhow::Result<Self> {
+        let config = flacenc::config::Encoder::default()
+            .into_verified()
+            .map_err(|e| anyhow::anyhow!("Failed to verify encoder config: {:?}", e))?;
+        let stream_info = StreamInfo::new(
+            sample_rate as usize,
+            channels as usize,
+            flacenc::constant::MAX_BITS_PER_SAMPLE,
+        )
+        .map_err(|e| anyhow::anyhow!("Failed to create flac stream: {:?}", e))?;
+        let framebuf = FrameBuf::with_size(channels as usize, config.block_size)
+            .map_err(|e| anyhow::anyhow!("Failed to create flac frame buffer: {:?}", e))?;
+        Ok(FlacStreamEncoder {
+            config,
+            stream_info,
+            framebuf,
+            pending: Vec::new(),
+            frame_number: 0,
+            header_written: false,
+        })
+    }
+
+    /// Encodes the first `frame_size` samples per channel of `pending`.
+    fn encode_frame(&mut self, frame_size: usize, out: &mut Vec<u8>) -> anyhow::Result<()> {
+        let channels = self.stream_info.channels();
+        let block: Vec<i32> = self.pending.drain(..frame_size * channels).collect();
+        let mut source = MemSource::from_samples(
+            &block,
+            channels,
+            self.stream_info.bits_per_sample(),
+            self.stream_info.sample_rate(),
+        );
+        if frame_size != self.framebuf.size() {
+            self.framebuf.resize(frame_size);
+        }
+        source
+            .read_samples(frame_size, &mut self.framebuf)
+            .map_err(|e| anyhow::anyhow!("Failed to read samples: {:?}", e))?;
+        let frame = flacenc::encode_fixed_size_frame(
+            &self.config,
+            &self.framebuf,
+            self.frame_number,
+            &self.stream_info,
+        )
+        .map_err(|e| anyhow::anyhow!("Failed to encode flac: {:?}", e))?;
+        self.frame_number += 1;
+
+        if !self.header_written {
+            // The first frame sets the block sizes, the rest stays unknown.
+            let mut stream_info = self.stream_info.clone();
+            stream_info.update_frame_info(&frame);
+            stream_info.set_total_samples(0);
+            let mut byte_sink = ByteSink::new();
+            Stream::with_stream_info(stream_info)
+                .write(&mut byte_sink)
+                .map_err(|e| anyhow::anyhow!("Failed to write flac stream: {:?}", e))?;
+            let mut header = byte_sink.into_inner();
+            header[FRAME_SIZES_OFFSET..FRAME_SIZES_OFFSET + 6].fill(0);
+            out.extend_from_slice(&header);
+            self.header_written = true;
+        }
+        let mut byte_sink = ByteSink::new();
+        frame
+            .write(&mut byte_sink)
+            .map_err(|e| anyhow::anyhow!("Failed to write flac frame: {:?}", e))?;
+        out.extend_from_slice(&byte_sink.into_inner());
+        Ok(())
+    }
+}
+
+impl StreamEncoder for FlacStreamEncoder {
+    fn encode(&mut self, samples: &[i32]) -> anyhow::Result<Vec<u8>> {
+        self.pending
+            .extend(samples.iter().map(|&sample| sample >> 8));
+        let block_len = self.config.block_size * self.stream_info.channels();
+        let mut out = Vec::new();
+        while self.pending.len() >= block_len {
+            self.encode_frame(self.config.block_size, &mut out)?;
+        }
+        Ok(out)
+    }
+
+    fn finish(&mut self) -> anyhow::Result<Vec<u8>> {
+        let mut out = Vec::new();
+        let frame_size = self.pending.len() / self.stream_info.channels();
+        if frame_size > 0 {
+            self.encode_frame(frame_size, &mut out)?;
+        }
+        Ok(out)
+    }
+}
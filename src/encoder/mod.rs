@@ -1,20 +1,35 @@
 mod flac;
 #[cfg(feature = "mp3")]
 mod mp3;
+mod registry;
 pub mod tags;
+pub mod verify;
+
+pub use self::registry::{FormatPlugin, PluginFormat, register_format, registered_formats};
+#[cfg(feature = "plugins")]
+pub use self::registry::{load_plugins, plugin_dir};
 
 use anyhow::Result;
 use std::{path::Path, str::FromStr};
 
-use self::{flac::FlacEncoder, mp3::Mp3Encoder};
+use self::{flac::FlacEncoder, flac::FlacStreamEncoder, mp3::Mp3Encoder, mp3::Mp3StreamEncoder};
 
 #[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
 pub enum Format {
     Flac,
     #[cfg(feature = "mp3")]
     Mp3,
+    /// A format added with `register_format`.
+    Plugin(PluginFormat),
 }
 
+/// The formats spotify-dl implements itself.
+pub const BUILT_IN_FORMATS: &[Format] = &[
+    #[cfg(feature = "mp3")]
+    Format::Mp3,
+    Format::Flac,
+];
+
 impl FromStr for Format {
     type Err = anyhow::Error;
 
@@ -23,17 +38,51 @@ impl FromStr for Format {
             "flac" => Ok(Format::Flac),
             #[cfg(feature = "mp3")]
             "mp3" => Ok(Format::Mp3),
-            _ => Err(anyhow::anyhow!("Unsupported format")),
+            _ => registry::find(|plugin| plugin.name() == s)
+                .ok_or(anyhow::anyhow!("Unsupported format")),
         }
     }
 }
 
 impl Format {
+    /// The name `--format` takes.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Format::Plugin(format) => format.plugin().name(),
+            _ => self.extension(),
+        }
+    }
+
     pub fn extension(&self) -> &'static str {
         match self {
             Format::Flac => "flac",
             #[cfg(feature = "mp3")]
             Format::Mp3 => "mp3",
+            Format::Plugin(format) => format.plugin().extension(),
+        }
+    }
+
+    /// The bitrate files are encoded at in kbps, `None` for lossless formats
+    /// and plugins that don't say.
+    pub fn bitrate(&self) -> Option<u32> {
+        match self {
+            Format::Flac => None,
+            #[cfg(feature = "mp3")]
+            Format::Mp3 => Some(mp3::BITRATE_KBPS as u32),
+            Format::Plugin(format) => format
+                .plugin()
+                .bitrate()
+                .and_then(|bitrate| u32::try_from(bitrate / 1000).ok()),
+        }
+    }
+
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        let extension = extension.to_ascii_lowercase();
+        match extension.as_str() {
+            "flac" => Some(Format::Flac),
+            #[cfg(feature = "mp3")]
+            "mp3" => Some(Format::Mp3),
+            _ => registry::find(|plugin| plugin.extension().eq_ignore_ascii_case(&extension)),
         }
     }
 }
@@ -47,6 +96,7 @@ pub fn get_encoder(format: Format) -> &'static dyn Encoder {
         Format::Flac => FLAC_ENCODER,
         #[cfg(feature = "mp3")]
         Format::Mp3 => MP3_ENCODER,
+        Format::Plugin(format) => format.plugin(),
     }
 }
 
@@ -55,6 +105,32 @@ pub trait Encoder: Sync {
     async fn encode(&self, samples: Samples) -> Result<EncodedStream>;
 }
 
+/// Encodes a track chunk by chunk while it streams. The bytes returned can
+/// be written out right away, so nothing that depends on the whole track,
+/// like the LAME tag or the FLAC MD5 signature, is written.
+pub trait StreamEncoder: Send {
+    fn encode(&mut self, samples: &[i32]) -> Result<Vec<u8>>;
+
+    /// Encodes whatever is still buffered at the end of the track.
+    fn finish(&mut self) -> Result<Vec<u8>>;
+}
+
+pub fn get_stream_encoder(
+    format: Format,
+    sample_rate: u32,
+    channels: u32,
+) -> Result<Box<dyn StreamEncoder>> {
+    match format {
+        Format::Flac => Ok(Box::new(FlacStreamEncoder::new(sample_rate, channels)?)),
+        #[cfg(feature = "mp3")]
+        Format::Mp3 => Ok(Box::new(Mp3StreamEncoder::new(sample_rate, channels)?)),
+        Format::Plugin(format) => Err(anyhow::anyhow!(
+            "The {} format can't be encoded while streaming",
+            format.plugin().name()
+        )),
+    }
+}
+
 pub struct Samples {
     pub samples: Vec<i32>,
     pub sample_rate: u32,
@@ -75,7 +151,7 @@ impl Samples {
     pub fn to_s24(&self) -> Vec<i32> {
         self.samples
             .iter()
-            .map(|&sample| (sample >> 8) as i32)
+            .map(|&sample| sample >> 8)
             .collect()
     }
 }
@@ -1,58 +1,142 @@
-use anyhow::Ok;
+use std::ffi::c_int;
+
+use anyhow::Result;
 use anyhow::anyhow;
-use mp3lame_encoder::Builder;
-use mp3lame_encoder::FlushNoGap;
-use mp3lame_encoder::InterleavedPcm;
+use mp3lame_sys as lame;
 
 use super::EncodedStream;
 use super::Encoder;
 use super::Samples;
+use super::StreamEncoder;
+
+pub(super) const BITRATE_KBPS: c_int = 320;
 
 pub struct Mp3Encoder;
 
-impl Mp3Encoder {
-    fn build_encoder(sample_rate: u32, channels: u32) -> anyhow::Result<mp3lame_encoder::Encoder> {
-        let mut builder = Builder::new().ok_or(anyhow::anyhow!("Failed to create mp3 encoder"))?;
+/// Owns a LAME encoder handle.
+///
+/// The raw bindings are used instead of a wrapper because the LAME tag has to
+/// be fetched from the encoder once everything was flushed.
+struct Lame(*mut lame::lame_global_flags);
+
+impl Lame {
+    fn new(sample_rate: u32, channels: u32, lametag: bool) -> Result<Self> {
+        let lame = unsafe { lame::lame_init() };
+        if lame.is_null() {
+            return Err(anyhow!("Failed to create mp3 encoder"));
+        }
+        let lame = Lame(lame);
+
+        check(
+            unsafe { lame::lame_set_in_samplerate(lame.0, sample_rate as c_int) },
+            "Failed to set sample rate for mp3 encoder",
+        )?;
+        check(
+            unsafe { lame::lame_set_num_channels(lame.0, channels as c_int) },
+            "Failed to set number of channels for mp3 encoder",
+        )?;
+        check(
+            unsafe { lame::lame_set_brate(lame.0, BITRATE_KBPS) },
+            "Failed to set bitrate for mp3 encoder",
+        )?;
+        // Reserves the first frame for the Info/LAME tag, which records the
+        // encoder delay and padding so players can trim them for gapless
+        // playback.
+        check(
+            unsafe { lame::lame_set_bWriteVbrTag(lame.0, lametag as c_int) },
+            "Failed to enable the LAME tag",
+        )?;
+        check(
+            unsafe { lame::lame_init_params(lame.0) },
+            "Failed to build mp3 encoder",
+        )?;
+        Ok(lame)
+    }
+
+    fn encode(&mut self, samples: &[i32], channels: usize, out: &mut Vec<u8>) -> Result<()> {
+        let frames = samples.len() / channels;
+        out.reserve(max_required_buffer_size(frames));
+        let encoded = unsafe {
+            lame::lame_encode_buffer_interleaved_int(
+                self.0,
+                samples.as_ptr(),
+                frames as c_int,
+                out.spare_capacity_mut().as_mut_ptr().cast(),
+                out.spare_capacity_mut().len().min(c_int::MAX as usize) as c_int,
+            )
+        };
+        let encoded = check(encoded, "Failed to encode mp3")?;
+        unsafe { out.set_len(out.len() + encoded as usize) };
+        Ok(())
+    }
+
+    /// Pads the last frame and writes out everything still buffered. Unlike
+    /// a no-gap flush this ends the stream, so the padding is known.
+    fn flush(&mut self, out: &mut Vec<u8>) -> Result<()> {
+        out.reserve(7200);
+        let encoded = unsafe {
+            lame::lame_encode_flush(
+                self.0,
+                out.spare_capacity_mut().as_mut_ptr().cast(),
+                out.spare_capacity_mut().len().min(c_int::MAX as usize) as c_int,
+            )
+        };
+        let encoded = check(encoded, "Failed to flush mp3 encoder")?;
+        unsafe { out.set_len(out.len() + encoded as usize) };
+        Ok(())
+    }
+
+    /// Overwrites the frame reserved at the start of `out` with the
+    /// completed Info/LAME tag.
+    fn write_lametag(&self, out: &mut [u8]) -> Result<()> {
+        let size = unsafe { lame::lame_get_lametag_frame(self.0, std::ptr::null_mut(), 0) };
+        if size == 0 || size > out.len() {
+            return Err(anyhow!("Failed to write the LAME tag"));
+        }
+        let written = unsafe { lame::lame_get_lametag_frame(self.0, out.as_mut_ptr(), size) };
+        if written != size {
+            return Err(anyhow!("Failed to write the LAME tag"));
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Lame {
+    fn drop(&mut self) {
+        unsafe { lame::lame_close(self.0) };
+    }
+}
 
-        builder
-            .set_sample_rate(sample_rate)
-            .map_err(|e| anyhow::anyhow!("Failed to set sample rate for mp3 encoder: {}", e))?;
-        builder.set_num_channels(channels as u8).map_err(|e| {
-            anyhow::anyhow!("Failed to set number of channels for mp3 encoder: {}", e)
-        })?;
-        builder
-            .set_brate(mp3lame_encoder::Bitrate::Kbps320)
-            .map_err(|e| anyhow::anyhow!("Failed to set bitrate for mp3 encoder: {}", e))?;
+// The handle is only ever used by one thread at a time.
+unsafe impl Send for Lame {}
 
-        builder
-            .build()
-            .map_err(|e| anyhow::anyhow!("Failed to build mp3 encoder: {}", e))
+fn check(result: c_int, message: &str) -> Result<c_int> {
+    if result < 0 {
+        return Err(anyhow!("{} (LAME error {})", message, result));
     }
+    Ok(result)
+}
+
+/// Worst case output size for `frames` samples per channel, as documented by
+/// LAME.
+fn max_required_buffer_size(frames: usize) -> usize {
+    frames + frames.div_ceil(4) + 7200
 }
 
 #[async_trait::async_trait]
 impl Encoder for Mp3Encoder {
     async fn encode(&self, samples: Samples) -> anyhow::Result<EncodedStream> {
-        let mut mp3_encoder = Self::build_encoder(samples.sample_rate, samples.channels)?;
-
-        let mp3_out_buffer = tokio::task::spawn_blocking(move || {
-            let input = InterleavedPcm(samples.samples.as_slice());
-            let mut mp3_out_buffer = Vec::with_capacity(mp3lame_encoder::max_required_buffer_size(
-                samples.samples.len(),
-            ));
-            let encoded_size = mp3_encoder
-                .encode(input, mp3_out_buffer.spare_capacity_mut())
-                .map_err(|e| anyhow!("Failed to encode mp3: {}", e))?;
-            unsafe {
-                mp3_out_buffer.set_len(mp3_out_buffer.len().wrapping_add(encoded_size));
-            }
-
-            let encoded_size = mp3_encoder
-                .flush::<FlushNoGap>(mp3_out_buffer.spare_capacity_mut())
-                .map_err(|e| anyhow!("Failed to flush mp3 encoder: {}", e))?;
-            unsafe {
-                mp3_out_buffer.set_len(mp3_out_buffer.len().wrapping_add(encoded_size));
-            }
+        let mut lame = Lame::new(samples.sample_rate, samples.channels, true)?;
+
+        let mp3_out_buffer = tokio::task::spawn_blocking(move || -> Result<Vec<u8>> {
+            let mut mp3_out_buffer = Vec::new();
+            lame.encode(
+                &samples.samples,
+                samples.channels as usize,
+                &mut mp3_out_buffer,
+            )?;
+            lame.flush(&mut mp3_out_buffer)?;
+            lame.write_lametag(&mut mp3_out_buffer)?;
             Ok(mp3_out_buffer)
         })
         .await??;
@@ -60,3 +144,33 @@ impl Encoder for Mp3Encoder {
         Ok(EncodedStream::new(mp3_out_buffer))
     }
 }
+
+/// Encodes MP3 frames as samples come in. The stream starts with audio
+/// right away: the first frame can't be rewritten into a LAME tag later.
+pub struct Mp3StreamEncoder {
+    lame: Lame,
+    channels: usize,
+}
+
+impl Mp3StreamEncoder {
+    pub fn new(sample_rate: u32, channels: u32) -> Result<Self> {
+        Ok(Mp3StreamEncoder {
+            lame: Lame::new(sample_rate, channels, false)?,
+            channels: channels as usize,
+        })
+    }
+}
+
+impl StreamEncoder for Mp3StreamEncoder {
+    fn encode(&mut self, samples: &[i32]) -> Result<Vec<u8>> {
+        let mut out = Vec::new();
+        self.lame.encode(samples, self.channels, &mut out)?;
+        Ok(out)
+    }
+
+    fn finish(&mut self) -> Result<Vec<u8>> {
+        let mut out = Vec::new();
+        self.lame.flush(&mut out)?;
+        Ok(out)
+    }
+}
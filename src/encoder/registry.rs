@@ -0,0 +1,168 @@
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::path::Path;
+use std::sync::RwLock;
+
+use anyhow::Result;
+
+use super::tags::Tags;
+use super::{Encoder, Format};
+
+/// A format implemented outside of spotify-dl. Register it with
+/// `register_format` before parsing `--format`, then it is encoded, tagged
+/// and verified through these methods like the built-in formats.
+pub trait FormatPlugin: Encoder + Send {
+    /// The name `--format` takes, e.g. `opus`.
+    fn name(&self) -> &'static str;
+
+    /// File extension without the dot.
+    fn extension(&self) -> &'static str;
+
+    /// Writes the tags into the encoded file at `path`.
+    fn store_tags(&self, path: &Path, tags: &Tags) -> Result<()>;
+
+    /// The Spotify URI stored by `store_tags`, for `--match-by-tag` and the
+    /// library index.
+    fn read_spotify_uri(&self, _path: &Path) -> Option<String> {
+        None
+    }
+
+    fn read_isrc(&self, _path: &Path) -> Option<String> {
+        None
+    }
+
+    /// Checks a written file for `--verify`. Files pass by default.
+    fn verify(&self, _path: &Path) -> Result<()> {
+        Ok(())
+    }
+
+    /// Average bitrate in bits per second, for size estimates.
+    fn bitrate(&self) -> Option<u64> {
+        None
+    }
+}
+
+/// A registered plugin. Formats compare equal by name.
+#[derive(Clone, Copy)]
+pub struct PluginFormat(&'static dyn FormatPlugin);
+
+impl PluginFormat {
+    pub fn plugin(&self) -> &'static dyn FormatPlugin {
+        self.0
+    }
+}
+
+impl fmt::Debug for PluginFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("PluginFormat").field(&self.0.name()).finish()
+    }
+}
+
+impl PartialEq for PluginFormat {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.name() == other.0.name()
+    }
+}
+
+impl Eq for PluginFormat {}
+
+impl Hash for PluginFormat {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.name().hash(state);
+    }
+}
+
+static PLUGINS: RwLock<Vec<PluginFormat>> = RwLock::new(Vec::new());
+
+/// Makes a format available by its name and extension. Names and
+/// extensions of built-in and already registered formats are taken.
+pub fn register_format(plugin: &'static dyn FormatPlugin) -> Result<Format> {
+    let taken =
+        |format: Format| format.name() == plugin.name() || format.extension() == plugin.extension();
+    let built_in = super::BUILT_IN_FORMATS.iter().copied();
+    if built_in.chain(registered_formats()).any(taken) {
+        return Err(anyhow::anyhow!(
+            "The name or extension of format {} is already taken",
+            plugin.name()
+        ));
+    }
+    let format = PluginFormat(plugin);
+    PLUGINS.write().unwrap().push(format);
+    Ok(Format::Plugin(format))
+}
+
+/// Formats registered by plugins, in the order they were registered.
+pub fn registered_formats() -> Vec<Format> {
+    PLUGINS
+        .read()
+        .unwrap()
+        .iter()
+        .map(|&format| Format::Plugin(format))
+        .collect()
+}
+
+pub(super) fn find(matches: impl Fn(&dyn FormatPlugin) -> bool) -> Option<Format> {
+    PLUGINS
+        .read()
+        .unwrap()
+        .iter()
+        .find(|format| matches(format.0))
+        .map(|&format| Format::Plugin(format))
+}
+
+/// Name of the function a plugin library exports, see
+/// `export_format_plugin!`.
+#[cfg(feature = "plugins")]
+const PLUGIN_ENTRY_POINT: &[u8] = b"spotify_dl_format_plugin";
+
+/// Where the CLI loads plugins from, `~/.spotify-dl/plugins`.
+#[cfg(feature = "plugins")]
+pub fn plugin_dir() -> Result<std::path::PathBuf> {
+    Ok(crate::utils::get_dot_path()?.join("plugins"))
+}
+
+/// Loads the plugin libraries (`.so`, `.dylib` or `.dll`) in `dir` and
+/// registers their formats. Plugins are Rust libraries built with
+/// `export_format_plugin!`, against the same spotify-dl version and with the
+/// same compiler as the binary loading them.
+#[cfg(feature = "plugins")]
+pub fn load_plugins(dir: &Path) -> Result<Vec<Format>> {
+    let mut formats = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().and_then(|ext| ext.to_str()) != Some(std::env::consts::DLL_EXTENSION) {
+            continue;
+        }
+        // Loading runs the library's initializers, and the entry point is
+        // trusted to have the signature `export_format_plugin!` gives it.
+        let plugin = unsafe {
+            let library = libloading::Library::new(&path)?;
+            let entry_point: fn() -> &'static dyn FormatPlugin =
+                *library.get(PLUGIN_ENTRY_POINT)?;
+            let plugin = entry_point();
+            // The plugin's code has to stay loaded for as long as it is
+            // registered, which is until the process exits.
+            std::mem::forget(library);
+            plugin
+        };
+        formats.push(register_format(plugin)?);
+    }
+    Ok(formats)
+}
+
+/// Exports a `&'static dyn FormatPlugin` from a plugin library for
+/// `load_plugins`:
+///
+/// ```ignore
+/// static OPUS: OpusFormat = OpusFormat;
+/// spotify_dl::export_format_plugin!(&OPUS);
+/// ```
+#[macro_export]
+macro_rules! export_format_plugin {
+    ($plugin:expr) => {
+        #[unsafe(no_mangle)]
+        pub fn spotify_dl_format_plugin() -> &'static dyn $crate::encoder::FormatPlugin {
+            $plugin
+        }
+    };
+}
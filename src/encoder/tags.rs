@@ -1,40 +1,334 @@
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
 use anyhow::Result;
-use audiotags::Picture;
-use audiotags::Tag;
-use audiotags::TagType;
 use bytes::Bytes;
+use id3::TagLike;
 
+use crate::chapters::{Chapter, format_timestamp};
 use crate::encoder::Format;
 
+const SPOTIFY_URI_TAG: &str = "SPOTIFY_URI";
+const ISRC_VORBIS_TAG: &str = "ISRC";
+const ISRC_ID3_FRAME: &str = "TSRC";
+const JPEG_MIME_TYPE: &str = "image/jpeg";
+const PNG_MIME_TYPE: &str = "image/png";
+const JPEG_MAGIC: &[u8] = &[0xff, 0xd8, 0xff];
+const PNG_MAGIC: &[u8] = &[0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a];
+/// Quality of covers converted from PNG, on a scale of 1 to 100.
+const CONVERTED_JPEG_QUALITY: u8 = 90;
+/// Default quality of resized covers, on the same scale.
+pub const DEFAULT_COVER_QUALITY: u8 = 90;
+
 pub struct Tags {
     pub title: String,
     pub artists: Vec<String>,
     pub album_title: String,
     pub album_cover: Option<Bytes>,
+    pub spotify_uri: Option<String>,
+    pub isrc: Option<String>,
+    pub track_number: Option<u32>,
+    pub disc_number: Option<u32>,
+    pub disc_count: Option<u32>,
+    /// Chapters of a podcast episode. FLAC files carry them as Vorbis
+    /// comments; MP3 files get a `.chapters.txt` sidecar when they are moved
+    /// into place.
+    pub chapters: Vec<Chapter>,
+    pub genre: Option<String>,
 }
 
-pub async fn store_tags(path: String, tags: &Tags, format: Format) -> Result<()> {
-    let tag_type = match format {
-        Format::Mp3 => TagType::Id3v2,
-        Format::Flac => TagType::Flac,
-    };
+/// MIME type of a cover image, from its magic bytes. Spotify serves JPEGs,
+/// but PNGs turn up too; anything else is not embedded.
+pub fn cover_mime_type(data: &[u8]) -> Option<&'static str> {
+    if data.starts_with(JPEG_MAGIC) {
+        Some(JPEG_MIME_TYPE)
+    } else if data.starts_with(PNG_MAGIC) {
+        Some(PNG_MIME_TYPE)
+    } else {
+        None
+    }
+}
 
-    if format == Format::Mp3 {
-        let tag = id3::Tag::new();
-        tag.write_to_path(&path, id3::Version::Id3v24)?;
+/// Re-encodes a PNG cover as a JPEG, which is usually much smaller. Other
+/// covers are returned as they are.
+pub fn convert_png_cover(cover: Bytes) -> Result<Bytes> {
+    if cover_mime_type(&cover) != Some(PNG_MIME_TYPE) {
+        return Ok(cover);
     }
+    let image = image::load_from_memory_with_format(&cover, image::ImageFormat::Png)?.to_rgb8();
+    let mut jpeg = Vec::new();
+    image::codecs::jpeg::JpegEncoder::new_with_quality(&mut jpeg, CONVERTED_JPEG_QUALITY)
+        .encode_image(&image)?;
+    Ok(Bytes::from(jpeg))
+}
+
+/// The size covers are scaled to before they are embedded, as `WIDTHxHEIGHT`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CoverSize {
+    pub width: u32,
+    pub height: u32,
+}
 
-    let mut tag = Tag::new().with_tag_type(tag_type).read_from_path(&path)?;
-    tag.set_title(&tags.title);
+impl FromStr for CoverSize {
+    type Err = anyhow::Error;
 
-    let artists: String = tags.artists.first().unwrap_or(&String::new()).to_string();
-    tag.set_artist(&artists);
-    tag.set_album_title(&tags.album_title);
+    fn from_str(s: &str) -> Result<Self> {
+        let unsupported = || anyhow::anyhow!("Unsupported cover size, expected WIDTHxHEIGHT");
+        let (width, height) = s.split_once(['x', 'X']).ok_or_else(unsupported)?;
+        let parse = |side: &str| side.trim().parse::<u32>().ok().filter(|&side| side > 0);
+        Ok(CoverSize {
+            width: parse(width).ok_or_else(unsupported)?,
+            height: parse(height).ok_or_else(unsupported)?,
+        })
+    }
+}
 
-    if let Some(cover) = &tags.album_cover {
-        tag.set_album_cover(Picture::new(cover.as_ref(), audiotags::MimeType::Jpeg));
+impl fmt::Display for CoverSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x{}", self.width, self.height)
     }
+}
+
+/// Scales a cover up or down to fit `size`, keeping its aspect ratio, so a
+/// square cover comes out at exactly that size, and re-encodes it as a JPEG
+/// of the given quality (1 to 100). Covers that already fit exactly are
+/// returned as they are.
+pub fn resize_cover(cover: Bytes, size: CoverSize, quality: u8) -> Result<Bytes> {
+    let image = image::load_from_memory(&cover)?;
+    let fits = (image.width() == size.width && image.height() <= size.height)
+        || (image.height() == size.height && image.width() <= size.width);
+    if fits && cover_mime_type(&cover) == Some(JPEG_MIME_TYPE) {
+        return Ok(cover);
+    }
+    let resized = image
+        .resize(
+            size.width,
+            size.height,
+            image::imageops::FilterType::Lanczos3,
+        )
+        .to_rgb8();
+    let mut jpeg = Vec::new();
+    image::codecs::jpeg::JpegEncoder::new_with_quality(&mut jpeg, quality)
+        .encode_image(&resized)?;
+    Ok(Bytes::from(jpeg))
+}
+
+/// The cover with its MIME type, or `None` when it can't be embedded.
+fn cover_with_mime_type(tags: &Tags) -> Option<(&'static str, &Bytes)> {
+    let cover = tags.album_cover.as_ref()?;
+    match cover_mime_type(cover) {
+        Some(mime_type) => Some((mime_type, cover)),
+        None => {
+            tracing::warn!(
+                "Not embedding cover of {}, it is neither JPEG nor PNG",
+                tags.title
+            );
+            None
+        }
+    }
+}
+
+/// Writes the tags with id3 and metaflac directly: both take the path as
+/// `AsRef<Path>`, so paths that are not valid UTF-8 or need wide characters on
+/// Windows reach the file system untouched.
+pub async fn store_tags<P: AsRef<Path>>(path: P, tags: &Tags, format: Format) -> Result<()> {
+    write_tags(path.as_ref(), tags, format, id3::Version::Id3v24)
+}
 
-    tag.write_to_path(&path)?;
+/// Stores the tags like [`store_tags`] and reads them back. MP3 files whose
+/// ID3v2.4 tag doesn't read back are tagged again with ID3v2.3, which more
+/// players understand. Returns the fields that still don't read back, none
+/// when the tags round-tripped.
+pub async fn store_verified_tags<P: AsRef<Path>>(
+    path: P,
+    tags: &Tags,
+    format: Format,
+) -> Result<Vec<&'static str>> {
+    write_verified_tags(path.as_ref(), tags, format)
+}
+
+/// [`store_verified_tags`] for blocking threads.
+pub fn write_verified_tags(path: &Path, tags: &Tags, format: Format) -> Result<Vec<&'static str>> {
+    write_tags(path, tags, format, id3::Version::Id3v24)?;
+    let mismatched = mismatched_tags(path, tags, format);
+    if mismatched.is_empty() || !matches!(format, Format::Mp3) {
+        return Ok(mismatched);
+    }
+    tracing::warn!(
+        "The ID3v2.4 {} of {} did not read back, retrying with ID3v2.3",
+        mismatched.join(", "),
+        path.display()
+    );
+    write_tags(path, tags, format, id3::Version::Id3v23)?;
+    Ok(mismatched_tags(path, tags, format))
+}
+
+/// The title, artist and cover of `tags` that the file at `path` doesn't
+/// have as they were written. Plugin formats are not read back.
+pub fn mismatched_tags(path: &Path, tags: &Tags, format: Format) -> Vec<&'static str> {
+    let artist = tags.artists.first().map(String::as_str).unwrap_or_default();
+    let embeds_cover = tags
+        .album_cover
+        .as_deref()
+        .and_then(cover_mime_type)
+        .is_some();
+    let (title, read_artist, has_cover) = match format {
+        Format::Mp3 => match id3::Tag::read_from_path(path) {
+            Ok(tag) => (
+                tag.title().map(ToString::to_string),
+                tag.artist().map(ToString::to_string),
+                tag.pictures()
+                    .any(|picture| picture.picture_type == id3::frame::PictureType::CoverFront),
+            ),
+            Err(_) => (None, None, false),
+        },
+        Format::Flac => match metaflac::Tag::read_from_path(path) {
+            Ok(tag) => {
+                let first = |key| {
+                    tag.get_vorbis(key)
+                        .and_then(|mut values| values.next())
+                        .map(ToString::to_string)
+                };
+                (
+                    first("TITLE"),
+                    first("ARTIST"),
+                    tag.pictures().any(|picture| {
+                        picture.picture_type == metaflac::block::PictureType::CoverFront
+                    }),
+                )
+            }
+            Err(_) => (None, None, false),
+        },
+        Format::Plugin(_) => return Vec::new(),
+    };
+    let mut mismatched = Vec::new();
+    if title.unwrap_or_default() != tags.title {
+        mismatched.push("title");
+    }
+    if read_artist.unwrap_or_default() != artist {
+        mismatched.push("artist");
+    }
+    if embeds_cover && !has_cover {
+        mismatched.push("cover");
+    }
+    mismatched
+}
+
+fn write_tags(path: &Path, tags: &Tags, format: Format, id3_version: id3::Version) -> Result<()> {
+    let artist = tags.artists.first().cloned().unwrap_or_default();
+    match format {
+        Format::Mp3 => {
+            let mut tag = id3::Tag::new();
+            tag.set_title(&tags.title);
+            tag.set_artist(&artist);
+            tag.set_album(&tags.album_title);
+            if let Some((mime_type, cover)) = cover_with_mime_type(tags) {
+                tag.add_frame(id3::frame::Picture {
+                    mime_type: mime_type.to_string(),
+                    picture_type: id3::frame::PictureType::CoverFront,
+                    description: String::new(),
+                    data: cover.to_vec(),
+                });
+            }
+            if let Some(uri) = &tags.spotify_uri {
+                tag.add_frame(id3::frame::ExtendedText {
+                    description: SPOTIFY_URI_TAG.to_string(),
+                    value: uri.to_string(),
+                });
+            }
+            if let Some(isrc) = &tags.isrc {
+                tag.set_text(ISRC_ID3_FRAME, isrc);
+            }
+            if let Some(track_number) = tags.track_number {
+                tag.set_track(track_number);
+            }
+            if let Some(disc_number) = tags.disc_number {
+                tag.set_disc(disc_number);
+            }
+            if let Some(disc_count) = tags.disc_count {
+                tag.set_total_discs(disc_count);
+            }
+            if let Some(genre) = &tags.genre {
+                tag.set_genre(genre);
+            }
+            tag.write_to_path(path, id3_version)?;
+        }
+        Format::Flac => {
+            let mut tag = metaflac::Tag::read_from_path(path)?;
+            tag.set_vorbis("TITLE", vec![tags.title.as_str()]);
+            tag.set_vorbis("ARTIST", vec![artist.as_str()]);
+            tag.set_vorbis("ALBUM", vec![tags.album_title.as_str()]);
+            if let Some((mime_type, cover)) = cover_with_mime_type(tags) {
+                tag.remove_picture_type(metaflac::block::PictureType::CoverFront);
+                tag.add_picture(
+                    mime_type,
+                    metaflac::block::PictureType::CoverFront,
+                    cover.to_vec(),
+                );
+            }
+            if let Some(uri) = &tags.spotify_uri {
+                tag.set_vorbis(SPOTIFY_URI_TAG, vec![uri.as_str()]);
+            }
+            if let Some(isrc) = &tags.isrc {
+                tag.set_vorbis(ISRC_VORBIS_TAG, vec![isrc.as_str()]);
+            }
+            if let Some(track_number) = tags.track_number {
+                tag.set_vorbis("TRACKNUMBER", vec![track_number.to_string()]);
+            }
+            if let Some(disc_number) = tags.disc_number {
+                tag.set_vorbis("DISCNUMBER", vec![disc_number.to_string()]);
+            }
+            if let Some(disc_count) = tags.disc_count {
+                tag.set_vorbis("DISCTOTAL", vec![disc_count.to_string()]);
+            }
+            if let Some(genre) = &tags.genre {
+                tag.set_vorbis("GENRE", vec![genre.as_str()]);
+            }
+            for (index, chapter) in tags.chapters.iter().enumerate() {
+                let key = format!("CHAPTER{:03}", index + 1);
+                tag.set_vorbis(key.as_str(), vec![format_timestamp(chapter.start)]);
+                tag.set_vorbis(format!("{key}NAME"), vec![chapter.title.as_str()]);
+            }
+            tag.write_to_path(path)?;
+        }
+        Format::Plugin(format) => format.plugin().store_tags(path, tags)?,
+    }
     Ok(())
 }
+
+pub fn read_spotify_uri<P: AsRef<Path>>(path: P, format: Format) -> Option<String> {
+    match format {
+        Format::Mp3 => {
+            let tag = id3::Tag::read_from_path(path).ok()?;
+            tag.extended_texts()
+                .find(|text| text.description == SPOTIFY_URI_TAG)
+                .map(|text| text.value.clone())
+        }
+        Format::Flac => {
+            let tag = metaflac::Tag::read_from_path(path).ok()?;
+            tag.get_vorbis(SPOTIFY_URI_TAG)?
+                .next()
+                .map(ToString::to_string)
+        }
+        Format::Plugin(format) => format.plugin().read_spotify_uri(path.as_ref()),
+    }
+}
+
+pub fn read_isrc<P: AsRef<Path>>(path: P, format: Format) -> Option<String> {
+    match format {
+        Format::Mp3 => {
+            let tag = id3::Tag::read_from_path(path).ok()?;
+            tag.get(ISRC_ID3_FRAME)
+                .and_then(|frame| frame.content().text())
+                .map(ToString::to_string)
+        }
+        Format::Flac => {
+            let tag = metaflac::Tag::read_from_path(path).ok()?;
+            tag.get_vorbis(ISRC_VORBIS_TAG)?
+                .next()
+                .map(ToString::to_string)
+        }
+        Format::Plugin(format) => format.plugin().read_isrc(path.as_ref()),
+    }
+}
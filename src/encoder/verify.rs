@@ -0,0 +1,179 @@
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::Result;
+use anyhow::anyhow;
+use md5::{Digest, Md5};
+
+use super::Format;
+
+/// Decodes the written file again to make sure it is complete and intact:
+/// every MP3 frame header must be valid and frames must run contiguously to
+/// the end of the file, and a FLAC stream must decode to audio matching the
+/// MD5 signature stored in its STREAMINFO block.
+pub fn verify_file<P: AsRef<Path>>(path: P, format: Format) -> Result<()> {
+    match format {
+        Format::Flac => verify_flac(path.as_ref()),
+        #[cfg(feature = "mp3")]
+        Format::Mp3 => verify_mp3(&std::fs::read(path)?).map(|_| ()),
+        Format::Plugin(format) => format.plugin().verify(path.as_ref()),
+    }
+}
+
+/// Length of the audio in a file, from the FLAC STREAMINFO block or by
+/// walking the MP3 frames. `None` for plugin formats, which don't tell.
+pub fn audio_duration<P: AsRef<Path>>(path: P, format: Format) -> Result<Option<Duration>> {
+    match format {
+        Format::Flac => {
+            let info = claxon::FlacReader::open(path.as_ref())?.streaminfo();
+            let samples = info
+                .samples
+                .ok_or(anyhow!("FLAC stream does not declare its length"))?;
+            Ok(Some(Duration::from_secs_f64(
+                samples as f64 / info.sample_rate as f64,
+            )))
+        }
+        #[cfg(feature = "mp3")]
+        Format::Mp3 => mp3_duration(&std::fs::read(path)?).map(Some),
+        Format::Plugin(_) => Ok(None),
+    }
+}
+
+fn verify_flac(path: &Path) -> Result<()> {
+    let mut reader = claxon::FlacReader::open(path)?;
+    let info = reader.streaminfo();
+    let bytes_per_sample = info.bits_per_sample.div_ceil(8) as usize;
+
+    // Files written by earlier versions pad the last block beyond the sample
+    // count in STREAMINFO and include that padding in the signature, other
+    // encoders only sign the declared samples. Either is accepted.
+    let expected = info.samples.map(|total| total * info.channels as u64);
+    let mut declared = Md5::new();
+    let mut padded = Md5::new();
+    let mut decoded = 0u64;
+    for sample in reader.samples() {
+        let bytes = sample?.to_le_bytes();
+        let bytes = &bytes[..bytes_per_sample];
+        if expected.is_none_or(|expected| decoded < expected) {
+            declared.update(bytes);
+        }
+        padded.update(bytes);
+        decoded += 1;
+    }
+
+    if let Some(expected) = expected
+        && decoded < expected
+    {
+        return Err(anyhow!(
+            "FLAC stream ended after {} of {} samples",
+            decoded,
+            expected
+        ));
+    }
+
+    // An all-zero signature means the encoder did not compute one.
+    if info.md5sum != [0; 16]
+        && declared.finalize().as_slice() != info.md5sum
+        && padded.finalize().as_slice() != info.md5sum
+    {
+        return Err(anyhow!("FLAC audio does not match its MD5 signature"));
+    }
+    Ok(())
+}
+
+/// Returns the number of MPEG audio frames in the file.
+#[cfg(feature = "mp3")]
+pub(crate) fn verify_mp3(data: &[u8]) -> Result<usize> {
+    Ok(mp3_frames(data)?.len())
+}
+
+#[cfg(feature = "mp3")]
+fn mp3_duration(data: &[u8]) -> Result<Duration> {
+    Ok(mp3_frames(data)?
+        .iter()
+        .map(|frame| Duration::from_secs_f64(frame.samples as f64 / frame.sample_rate as f64))
+        .sum())
+}
+
+/// The headers of the MPEG audio frames in the file, which must run
+/// contiguously from the ID3v2 tag to the end or the ID3v1 tag.
+#[cfg(feature = "mp3")]
+fn mp3_frames(data: &[u8]) -> Result<Vec<FrameHeader>> {
+    let mut position = id3v2_size(data);
+    let mut end = data.len();
+    if end >= position + 128 && &data[end - 128..end - 125] == b"TAG" {
+        end -= 128;
+    }
+
+    let mut frames = Vec::new();
+    while position < end {
+        let frame = mp3_frame_header(&data[position..end])
+            .ok_or(anyhow!("Invalid MP3 frame header at byte {}", position))?;
+        if position + frame.length > end {
+            return Err(anyhow!("MP3 frame at byte {} is truncated", position));
+        }
+        position += frame.length;
+        frames.push(frame);
+    }
+
+    if frames.is_empty() {
+        return Err(anyhow!("MP3 file does not contain any audio frames"));
+    }
+    Ok(frames)
+}
+
+#[cfg(feature = "mp3")]
+fn id3v2_size(data: &[u8]) -> usize {
+    if data.len() < 10 || &data[..3] != b"ID3" {
+        return 0;
+    }
+    let size = data[6..10]
+        .iter()
+        .fold(0usize, |size, &byte| (size << 7) | (byte & 0x7f) as usize);
+    let footer = if data[5] & 0x10 != 0 { 10 } else { 0 };
+    10 + size + footer
+}
+
+#[cfg(feature = "mp3")]
+struct FrameHeader {
+    length: usize,
+    sample_rate: usize,
+    /// Samples per channel.
+    samples: usize,
+}
+
+#[cfg(feature = "mp3")]
+fn mp3_frame_header(header: &[u8]) -> Option<FrameHeader> {
+    const BITRATES_V1_L3: [usize; 15] = [
+        0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320,
+    ];
+    const BITRATES_V2_L3: [usize; 15] =
+        [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160];
+    const SAMPLE_RATES: [usize; 3] = [44100, 48000, 32000];
+
+    if header.len() < 4 || header[0] != 0xff || header[1] & 0xe0 != 0xe0 {
+        return None;
+    }
+    let version = (header[1] >> 3) & 0x03;
+    let layer = (header[1] >> 1) & 0x03;
+    let bitrate_index = (header[2] >> 4) as usize;
+    let sample_rate_index = ((header[2] >> 2) & 0x03) as usize;
+    let padding = ((header[2] >> 1) & 0x01) as usize;
+
+    // Only layer III is produced by the encoder.
+    if version == 0x01 || layer != 0x01 || bitrate_index == 0 || bitrate_index == 15 {
+        return None;
+    }
+    let sample_rate = *SAMPLE_RATES.get(sample_rate_index)?;
+    let (bitrate, sample_rate, coefficient) = match version {
+        0x03 => (BITRATES_V1_L3[bitrate_index], sample_rate, 144),
+        0x02 => (BITRATES_V2_L3[bitrate_index], sample_rate / 2, 72),
+        _ => (BITRATES_V2_L3[bitrate_index], sample_rate / 4, 72),
+    };
+    Some(FrameHeader {
+        length: coefficient * bitrate * 1000 / sample_rate + padding,
+        sample_rate,
+        // 1152 samples for MPEG-1, half as many for MPEG-2 and 2.5.
+        samples: coefficient * 8,
+    })
+}
@@ -0,0 +1,71 @@
+use anyhow::Result;
+use librespot::core::session::Session;
+use std::sync::Arc;
+use tokio::runtime::Runtime;
+use tokio::sync::Mutex;
+
+use crate::download::{DownloadOptions, Downloader, OutcomeListener, RunSummary};
+use crate::history::{HISTORY_FILE, PlaylistHistory};
+use crate::library::{LIBRARY_FILE, LibraryIndex};
+use crate::session::create_session;
+use crate::track::get_tracks;
+
+/// A blocking handle on the download engine for programs that embed it, like
+/// the C and Python bindings in `bindings/`. It owns the async runtime, so
+/// callers don't need one of their own.
+pub struct Engine {
+    runtime: Runtime,
+    session: Session,
+}
+
+impl Engine {
+    /// Logs in with the credentials cached by the CLI, or through the
+    /// browser when there are none yet.
+    pub fn connect() -> Result<Self> {
+        let runtime = Runtime::new()?;
+        let session = runtime.block_on(create_session(true))?;
+        Ok(Engine { runtime, session })
+    }
+
+    /// The URIs of the tracks the given songs, albums, playlists or podcasts
+    /// resolve to.
+    pub fn resolve(&self, inputs: &[String]) -> Result<Vec<String>> {
+        let tracks = self
+            .runtime
+            .block_on(get_tracks(inputs.to_vec(), &self.session))?;
+        Ok(tracks
+            .iter()
+            .filter_map(|track| track.id.to_uri().ok())
+            .collect())
+    }
+
+    /// Downloads the inputs like the CLI does, skipping tracks that are
+    /// already in the destination's library or download history. `listener`
+    /// is called from worker threads as each track finishes.
+    pub fn download(
+        &self,
+        inputs: &[String],
+        options: &DownloadOptions,
+        listener: Option<OutcomeListener>,
+    ) -> Result<RunSummary> {
+        self.runtime.block_on(async {
+            let tracks = get_tracks(inputs.to_vec(), &self.session).await?;
+            let history = tracks
+                .iter()
+                .any(|track| track.playlist().is_some())
+                .then(|| {
+                    let path = options.destination.join(HISTORY_FILE);
+                    Arc::new(Mutex::new(PlaylistHistory::load(path)))
+                });
+            let library = LibraryIndex::load(options.destination.join(LIBRARY_FILE));
+
+            let downloader =
+                Downloader::new(self.session.clone(), history, Arc::new(Mutex::new(library)));
+            let downloader = match listener {
+                Some(listener) => downloader.with_outcome_listener(listener),
+                None => downloader,
+            };
+            downloader.download_tracks(tracks, options).await
+        })
+    }
+}
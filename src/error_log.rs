@@ -0,0 +1,96 @@
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::Result;
+use librespot::core::SpotifyUri;
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Level {
+    Warning,
+    Error,
+}
+
+/// The step of a track's download that went wrong.
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Phase {
+    Metadata,
+    Stream,
+    Download,
+    Encode,
+    Write,
+    Verify,
+    Tag,
+    Move,
+    Record,
+    Canvas,
+    Replicate,
+}
+
+#[derive(Serialize)]
+struct Entry<'a> {
+    time: u64,
+    level: Level,
+    track: Option<String>,
+    phase: Phase,
+    retries: usize,
+    error: String,
+    /// The error and all of its causes, outermost first.
+    chain: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<&'a str>,
+}
+
+/// Appends one JSON object per warning or error of a run to a file, separate
+/// from the console output.
+pub struct ErrorLog {
+    file: Mutex<File>,
+}
+
+impl ErrorLog {
+    pub fn open(path: &Path) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(ErrorLog {
+            file: Mutex::new(file),
+        })
+    }
+
+    pub fn record(
+        &self,
+        level: Level,
+        track: &SpotifyUri,
+        name: Option<&str>,
+        phase: Phase,
+        retries: usize,
+        error: &anyhow::Error,
+    ) {
+        let entry = Entry {
+            time: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|elapsed| elapsed.as_secs())
+                .unwrap_or_default(),
+            level,
+            track: track.to_uri().ok(),
+            phase,
+            retries,
+            error: error.to_string(),
+            chain: error.chain().map(ToString::to_string).collect(),
+            name,
+        };
+
+        let written = serde_json::to_string(&entry)
+            .map_err(anyhow::Error::from)
+            .and_then(|line| Ok(writeln!(self.file.lock().unwrap(), "{line}")?));
+        if let Err(err) = written {
+            tracing::warn!(error = %err, "Failed to write to the error log");
+        }
+    }
+}
@@ -0,0 +1,60 @@
+use anyhow::Result;
+use futures::StreamExt;
+use librespot::core::session::Session;
+use std::time::Duration;
+
+use crate::encoder::Format;
+use crate::track::{CONCURRENT_METADATA_REQUESTS, Track};
+
+/// The MP3 encoder writes constant 320 kbps.
+const MP3_BITRATE: u64 = 320_000;
+/// 24 bit FLAC of the decoded 320 kbps Ogg Vorbis streams averages about
+/// this much; quiet or mono material comes out smaller.
+const FLAC_BITRATE: u64 = 1_400_000;
+
+/// How much downloading some inputs would take, computed from track
+/// metadata only: no audio is streamed.
+#[derive(Debug, Default, Clone)]
+pub struct Estimate {
+    pub tracks: usize,
+    pub duration: Duration,
+    /// Tracks whose metadata could not be fetched, left out of the totals.
+    pub unavailable: usize,
+}
+
+impl Estimate {
+    pub async fn of(session: &Session, tracks: &[Track]) -> Self {
+        let durations: Vec<Result<Duration>> = futures::stream::iter(tracks)
+            .map(|track| track.duration(session))
+            .buffer_unordered(CONCURRENT_METADATA_REQUESTS)
+            .collect()
+            .await;
+
+        let mut estimate = Estimate::default();
+        for duration in durations {
+            match duration {
+                Ok(duration) => {
+                    estimate.tracks += 1;
+                    estimate.duration += duration;
+                }
+                Err(err) => {
+                    tracing::warn!(error = %err, "Leaving a track out of the estimate");
+                    estimate.unavailable += 1;
+                }
+            }
+        }
+        estimate
+    }
+
+    /// Bytes the tracks would take up in the given format, unless a plugin
+    /// format does not tell its bitrate.
+    pub fn projected_size(&self, format: Format) -> Option<u64> {
+        let bitrate = match format {
+            Format::Flac => FLAC_BITRATE,
+            #[cfg(feature = "mp3")]
+            Format::Mp3 => MP3_BITRATE,
+            Format::Plugin(format) => format.plugin().bitrate()?,
+        };
+        Some((self.duration.as_secs_f64() * bitrate as f64 / 8.0) as u64)
+    }
+}
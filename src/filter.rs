@@ -0,0 +1,221 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use anyhow::Result;
+use chrono::NaiveDate;
+use futures::StreamExt;
+use librespot::core::SpotifyUri;
+use librespot::core::session::Session;
+use librespot::metadata::Metadata;
+
+use crate::track::{CONCURRENT_METADATA_REQUESTS, Track};
+
+/// Keeps only tracks released on or after a date and at least this popular,
+/// and at most so many of each artist and album. Episodes are dated by their
+/// publish time, have no popularity and count towards their show as album.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TrackFilter {
+    pub released_after: Option<NaiveDate>,
+    /// Spotify's popularity score, 0 to 100.
+    pub min_popularity: Option<i32>,
+    /// Tracks kept per primary artist.
+    pub max_per_artist: Option<usize>,
+    /// Tracks kept per album.
+    pub max_per_album: Option<usize>,
+    /// Which tracks the caps keep.
+    pub keep: CapKeep,
+}
+
+/// Which tracks of an artist or album over its cap are kept.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum CapKeep {
+    /// The first ones in the input.
+    #[default]
+    First,
+    /// The most popular ones, earlier ones first on a tie.
+    Popular,
+}
+
+impl FromStr for CapKeep {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "first" => Ok(CapKeep::First),
+            "popular" => Ok(CapKeep::Popular),
+            _ => Err(anyhow::anyhow!(
+                "Unsupported cap order, expected first or popular"
+            )),
+        }
+    }
+}
+
+/// What the filter looks at of a track.
+#[derive(Debug, Clone, Default)]
+pub struct TrackFacts {
+    pub released: Option<NaiveDate>,
+    pub popularity: Option<i32>,
+    /// The URI of the first credited artist.
+    pub artist: Option<String>,
+    /// The URI of the album, or the show name of an episode.
+    pub album: Option<String>,
+}
+
+/// A date as `YYYY` or `YYYY-MM-DD`. A bare year stands for January 1st.
+pub fn parse_release_date(s: &str) -> Result<NaiveDate> {
+    let date = match s.parse::<i32>() {
+        Ok(year) => NaiveDate::from_ymd_opt(year, 1, 1),
+        Err(_) => NaiveDate::parse_from_str(s, "%Y-%m-%d").ok(),
+    };
+    date.ok_or(anyhow::anyhow!(
+        "Unsupported release date, expected YYYY or YYYY-MM-DD"
+    ))
+}
+
+impl TrackFilter {
+    pub fn is_empty(&self) -> bool {
+        self.released_after.is_none()
+            && self.min_popularity.is_none()
+            && self.max_per_artist.is_none()
+            && self.max_per_album.is_none()
+    }
+
+    pub fn keeps(&self, released: NaiveDate, popularity: Option<i32>) -> bool {
+        self.released_after.is_none_or(|after| released >= after)
+            && match (self.min_popularity, popularity) {
+                (Some(min), Some(popularity)) => popularity >= min,
+                _ => true,
+            }
+    }
+
+    /// Which of the tracks stay within `--max-per-artist` and
+    /// `--max-per-album`, as one flag per track in input order. Tracks
+    /// without facts stay and don't count; a track listed again stays
+    /// when its first listing does.
+    pub fn cap(&self, tracks: &[(String, Option<TrackFacts>)]) -> Vec<bool> {
+        let mut order: Vec<usize> = (0..tracks.len()).collect();
+        if self.keep == CapKeep::Popular {
+            // Stable, so ties keep their input order.
+            order.sort_by_key(|&i| {
+                std::cmp::Reverse(tracks[i].1.as_ref().and_then(|facts| facts.popularity))
+            });
+        }
+        let mut kept = vec![false; tracks.len()];
+        let mut seen: HashMap<&str, bool> = HashMap::new();
+        let mut per_artist: HashMap<&str, usize> = HashMap::new();
+        let mut per_album: HashMap<&str, usize> = HashMap::new();
+        for i in order {
+            let (id, facts) = &tracks[i];
+            if let Some(&first) = seen.get(id.as_str()) {
+                kept[i] = first;
+                continue;
+            }
+            let Some(facts) = facts else {
+                kept[i] = true;
+                continue;
+            };
+            let keep = under_cap(&per_artist, &facts.artist, self.max_per_artist)
+                && under_cap(&per_album, &facts.album, self.max_per_album);
+            if keep {
+                if let Some(artist) = &facts.artist {
+                    *per_artist.entry(artist.as_str()).or_default() += 1;
+                }
+                if let Some(album) = &facts.album {
+                    *per_album.entry(album.as_str()).or_default() += 1;
+                }
+            }
+            seen.insert(id.as_str(), keep);
+            kept[i] = keep;
+        }
+        kept
+    }
+
+    /// Drops the tracks the filter doesn't keep. Tracks whose metadata can't
+    /// be fetched stay in, so their download reports the problem.
+    pub async fn apply(&self, session: &Session, tracks: Vec<Track>) -> Vec<Track> {
+        if self.is_empty() {
+            return tracks;
+        }
+        let fetched: Vec<(Track, Option<TrackFacts>)> = futures::stream::iter(tracks)
+            .map(|track| async move {
+                match track_facts(session, &track.id).await {
+                    Ok(facts) => (track, Some(facts)),
+                    Err(err) => {
+                        tracing::warn!(error = %err, "Could not filter track {}", track.id);
+                        (track, None)
+                    }
+                }
+            })
+            .buffered(CONCURRENT_METADATA_REQUESTS)
+            .collect()
+            .await;
+        let fetched: Vec<(Track, Option<TrackFacts>)> = fetched
+            .into_iter()
+            .filter(|(_, facts)| {
+                facts.as_ref().is_none_or(|facts| {
+                    facts
+                        .released
+                        .is_none_or(|released| self.keeps(released, facts.popularity))
+                })
+            })
+            .collect();
+        if self.max_per_artist.is_none() && self.max_per_album.is_none() {
+            return fetched.into_iter().map(|(track, _)| track).collect();
+        }
+        let keys: Vec<(String, Option<TrackFacts>)> = fetched
+            .iter()
+            .map(|(track, facts)| (track.id.to_uri().unwrap_or_default(), facts.clone()))
+            .collect();
+        let kept = self.cap(&keys);
+        fetched
+            .into_iter()
+            .zip(kept)
+            .filter_map(|((track, _), keep)| keep.then_some(track))
+            .collect()
+    }
+}
+
+fn under_cap(counts: &HashMap<&str, usize>, key: &Option<String>, max: Option<usize>) -> bool {
+    match (key, max) {
+        (Some(key), Some(max)) => counts.get(key.as_str()).copied().unwrap_or(0) < max,
+        _ => true,
+    }
+}
+
+async fn track_facts(session: &Session, id: &SpotifyUri) -> Result<TrackFacts> {
+    let (date, popularity, artist, album) = match id {
+        SpotifyUri::Episode { .. } => {
+            let episode = librespot::metadata::Episode::get(session, id)
+                .await
+                .map_err(|_| anyhow::anyhow!("Failed to get episode metadata"))?;
+            (episode.publish_time, None, None, Some(episode.show_name))
+        }
+        _ => {
+            let track = librespot::metadata::Track::get(session, id)
+                .await
+                .map_err(|_| anyhow::anyhow!("Failed to get metadata"))?;
+            let artist = track
+                .artists
+                .first()
+                .and_then(|artist| artist.id.to_uri().ok());
+            (
+                track.album.date,
+                Some(track.popularity),
+                artist,
+                track.album.id.to_uri().ok(),
+            )
+        }
+    };
+    let released = NaiveDate::from_ymd_opt(
+        date.year(),
+        u8::from(date.month()) as u32,
+        date.day() as u32,
+    )
+    .ok_or(anyhow::anyhow!("Invalid release date"))?;
+    Ok(TrackFacts {
+        released: Some(released),
+        popularity,
+        artist,
+        album,
+    })
+}
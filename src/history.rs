@@ -3,11 +3,106 @@ use librespot::core::SpotifyUri;
 use serde::{Deserialize, Serialize};
 use std::collections::{hash_map::Entry, BTreeSet, HashMap};
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Name of the file in the destination that records the download history.
+pub const HISTORY_FILE: &str = ".spotify-dl-history.json";
+
+/// The scope of tombstones in [`Changes`], next to the playlists.
+const TOMBSTONES: &str = "tombstones";
 
 #[derive(Debug, Serialize, Deserialize, Default)]
 struct StoredHistory {
     playlists: HashMap<String, BTreeSet<String>>,
+    /// Tracks whose files were deleted on purpose and must not be downloaded again.
+    #[serde(default)]
+    tombstones: BTreeSet<String>,
+    /// Ordered track list of every playlist as seen on the last sync.
+    #[serde(default)]
+    snapshots: HashMap<String, Vec<String>>,
+    /// The number every track got in its playlist, for `{position}` names.
+    #[serde(default)]
+    positions: HashMap<String, HashMap<String, usize>>,
+    /// The files each playlist's downloads ended up in, relative to the
+    /// destination, so `--sync-delete` only deletes the playlist's own copy.
+    #[serde(default)]
+    files: HashMap<String, HashMap<String, BTreeSet<PathBuf>>>,
+    #[serde(default)]
+    changes: Changes,
+}
+
+/// When downloads and tombstones were last recorded and dropped, in
+/// milliseconds since the epoch, per playlist or [`TOMBSTONES`] and track.
+/// Merging two copies of the history keeps a record only if neither copy
+/// dropped it after it was recorded, so a `--forget` on one device isn't
+/// undone by another device that still has the record.
+#[derive(Debug, Serialize, Deserialize, Default)]
+struct Changes {
+    #[serde(default)]
+    added: HashMap<String, HashMap<String, u64>>,
+    #[serde(default)]
+    removed: HashMap<String, HashMap<String, u64>>,
+}
+
+impl Changes {
+    fn add(&mut self, scope: &str, track: &str) {
+        Self::stamp(&mut self.added, scope, track);
+    }
+
+    fn remove(&mut self, scope: &str, track: &str) {
+        Self::stamp(&mut self.removed, scope, track);
+    }
+
+    fn stamp(times: &mut HashMap<String, HashMap<String, u64>>, scope: &str, track: &str) {
+        times
+            .entry(scope.to_string())
+            .or_default()
+            .insert(track.to_string(), now_millis());
+    }
+
+    /// Whether the record was dropped after it was last recorded. Records
+    /// from before there were timestamps count as recorded at the epoch.
+    fn is_removed(&self, scope: &str, track: &str) -> bool {
+        let time = |times: &HashMap<String, HashMap<String, u64>>| {
+            times
+                .get(scope)
+                .and_then(|tracks| tracks.get(track))
+                .copied()
+        };
+        match (time(&self.added), time(&self.removed)) {
+            (_, None) => false,
+            (None, Some(_)) => true,
+            (Some(added), Some(removed)) => removed >= added,
+        }
+    }
+
+    /// Takes the later time of every record.
+    fn merge(&mut self, other: Changes) {
+        for (mine, theirs) in [
+            (&mut self.added, other.added),
+            (&mut self.removed, other.removed),
+        ] {
+            for (scope, tracks) in theirs {
+                let times = mine.entry(scope).or_default();
+                for (track, time) in tracks {
+                    let entry = times.entry(track).or_default();
+                    *entry = (*entry).max(time);
+                }
+            }
+        }
+    }
+}
+
+/// Merges `theirs` into `mine`, leaving out records that were dropped.
+fn merge_records(
+    mine: &mut BTreeSet<String>,
+    theirs: BTreeSet<String>,
+    scope: &str,
+    changes: &Changes,
+) {
+    mine.extend(theirs);
+    mine.retain(|track| !changes.is_removed(scope, track));
 }
 
 pub struct PlaylistHistory {
@@ -28,6 +123,7 @@ impl PlaylistHistory {
     pub fn record_download(&mut self, playlist: &SpotifyUri, track: &SpotifyUri) -> Result<()> {
         if let (Some(playlist_id), Some(track_id)) = (to_uri_string(playlist), to_uri_string(track))
         {
+            self.data.changes.add(&playlist_id, &track_id);
             match self.data.playlists.entry(playlist_id) {
                 Entry::Occupied(mut entry) => {
                     entry.get_mut().insert(track_id);
@@ -44,6 +140,35 @@ impl PlaylistHistory {
         Ok(())
     }
 
+    /// Records `file` as the playlist's copy of the track.
+    pub fn record_file(
+        &mut self,
+        playlist: &SpotifyUri,
+        track: &SpotifyUri,
+        file: &Path,
+    ) -> Result<()> {
+        let (Some(playlist_id), Some(track_id)) = (to_uri_string(playlist), to_uri_string(track))
+        else {
+            return Ok(());
+        };
+        let file = match self.path.parent() {
+            Some(destination) => file.strip_prefix(destination).unwrap_or(file),
+            None => file,
+        };
+        let inserted = self
+            .data
+            .files
+            .entry(playlist_id)
+            .or_default()
+            .entry(track_id)
+            .or_default()
+            .insert(file.to_path_buf());
+        if inserted {
+            self.persist()?;
+        }
+        Ok(())
+    }
+
     pub fn has_downloaded(&self, playlist: &SpotifyUri, track: &SpotifyUri) -> bool {
         let (Some(playlist_id), Some(track_id)) =
             (to_uri_string(playlist), to_uri_string(track))
@@ -54,15 +179,196 @@ impl PlaylistHistory {
         self.data
             .playlists
             .get(&playlist_id)
-            .map_or(false, |tracks| tracks.contains(&track_id))
+            .is_some_and(|tracks| tracks.contains(&track_id))
+    }
+
+    pub fn record_tombstone(&mut self, track: &SpotifyUri) -> Result<()> {
+        if let Some(track_id) = to_uri_string(track)
+            && !self.data.tombstones.contains(&track_id)
+        {
+            self.data.changes.add(TOMBSTONES, &track_id);
+            self.data.tombstones.insert(track_id);
+            self.persist()?;
+        }
+        Ok(())
+    }
+
+    pub fn is_tombstoned(&self, track: &SpotifyUri) -> bool {
+        to_uri_string(track).is_some_and(|track_id| self.data.tombstones.contains(&track_id))
+    }
+
+    /// Drops the track's tombstone and download records so the next run
+    /// downloads it again.
+    pub fn forget(&mut self, track: &SpotifyUri) -> Result<bool> {
+        let Some(track_id) = to_uri_string(track) else {
+            return Ok(false);
+        };
+        let mut forgotten = self.data.tombstones.remove(&track_id);
+        if forgotten {
+            self.data.changes.remove(TOMBSTONES, &track_id);
+        }
+        for (playlist_id, tracks) in &mut self.data.playlists {
+            if tracks.remove(&track_id) {
+                self.data.changes.remove(playlist_id, &track_id);
+                forgotten = true;
+            }
+        }
+        for files in self.data.files.values_mut() {
+            files.remove(&track_id);
+        }
+        if forgotten {
+            self.persist()?;
+        }
+        Ok(forgotten)
+    }
+
+    /// Drops the track from the playlist's download records, for tracks that
+    /// were removed from the playlist, and returns the files recorded as the
+    /// playlist's copy of it.
+    pub fn remove_download(
+        &mut self,
+        playlist: &SpotifyUri,
+        track: &SpotifyUri,
+    ) -> Result<Vec<PathBuf>> {
+        let (Some(playlist_id), Some(track_id)) = (to_uri_string(playlist), to_uri_string(track))
+        else {
+            return Ok(Vec::new());
+        };
+        let removed = self
+            .data
+            .playlists
+            .get_mut(&playlist_id)
+            .is_some_and(|tracks| tracks.remove(&track_id));
+        if removed {
+            self.data.changes.remove(&playlist_id, &track_id);
+        }
+        let files = self
+            .data
+            .files
+            .get_mut(&playlist_id)
+            .and_then(|files| files.remove(&track_id))
+            .unwrap_or_default();
+        if removed || !files.is_empty() {
+            self.persist()?;
+        }
+        let destination = self.path.parent().unwrap_or(Path::new(""));
+        Ok(files.iter().map(|file| destination.join(file)).collect())
+    }
+
+    /// Whether any playlist other than `playlist` recorded the track.
+    pub fn downloaded_elsewhere(&self, playlist: &SpotifyUri, track: &SpotifyUri) -> bool {
+        let (Some(playlist_id), Some(track_id)) = (to_uri_string(playlist), to_uri_string(track))
+        else {
+            return false;
+        };
+        self.data
+            .playlists
+            .iter()
+            .any(|(id, tracks)| *id != playlist_id && tracks.contains(&track_id))
+    }
+
+    pub fn snapshot(&self, playlist: &SpotifyUri) -> Option<&[String]> {
+        let playlist_id = to_uri_string(playlist)?;
+        self.data.snapshots.get(&playlist_id).map(Vec::as_slice)
+    }
+
+    pub fn record_snapshot(&mut self, playlist: &SpotifyUri, tracks: Vec<String>) -> Result<()> {
+        if let Some(playlist_id) = to_uri_string(playlist) {
+            self.data.snapshots.insert(playlist_id, tracks);
+            self.persist()?;
+        }
+        Ok(())
+    }
+
+    /// Numbers the playlist's tracks that have no number yet in the order
+    /// given, after the highest one handed out so far. Tracks keep their
+    /// number when the playlist is reordered or tracks are removed, so files
+    /// named after it don't change on later syncs.
+    pub fn assign_positions(&mut self, playlist: &SpotifyUri, tracks: &[String]) -> Result<()> {
+        let Some(playlist_id) = to_uri_string(playlist) else {
+            return Ok(());
+        };
+        let positions = self.data.positions.entry(playlist_id).or_default();
+        let mut next = positions.values().max().copied().unwrap_or_default() + 1;
+        let mut assigned = false;
+        for track in tracks {
+            if let Entry::Vacant(entry) = positions.entry(track.clone()) {
+                entry.insert(next);
+                next += 1;
+                assigned = true;
+            }
+        }
+        if assigned {
+            self.persist()?;
+        }
+        Ok(())
+    }
+
+    /// The track's number in the playlist, counting from 1.
+    pub fn position(&self, playlist: &SpotifyUri, track: &SpotifyUri) -> Option<usize> {
+        let (playlist_id, track_id) = (to_uri_string(playlist)?, to_uri_string(track)?);
+        self.data
+            .positions
+            .get(&playlist_id)?
+            .get(&track_id)
+            .copied()
+    }
+
+    /// The history as stored, for sharing it through a remote backend.
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(&self.data)?)
+    }
+
+    /// Adds what another copy of the history recorded: downloads and
+    /// tombstones are combined, except those either copy dropped after they
+    /// were recorded, and snapshots and track numbers are only taken for
+    /// playlists this copy has none of.
+    pub fn merge_json(&mut self, contents: &str) -> Result<()> {
+        let mut other: StoredHistory = serde_json::from_str(contents)?;
+        self.data.changes.merge(std::mem::take(&mut other.changes));
+        let changes = &self.data.changes;
+        let playlists: BTreeSet<String> = self
+            .data
+            .playlists
+            .keys()
+            .chain(other.playlists.keys())
+            .cloned()
+            .collect();
+        for playlist in playlists {
+            let theirs = other.playlists.remove(&playlist).unwrap_or_default();
+            let mine = self.data.playlists.entry(playlist.clone()).or_default();
+            merge_records(mine, theirs, &playlist, changes);
+        }
+        merge_records(
+            &mut self.data.tombstones,
+            other.tombstones,
+            TOMBSTONES,
+            changes,
+        );
+        for (playlist, tracks) in other.snapshots {
+            self.data.snapshots.entry(playlist).or_insert(tracks);
+        }
+        for (playlist, positions) in other.positions {
+            self.data.positions.entry(playlist).or_insert(positions);
+        }
+        for (playlist, tracks) in other.files {
+            let files = self.data.files.entry(playlist).or_default();
+            for (track, paths) in tracks {
+                files.entry(track).or_default().extend(paths);
+            }
+        }
+        for (playlist, files) in &mut self.data.files {
+            let downloaded = self.data.playlists.get(playlist);
+            files.retain(|track, _| downloaded.is_some_and(|tracks| tracks.contains(track)));
+        }
+        self.persist()
     }
 
     fn persist(&self) -> Result<()> {
         if let Some(parent) = self.path.parent() {
             fs::create_dir_all(parent)?;
         }
-        let serialized = serde_json::to_string_pretty(&self.data)?;
-        fs::write(&self.path, serialized)?;
+        fs::write(&self.path, self.to_json()?)?;
         Ok(())
     }
 }
@@ -70,3 +376,9 @@ impl PlaylistHistory {
 fn to_uri_string(uri: &SpotifyUri) -> Option<String> {
     uri.to_uri().ok()
 }
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_millis() as u64)
+}
@@ -0,0 +1,125 @@
+use anyhow::Result;
+use bytes::Bytes;
+use http::{Method, Request, header};
+use http_body_util::BodyExt;
+use lazy_static::lazy_static;
+use librespot::core::SpotifyUri;
+use librespot::core::session::Session;
+use regex::Regex;
+
+lazy_static! {
+    /// URIs, open.spotify.com URLs with or without a scheme, locale prefix,
+    /// `embed/` or `user/<name>/` in front and query parameters behind, and
+    /// spotify.link short URLs, anywhere in a line of text.
+    static ref LINK_REGEX: Regex = Regex::new(concat!(
+        r"spotify:(?:user:[^:\s]+:)?(?P<uri_type>track|album|playlist|episode|show|artist):(?P<uri_id>[a-zA-Z0-9]+)",
+        r"|(?:https?://)?(?:open|play)\.spotify\.com/(?:intl-[a-zA-Z-]+/)?(?:embed/)?(?:user/[^/\s]+/)?",
+        r"(?P<url_type>track|album|playlist|episode|show|artist)/(?P<url_id>[a-zA-Z0-9]+)",
+        r"|(?P<short>(?:https?://)?spotify\.link/[a-zA-Z0-9]+)",
+    ))
+    .unwrap();
+}
+
+/// A link found in the input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Link {
+    /// A URI or URL, as the canonical `spotify:<type>:<id>` URI.
+    Uri(String),
+    /// A spotify.link short URL, which only Spotify can resolve.
+    Short(String),
+}
+
+/// Every Spotify link in `text`, in the order they appear. Anything else,
+/// like the chat message around a link, is ignored.
+pub fn find_links(text: &str) -> Vec<Link> {
+    LINK_REGEX
+        .captures_iter(text)
+        .filter_map(|captures| {
+            if let Some(short) = captures.name("short") {
+                let url = short.as_str();
+                return Some(Link::Short(match url.starts_with("http") {
+                    true => url.to_string(),
+                    false => format!("https://{}", url),
+                }));
+            }
+            let item_type = captures.name("uri_type").or(captures.name("url_type"))?;
+            let id = captures.name("uri_id").or(captures.name("url_id"))?;
+            Some(Link::Uri(format!(
+                "spotify:{}:{}",
+                item_type.as_str(),
+                id.as_str()
+            )))
+        })
+        .collect()
+}
+
+/// The first URI or URL in `text`, without resolving short links.
+pub fn parse_link(text: &str) -> Option<SpotifyUri> {
+    if let Ok(uri) = SpotifyUri::from_uri(text.trim()) {
+        return Some(uri);
+    }
+    find_links(text).into_iter().find_map(|link| match link {
+        Link::Uri(uri) => SpotifyUri::from_uri(&uri).ok(),
+        Link::Short(_) => None,
+    })
+}
+
+/// The canonical URIs of every link in the inputs, with short links
+/// resolved. An input with no link at all is an error.
+pub async fn canonical_uris(inputs: &[String], session: &Session) -> Result<Vec<String>> {
+    let mut uris = Vec::new();
+    for input in inputs {
+        // URIs librespot understands but that aren't links, like local files.
+        if !input.trim().contains(char::is_whitespace)
+            && let Ok(uri) = SpotifyUri::from_uri(input.trim())
+        {
+            uris.push(uri.to_uri()?);
+            continue;
+        }
+        let links = find_links(input);
+        if links.is_empty() {
+            return Err(anyhow::anyhow!("No Spotify link in {:?}", input));
+        }
+        for link in links {
+            let uri = match link {
+                Link::Uri(uri) => uri,
+                Link::Short(url) => resolve_short_link(session, &url).await?,
+            };
+            if !uris.contains(&uri) {
+                uris.push(uri);
+            }
+        }
+    }
+    Ok(uris)
+}
+
+/// The URI a spotify.link short URL leads to, from its redirect or,
+/// where it answers with a page instead, from the links on that page.
+pub async fn resolve_short_link(session: &Session, url: &str) -> Result<String> {
+    let request = Request::builder()
+        .method(Method::GET)
+        .uri(url)
+        .body(Bytes::new())?;
+    let response = session.http_client().request_fut(request)?.await?;
+    let target = match response.headers().get(header::LOCATION) {
+        Some(location) => location.to_str()?.to_string(),
+        None if response.status().is_success() => {
+            let body = response.into_body().collect().await?.to_bytes();
+            String::from_utf8_lossy(&body).into_owned()
+        }
+        None => {
+            return Err(anyhow::anyhow!(
+                "Failed to resolve {}: {}",
+                url,
+                response.status()
+            ));
+        }
+    };
+    find_links(&target)
+        .into_iter()
+        .find_map(|link| match link {
+            Link::Uri(uri) => Some(uri),
+            Link::Short(_) => None,
+        })
+        .ok_or(anyhow::anyhow!("{} does not lead to a Spotify link", url))
+}
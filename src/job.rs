@@ -0,0 +1,97 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::Result;
+use librespot::core::SpotifyUri;
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+use crate::track::Track;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JobTrack {
+    pub uri: String,
+    #[serde(default)]
+    pub playlist: Option<String>,
+    #[serde(default)]
+    pub album: Option<String>,
+}
+
+/// A resolved and filtered queue saved with `--save-job`, to be downloaded
+/// later with `run-job`, possibly on another machine.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Job {
+    /// The command line options of the run that saved the job, without its
+    /// inputs and `--save-job`.
+    pub arguments: Vec<String>,
+    /// The options that run took from the config file, so the job doesn't
+    /// depend on the config of the machine it runs on.
+    pub config: Config,
+    pub tracks: Vec<JobTrack>,
+}
+
+impl Job {
+    pub fn new(arguments: Vec<String>, config: Config, tracks: &[Track]) -> Self {
+        let tracks = tracks
+            .iter()
+            .filter_map(|track| {
+                Some(JobTrack {
+                    uri: track.id.to_uri().ok()?,
+                    playlist: track.playlist().and_then(|uri| uri.to_uri().ok()),
+                    album: track.album().and_then(|uri| uri.to_uri().ok()),
+                })
+            })
+            .collect();
+        Job {
+            arguments,
+            config,
+            tracks,
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path)
+            .map_err(|err| anyhow::anyhow!("Failed to read job {}: {}", path.display(), err))?;
+        Ok(serde_json::from_str(&contents)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// The queued tracks, skipping those whose URIs can't be read.
+    pub fn tracks(&self) -> Vec<Track> {
+        self.tracks
+            .iter()
+            .filter_map(|queued| {
+                let id = SpotifyUri::from_uri(&queued.uri).ok()?;
+                let playlist = queued.playlist.as_deref().map(SpotifyUri::from_uri);
+                let album = queued.album.as_deref().map(SpotifyUri::from_uri);
+                Some(match (playlist, album) {
+                    (Some(Ok(playlist)), _) => Track::from_playlist(id, playlist),
+                    (_, Some(Ok(album))) => Track::from_album(id, album),
+                    _ => Track::from_id(id),
+                })
+            })
+            .collect()
+    }
+}
+
+/// The command line `arguments` without the `inputs` and `--save-job`, as
+/// they are kept in a job.
+pub fn job_arguments(
+    arguments: impl IntoIterator<Item = String>,
+    inputs: &[String],
+) -> Vec<String> {
+    let mut kept = Vec::new();
+    let mut arguments = arguments.into_iter();
+    while let Some(argument) = arguments.next() {
+        if argument == "--save-job" {
+            arguments.next();
+        } else if !argument.starts_with("--save-job=") && !inputs.contains(&argument) {
+            kept.push(argument);
+        }
+    }
+    kept
+}
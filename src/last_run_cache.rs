@@ -1,6 +1,133 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use librespot::core::SpotifyUri;
 use serde::{Deserialize, Serialize};
+use spotify_dl::download::TrackOutcome;
+use spotify_dl::track::Track;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct LastRunCache {
     pub url: Vec<String>,
+    /// Every track the inputs resolved to, with what happened to it.
+    #[serde(default)]
+    pub queue: Vec<QueuedTrack>,
+    /// What the run that set up the folder sync downloaded to. `None` in
+    /// caches written before it was recorded.
+    #[serde(default)]
+    pub options: Option<RunOptions>,
+    /// Where each track and playlist pair is in `queue`, built on the first
+    /// outcome.
+    #[serde(skip)]
+    positions: HashMap<(String, Option<String>), usize>,
+}
+
+/// The options of a run that decide where its files end up and what they
+/// are, so a later sync that would put them somewhere else is noticed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RunOptions {
+    pub format: String,
+    pub destination: PathBuf,
+}
+
+impl RunOptions {
+    /// The differences from `previous`, one description each.
+    pub fn changes_from(&self, previous: &RunOptions) -> Vec<String> {
+        let mut changes = Vec::new();
+        if self.format != previous.format {
+            changes.push(format!(
+                "format {} instead of {}, which downloads the whole library again",
+                self.format, previous.format
+            ));
+        }
+        if self.destination != previous.destination {
+            changes.push(format!(
+                "destination {} instead of {}",
+                self.destination.display(),
+                previous.destination.display()
+            ));
+        }
+        changes
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct QueuedTrack {
+    pub uri: String,
+    #[serde(default)]
+    pub playlist: Option<String>,
+    #[serde(default)]
+    pub album: Option<String>,
+    /// `None` until the track was reached.
+    #[serde(default)]
+    pub outcome: Option<TrackOutcome>,
+}
+
+impl LastRunCache {
+    /// A cache for a run of `url` whose tracks are not known yet.
+    pub fn new(url: Vec<String>, options: Option<RunOptions>) -> Self {
+        LastRunCache {
+            url,
+            queue: Vec::new(),
+            options,
+            positions: HashMap::new(),
+        }
+    }
+
+    pub fn set_queue(&mut self, tracks: &[Track]) {
+        self.queue = tracks
+            .iter()
+            .filter_map(|track| {
+                Some(QueuedTrack {
+                    uri: track.id.to_uri().ok()?,
+                    playlist: track.playlist().and_then(|uri| uri.to_uri().ok()),
+                    album: track.album().and_then(|uri| uri.to_uri().ok()),
+                    outcome: None,
+                })
+            })
+            .collect();
+        self.positions.clear();
+    }
+
+    pub fn record_outcome(&mut self, track: &Track, outcome: TrackOutcome) {
+        let Ok(uri) = track.id.to_uri() else {
+            return;
+        };
+        let playlist = track.playlist().and_then(|uri| uri.to_uri().ok());
+        if self.positions.is_empty() {
+            self.positions = self
+                .queue
+                .iter()
+                .enumerate()
+                .rev()
+                .map(|(position, queued)| ((queued.uri.clone(), queued.playlist.clone()), position))
+                .collect();
+        }
+        if let Some(&position) = self.positions.get(&(uri, playlist)) {
+            self.queue[position].outcome = Some(outcome);
+        }
+    }
+
+    /// Tracks that failed, were deferred or were never reached.
+    pub fn unfinished(&self) -> Vec<Track> {
+        self.queue
+            .iter()
+            .filter(|queued| {
+                matches!(
+                    queued.outcome,
+                    None | Some(TrackOutcome::Failed | TrackOutcome::Deferred)
+                )
+            })
+            .filter_map(|queued| {
+                let id = SpotifyUri::from_uri(&queued.uri).ok()?;
+                let playlist = queued.playlist.as_deref().map(SpotifyUri::from_uri);
+                let album = queued.album.as_deref().map(SpotifyUri::from_uri);
+                Some(match (playlist, album) {
+                    (Some(Ok(playlist)), _) => Track::from_playlist(id, playlist),
+                    (_, Some(Ok(album))) => Track::from_album(id, album),
+                    _ => Track::from_id(id),
+                })
+            })
+            .collect()
+    }
 }
@@ -1,8 +1,46 @@
+#[cfg(feature = "webapi")]
+pub mod api;
+pub mod bench;
+pub mod budget;
+pub mod chapters;
+pub mod charts;
+pub mod cleanup;
+pub mod checksums;
+pub mod color;
+pub mod config;
+#[cfg(feature = "sqlite")]
+pub mod database;
+pub mod discover;
 pub mod download;
+pub mod dupes;
+pub mod engine;
+pub mod error_log;
+pub mod estimate;
+pub mod filter;
 pub mod history;
+pub mod input;
+pub mod job;
+pub mod library;
 pub mod encoder;
 pub mod log;
+pub mod mtime;
+pub mod notify;
+pub mod overrides;
+pub mod pipe;
+pub mod profile;
+pub mod progress;
+pub mod remote_history;
+pub mod report;
+pub mod resolved;
+pub mod rootlist;
+pub mod schedule;
+#[cfg(feature = "serve")]
+pub mod serve;
 pub mod session;
 pub mod stream;
+pub mod split;
+pub mod sync;
+pub mod tagging;
 pub mod track;
+pub mod xattrs;
 mod utils;
@@ -0,0 +1,384 @@
+use anyhow::Result;
+use icu_normalizer::DecomposingNormalizerBorrowed;
+use librespot::core::SpotifyUri;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, UNIX_EPOCH};
+
+use crate::encoder::Format;
+use crate::encoder::tags::{read_isrc, read_spotify_uri};
+use crate::utils::hash_file;
+
+/// Name of the file in the destination that indexes the downloaded files.
+pub const LIBRARY_FILE: &str = ".spotify-dl-library.json";
+
+/// How often recorded files are written to the index file at most; the rest
+/// is written by [`LibraryIndex::flush`], or when the index is dropped.
+const PERSIST_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+struct StoredIndex {
+    files: HashMap<PathBuf, IndexedFile>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct IndexedFile {
+    uri: Option<String>,
+    isrc: Option<String>,
+    modified: u64,
+    hash: String,
+}
+
+#[derive(Debug, Default)]
+pub struct ReindexSummary {
+    pub indexed: usize,
+    pub relinked: usize,
+    pub removed: usize,
+    /// URIs of the removed files that are not in the library anymore at all.
+    pub deleted_uris: Vec<String>,
+}
+
+/// Index of everything below the destination directory: the Spotify URI and
+/// ISRC embedded in each file's tags together with its mtime and content hash.
+/// Tags and hashes are only re-read for files whose mtime changed, and files
+/// that were moved or renamed are re-linked to their previous entry by hash.
+pub struct LibraryIndex {
+    path: PathBuf,
+    data: StoredIndex,
+    /// The indexed files of each URI, by path and then in the order they were
+    /// recorded.
+    by_uri: HashMap<String, Vec<PathBuf>>,
+    /// Changes not written to `path` yet.
+    dirty: bool,
+    persisted: Instant,
+}
+
+impl LibraryIndex {
+    pub fn load(path: PathBuf) -> Self {
+        let data = fs::read_to_string(&path)
+            .ok()
+            .and_then(|contents| serde_json::from_str(&contents).ok())
+            .unwrap_or_default();
+
+        let mut index = LibraryIndex {
+            path,
+            data,
+            by_uri: HashMap::new(),
+            dirty: false,
+            persisted: Instant::now(),
+        };
+        index.link_uris();
+        index
+    }
+
+    pub fn reindex(&mut self, root: &Path) -> Result<ReindexSummary> {
+        let mut summary = ReindexSummary::default();
+        let mut previous = std::mem::take(&mut self.data.files);
+        let mut unknown = Vec::new();
+
+        for file in audio_files(root) {
+            let Some(modified) = modified_secs(&file) else {
+                continue;
+            };
+            match previous.remove(&file) {
+                Some(entry) if entry.modified == modified => {
+                    self.data.files.insert(file, entry);
+                }
+                _ => unknown.push((file, modified)),
+            }
+        }
+
+        // Whatever is left in `previous` no longer exists at its old path, so
+        // new files with the same content are the same download, moved.
+        let mut missing: HashMap<String, IndexedFile> = previous
+            .into_values()
+            .map(|entry| (entry.hash.clone(), entry))
+            .collect();
+
+        for (file, modified) in unknown {
+            let Ok(hash) = hash_file(&file) else {
+                continue;
+            };
+            let entry = match missing.remove(&hash) {
+                Some(entry) => {
+                    summary.relinked += 1;
+                    IndexedFile { modified, ..entry }
+                }
+                None => read_entry(&file, modified, hash),
+            };
+            self.data.files.insert(file, entry);
+        }
+
+        self.link_uris();
+        summary.indexed = self.data.files.len();
+        summary.removed = missing.len();
+        summary.deleted_uris = missing
+            .into_values()
+            .filter_map(|entry| entry.uri)
+            .filter(|uri| !self.by_uri.contains_key(uri))
+            .collect();
+        self.persist()?;
+        Ok(summary)
+    }
+
+    /// Indexes a downloaded file. The index file is written at most every
+    /// few seconds, so call [`LibraryIndex::flush`] once a batch is done.
+    pub fn record(&mut self, file: &Path, uri: &SpotifyUri, isrc: Option<String>) -> Result<()> {
+        let modified = modified_secs(file).unwrap_or_default();
+        let hash = hash_file(file)?;
+        self.unlink(file);
+        let uri = uri.to_uri().ok();
+        if let Some(uri) = &uri {
+            self.by_uri
+                .entry(uri.clone())
+                .or_default()
+                .push(file.to_path_buf());
+        }
+        self.data.files.insert(
+            file.to_path_buf(),
+            IndexedFile {
+                uri,
+                isrc,
+                modified,
+                hash,
+            },
+        );
+        self.changed()
+    }
+
+    pub fn contains(&self, track: &SpotifyUri) -> bool {
+        self.path_of(track).is_some()
+    }
+
+    /// Returns the indexed file for the track, provided it still exists.
+    pub fn path_of(&self, track: &SpotifyUri) -> Option<&Path> {
+        self.paths_of(track)
+            .iter()
+            .find(|path| path.exists())
+            .map(PathBuf::as_path)
+    }
+
+    /// The track was indexed, but none of its files exist anymore.
+    pub fn is_missing(&self, track: &SpotifyUri) -> bool {
+        let paths = self.paths_of(track);
+        !paths.is_empty() && paths.iter().all(|path| !path.exists())
+    }
+
+    fn paths_of(&self, track: &SpotifyUri) -> &[PathBuf] {
+        track
+            .to_uri()
+            .ok()
+            .and_then(|uri| self.by_uri.get(&uri))
+            .map_or(&[], Vec::as_slice)
+    }
+
+    /// Drops the track's files from the index and returns them.
+    pub fn remove(&mut self, track: &SpotifyUri) -> Result<Vec<PathBuf>> {
+        let Some(paths) = track.to_uri().ok().and_then(|uri| self.by_uri.remove(&uri)) else {
+            return Ok(Vec::new());
+        };
+        for path in &paths {
+            self.data.files.remove(path);
+        }
+        self.changed()?;
+        Ok(paths)
+    }
+
+    /// Reindexes `root` when any of the tracks has no file in the index, like
+    /// files downloaded before there was an index.
+    pub fn index_missing(&mut self, root: &Path, tracks: &[String]) -> Result<()> {
+        let unindexed = tracks
+            .iter()
+            .any(|uri| SpotifyUri::from_uri(uri).is_ok_and(|track| !self.contains(&track)));
+        if unindexed {
+            self.reindex(root)?;
+        }
+        Ok(())
+    }
+
+    /// Reindexes `root` when any of the tracks has indexed files that are all
+    /// gone, so files that were moved or renamed are linked to their new path
+    /// before the tracks are taken for deleted.
+    pub fn relink_missing(&mut self, root: &Path, tracks: &[String]) -> Result<()> {
+        let missing = tracks
+            .iter()
+            .any(|uri| SpotifyUri::from_uri(uri).is_ok_and(|track| self.is_missing(&track)));
+        if missing {
+            self.reindex(root)?;
+        }
+        Ok(())
+    }
+
+    /// Drops a single file from the index, returning whether it was in it.
+    pub fn remove_file(&mut self, file: &Path) -> Result<bool> {
+        if !self.unlink(file) {
+            return Ok(false);
+        }
+        self.changed()?;
+        Ok(true)
+    }
+
+    /// Writes the changes not written yet to the index file.
+    pub fn flush(&mut self) -> Result<()> {
+        if self.dirty {
+            self.persist()?;
+        }
+        Ok(())
+    }
+
+    /// Drops `file` from the index, returning whether it was in it.
+    fn unlink(&mut self, file: &Path) -> bool {
+        let Some(entry) = self.data.files.remove(file) else {
+            return false;
+        };
+        if let Some(uri) = entry.uri
+            && let Some(paths) = self.by_uri.get_mut(&uri)
+        {
+            paths.retain(|path| path != file);
+            if paths.is_empty() {
+                self.by_uri.remove(&uri);
+            }
+        }
+        true
+    }
+
+    fn link_uris(&mut self) {
+        self.by_uri.clear();
+        for (path, entry) in &self.data.files {
+            if let Some(uri) = &entry.uri {
+                self.by_uri
+                    .entry(uri.clone())
+                    .or_default()
+                    .push(path.clone());
+            }
+        }
+        for paths in self.by_uri.values_mut() {
+            paths.sort();
+        }
+    }
+
+    /// Notes a change, writing the index file if it was not written for
+    /// `PERSIST_INTERVAL`.
+    fn changed(&mut self) -> Result<()> {
+        self.dirty = true;
+        if self.persisted.elapsed() >= PERSIST_INTERVAL {
+            self.persist()?;
+        }
+        Ok(())
+    }
+
+    fn persist(&mut self) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let serialized = serde_json::to_string_pretty(&self.data)?;
+        fs::write(&self.path, serialized)?;
+        self.dirty = false;
+        self.persisted = Instant::now();
+        Ok(())
+    }
+}
+
+impl Drop for LibraryIndex {
+    fn drop(&mut self) {
+        if let Err(err) = self.flush() {
+            tracing::warn!(error = %err, "Failed to write library index {}", self.path.display());
+        }
+    }
+}
+
+fn read_entry(file: &Path, modified: u64, hash: String) -> IndexedFile {
+    let format = file
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .and_then(Format::from_extension);
+    IndexedFile {
+        uri: format.and_then(|format| read_spotify_uri(file, format)),
+        isrc: format.and_then(|format| read_isrc(file, format)),
+        modified,
+        hash,
+    }
+}
+
+/// The file name folded so that names only differing in case, accents or
+/// Unicode normalization compare equal: "Beyoncé - Halo" and "BEYONCE -
+/// Halo" both become "beyonce - halo". Compatibility forms are decomposed
+/// and combining marks dropped, the few Latin letters that don't decompose
+/// are spelled out.
+pub fn loose_name(name: &str) -> String {
+    let decomposed = DecomposingNormalizerBorrowed::new_nfkd().normalize(name);
+    let mut loose = String::with_capacity(decomposed.len());
+    for c in decomposed.chars().flat_map(char::to_lowercase) {
+        match c {
+            '\u{0300}'..='\u{036f}'
+            | '\u{1ab0}'..='\u{1aff}'
+            | '\u{1dc0}'..='\u{1dff}'
+            | '\u{20d0}'..='\u{20ff}'
+            | '\u{fe20}'..='\u{fe2f}' => {}
+            'ß' => loose.push_str("ss"),
+            'æ' => loose.push_str("ae"),
+            'œ' => loose.push_str("oe"),
+            'þ' => loose.push_str("th"),
+            'ø' => loose.push('o'),
+            'đ' | 'ð' => loose.push('d'),
+            'ł' => loose.push('l'),
+            'ı' => loose.push('i'),
+            c => loose.push(c),
+        }
+    }
+    loose
+}
+
+/// A file next to `target` with its extension whose name is the same once
+/// both are folded with [`loose_name`], such as one written before the
+/// file name policy changed.
+pub fn find_loosely_named(target: &Path) -> Option<PathBuf> {
+    let stem = loose_name(&target.file_stem()?.to_string_lossy());
+    let extension = target.extension()?.to_str()?;
+    fs::read_dir(target.parent()?)
+        .ok()?
+        .flatten()
+        .map(|entry| entry.path())
+        .find(|path| {
+            path.extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| ext.eq_ignore_ascii_case(extension))
+                && path
+                    .file_stem()
+                    .is_some_and(|name| loose_name(&name.to_string_lossy()) == stem)
+        })
+}
+
+fn audio_files(root: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let mut pending = vec![root.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                pending.push(path);
+            } else if path
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .and_then(Format::from_extension)
+                .is_some()
+            {
+                files.push(path);
+            }
+        }
+    }
+    files
+}
+
+fn modified_secs(path: &Path) -> Option<u64> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map(|duration| duration.as_secs())
+}
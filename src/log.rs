@@ -64,7 +64,8 @@ impl Write for RotatingFileWriter {
     }
 }
 
-pub fn configure_logger() -> Result<()> {
+/// `ansi` colors the console log, see [`crate::color::ColorChoice::apply`].
+pub fn configure_logger(ansi: bool) -> Result<()> {
     let path = get_dot_path()?.join("spotify-dl.log");
 
     let writer = RotatingFileWriter::new(path)?;
@@ -75,11 +76,16 @@ pub fn configure_logger() -> Result<()> {
         .with_target("spotify_dl", tracing::Level::DEBUG)
         .with_default(LevelFilter::OFF);
 
-    let console_layer = fmt::layer().with_target(false).with_filter(
-        EnvFilter::builder()
-            .with_default_directive(LevelFilter::OFF.into())
-            .from_env_lossy(),
-    );
+    // Console logs go to stderr so they never end up in piped audio.
+    let console_layer = fmt::layer()
+        .with_writer(io::stderr)
+        .with_ansi(ansi)
+        .with_target(false)
+        .with_filter(
+            EnvFilter::builder()
+                .with_default_directive(LevelFilter::OFF.into())
+                .from_env_lossy(),
+        );
 
     let file_layer = fmt::layer()
         .with_writer(non_blocking)
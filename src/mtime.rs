@@ -0,0 +1,47 @@
+use std::path::Path;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::Result;
+
+/// What the modification time of a downloaded file is set to.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum FileMtime {
+    /// When the album or episode was released.
+    ReleaseDate,
+    /// When the track was added to the playlist it was downloaded from.
+    AddedDate,
+    /// When the file was finished.
+    Now,
+}
+
+impl FromStr for FileMtime {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "release-date" => Ok(FileMtime::ReleaseDate),
+            "added-date" => Ok(FileMtime::AddedDate),
+            "now" => Ok(FileMtime::Now),
+            _ => Err(anyhow::anyhow!(
+                "Unsupported file mtime, expected release-date, added-date or now"
+            )),
+        }
+    }
+}
+
+/// The time of a Spotify timestamp in milliseconds, or `None` for the zero
+/// Spotify sends when it doesn't know.
+pub fn from_timestamp_ms(timestamp: i64) -> Option<SystemTime> {
+    u64::try_from(timestamp)
+        .ok()
+        .filter(|&timestamp| timestamp > 0)
+        .map(|timestamp| UNIX_EPOCH + Duration::from_millis(timestamp))
+}
+
+/// Sets the modification time of the file at `path`.
+pub fn set_mtime(path: &Path, time: SystemTime) -> Result<()> {
+    let file = std::fs::File::options().write(true).open(path)?;
+    file.set_modified(time)?;
+    Ok(())
+}
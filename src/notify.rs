@@ -0,0 +1,171 @@
+use anyhow::Result;
+use bytes::Bytes;
+use http::{Method, Request, header};
+use librespot::core::http_client::HttpClient;
+use serde_json::json;
+
+use crate::download::RunSummary;
+
+/// Longest list of tracks spelled out in a message.
+const MAX_LISTED_TRACKS: usize = 20;
+/// Most album covers attached to a message. Telegram and Discord both cap
+/// albums and embeds at ten.
+const MAX_COVERS: usize = 10;
+
+/// A chat service that gets a message at the end of a run.
+#[derive(Debug, Clone)]
+pub enum Notifier {
+    Telegram { token: String, chat: String },
+    Discord { webhook: String },
+}
+
+impl Notifier {
+    /// Parses `<bot token>:<chat id>`. Bot tokens contain a colon themselves,
+    /// so the chat id is whatever follows the last one.
+    pub fn telegram(spec: &str) -> Result<Self> {
+        match spec.rsplit_once(':') {
+            Some((token, chat)) if token.contains(':') && !chat.is_empty() => {
+                Ok(Notifier::Telegram {
+                    token: token.to_string(),
+                    chat: chat.to_string(),
+                })
+            }
+            _ => Err(anyhow::anyhow!(
+                "Expected <bot token>:<chat id> for the Telegram notifier"
+            )),
+        }
+    }
+
+    pub fn discord(webhook: &str) -> Result<Self> {
+        if !webhook.starts_with("https://") {
+            return Err(anyhow::anyhow!("Expected an https:// Discord webhook URL"));
+        }
+        Ok(Notifier::Discord {
+            webhook: webhook.to_string(),
+        })
+    }
+
+    pub async fn notify_summary(&self, client: &HttpClient, summary: &RunSummary) -> Result<()> {
+        let text = summary_text(summary);
+        let covers = new_album_covers(summary);
+        match self {
+            Notifier::Telegram { token, chat } => {
+                self.post(
+                    client,
+                    &format!("https://api.telegram.org/bot{token}/sendMessage"),
+                    json!({ "chat_id": chat, "text": text }),
+                )
+                .await?;
+                for (album, url) in covers {
+                    self.post(
+                        client,
+                        &format!("https://api.telegram.org/bot{token}/sendPhoto"),
+                        json!({ "chat_id": chat, "photo": url, "caption": album }),
+                    )
+                    .await?;
+                }
+                Ok(())
+            }
+            Notifier::Discord { webhook } => {
+                let embeds: Vec<_> = covers
+                    .into_iter()
+                    .map(|(album, url)| json!({ "title": album, "thumbnail": { "url": url } }))
+                    .collect();
+                self.post(
+                    client,
+                    webhook,
+                    json!({ "content": truncate(&text, 2000), "embeds": embeds }),
+                )
+                .await
+            }
+        }
+    }
+
+    /// Reports a run that was aborted before it could produce a summary.
+    pub async fn notify_error(&self, client: &HttpClient, error: &anyhow::Error) -> Result<()> {
+        let text = format!("spotify-dl run aborted: {error:#}");
+        match self {
+            Notifier::Telegram { token, chat } => {
+                self.post(
+                    client,
+                    &format!("https://api.telegram.org/bot{token}/sendMessage"),
+                    json!({ "chat_id": chat, "text": text }),
+                )
+                .await
+            }
+            Notifier::Discord { webhook } => {
+                self.post(client, webhook, json!({ "content": truncate(&text, 2000) }))
+                    .await
+            }
+        }
+    }
+
+    async fn post(&self, client: &HttpClient, url: &str, body: serde_json::Value) -> Result<()> {
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri(url)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Bytes::from(serde_json::to_vec(&body)?))?;
+        client.request_body(request).await?;
+        Ok(())
+    }
+}
+
+fn summary_text(summary: &RunSummary) -> String {
+    let mut text = format!(
+        "spotify-dl: {} downloaded, {} skipped, {} failed",
+        summary.downloaded.len(),
+        summary.skipped,
+        summary.failed.len()
+    );
+
+    if !summary.downloaded.is_empty() {
+        text.push_str("\n\nNew:");
+        for track in summary.downloaded.iter().take(MAX_LISTED_TRACKS) {
+            text.push_str(&format!("\n- {}", track.name));
+        }
+        push_remainder(&mut text, summary.downloaded.len());
+    }
+
+    if !summary.failed.is_empty() {
+        text.push_str("\n\nFailed:");
+        for track in summary.failed.iter().take(MAX_LISTED_TRACKS) {
+            match &track.error {
+                Some(error) => text.push_str(&format!("\n- {}: {}", track.name, error)),
+                None => text.push_str(&format!("\n- {}", track.name)),
+            }
+        }
+        push_remainder(&mut text, summary.failed.len());
+    }
+    text
+}
+
+fn push_remainder(text: &mut String, total: usize) {
+    if total > MAX_LISTED_TRACKS {
+        text.push_str(&format!("\n...and {} more", total - MAX_LISTED_TRACKS));
+    }
+}
+
+/// One cover per album among the newly downloaded tracks.
+fn new_album_covers(summary: &RunSummary) -> Vec<(String, String)> {
+    let mut covers: Vec<(String, String)> = Vec::new();
+    for track in &summary.downloaded {
+        if let Some(url) = &track.cover_url
+            && !covers.iter().any(|(_, seen)| seen == url)
+        {
+            let album = track.album.clone().unwrap_or_else(|| track.name.clone());
+            covers.push((album, url.clone()));
+        }
+    }
+    covers.truncate(MAX_COVERS);
+    covers
+}
+
+fn truncate(text: &str, max_chars: usize) -> String {
+    if text.chars().count() <= max_chars {
+        return text.to_string();
+    }
+    let mut truncated: String = text.chars().take(max_chars - 1).collect();
+    truncated.push('…');
+    truncated
+}
@@ -0,0 +1,89 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::Result;
+use librespot::core::SpotifyUri;
+use serde::Deserialize;
+
+use crate::track::{ArtistMetadata, TrackMetadata};
+
+/// Corrections for one track. Fields left out keep Spotify's metadata.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TrackOverride {
+    pub title: Option<String>,
+    pub artists: Option<Vec<String>>,
+    pub album: Option<String>,
+    pub genre: Option<String>,
+}
+
+/// Metadata corrections by track URI, read from a TOML file like
+///
+/// ```toml
+/// ["spotify:track:4uLU6hMCjMI75M1A2tKUQC"]
+/// title = "Never Gonna Give You Up"
+/// artists = ["Rick Astley"]
+/// genre = "Pop"
+/// ```
+///
+/// They are applied to the metadata before the file is named and tagged.
+#[derive(Debug, Default, Clone)]
+pub struct Overrides {
+    tracks: HashMap<SpotifyUri, TrackOverride>,
+}
+
+impl Overrides {
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)?;
+        Self::parse(&contents)
+    }
+
+    pub fn parse(contents: &str) -> Result<Self> {
+        let entries: HashMap<String, TrackOverride> = toml::from_str(contents)?;
+        let tracks = entries
+            .into_iter()
+            .map(|(uri, corrections)| {
+                let id = SpotifyUri::from_uri(&uri)
+                    .map_err(|_| anyhow::anyhow!("Unsupported track URI in overrides: {}", uri))?;
+                Ok((id, corrections))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Overrides { tracks })
+    }
+
+    pub fn len(&self) -> usize {
+        self.tracks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tracks.is_empty()
+    }
+
+    pub fn get(&self, id: &SpotifyUri) -> Option<&TrackOverride> {
+        self.tracks.get(id)
+    }
+
+    /// Replaces the overridden fields of the metadata, if there are
+    /// corrections for its track.
+    pub fn apply(&self, metadata: &mut TrackMetadata) {
+        let Some(corrections) = self.get(&metadata.id) else {
+            return;
+        };
+        tracing::info!("Applying metadata overrides to {}", metadata.id);
+        if let Some(title) = &corrections.title {
+            metadata.track_name = title.clone();
+        }
+        if let Some(artists) = &corrections.artists {
+            metadata.artists = artists
+                .iter()
+                .map(|name| ArtistMetadata { name: name.clone() })
+                .collect();
+        }
+        if let Some(album) = &corrections.album {
+            metadata.album.name = album.clone();
+        }
+        if let Some(genre) = &corrections.genre {
+            metadata.genre = Some(genre.clone());
+        }
+    }
+}
@@ -0,0 +1,100 @@
+use anyhow::Result;
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+use librespot::core::session::Session;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+use tokio::time::{Duration, timeout};
+
+use crate::encoder::{Format, Samples, get_stream_encoder};
+use crate::stream::{Stream, StreamEvent};
+use crate::track::Track;
+
+/// Streams a single track and writes it to `out` as it arrives: encoded in
+/// `format` chunk by chunk, or without a format as its decoded samples,
+/// interleaved 16 bit little endian stereo PCM at 44.1 kHz. Progress is
+/// drawn to stderr so `out` can be stdout.
+///
+/// A stalled stream can't be restarted once samples were written, so it
+/// fails the track instead.
+pub async fn pipe_track<W: AsyncWrite + Unpin>(
+    session: &Session,
+    track: Track,
+    format: Option<Format>,
+    out: &mut W,
+) -> Result<()> {
+    let Samples {
+        sample_rate,
+        channels,
+        ..
+    } = Samples::default();
+    let mut encoder = format
+        .map(|format| get_stream_encoder(format, sample_rate, channels))
+        .transpose()?;
+
+    let metadata = track.metadata(session).await?;
+    let artists = metadata
+        .artists
+        .iter()
+        .map(|artist| artist.name.as_str())
+        .collect::<Vec<_>>()
+        .join(", ");
+    let label = format!("{} - {}", artists, metadata.track_name);
+
+    let pb = ProgressBar::with_draw_target(
+        Some(metadata.approx_size() as u64),
+        ProgressDrawTarget::stderr(),
+    );
+    pb.enable_steady_tick(Duration::from_millis(100));
+    pb.set_style(
+        ProgressStyle::with_template(
+            "{spinner:.green} {msg} [{elapsed_precise}] [{wide_bar:.cyan/blue}] {bytes}/{total_bytes}",
+        )
+        // Infallible
+        .unwrap()
+        .progress_chars("#>-"),
+    );
+    pb.set_message(format!("Piping {}", label));
+
+    let mut channel = Stream::new(session.clone()).stream(track).await?;
+    let timeout_duration = Duration::from_secs(30);
+    loop {
+        match timeout(timeout_duration, channel.recv()).await {
+            Ok(Some(StreamEvent::Write { bytes, content, .. })) => {
+                pb.set_position(bytes as u64);
+                let output = match &mut encoder {
+                    Some(encoder) => encoder.encode(&content)?,
+                    None => content
+                        .iter()
+                        .flat_map(|&sample| ((sample >> 16) as i16).to_le_bytes())
+                        .collect(),
+                };
+                out.write_all(&output).await?;
+            }
+            Ok(Some(StreamEvent::Retry {
+                attempt,
+                max_attempts,
+            })) => {
+                pb.set_message(format!("Retrying ({}/{}) {}", attempt, max_attempts, label));
+            }
+            Ok(Some(StreamEvent::Error(e))) => {
+                pb.abandon_with_message(format!("Failed {}", label));
+                return Err(anyhow::anyhow!("Streaming error: {:?}", e));
+            }
+            Ok(Some(StreamEvent::Finished)) | Ok(None) => {
+                if let Some(encoder) = &mut encoder {
+                    out.write_all(&encoder.finish()?).await?;
+                }
+                break;
+            }
+            Err(_) => {
+                pb.abandon_with_message(format!("Stalled {}", label));
+                return Err(anyhow::anyhow!(
+                    "No data received for {:?}, the stream stalled",
+                    timeout_duration
+                ));
+            }
+        }
+    }
+    out.flush().await?;
+    pb.finish_with_message(format!("Piped {}", label));
+    Ok(())
+}
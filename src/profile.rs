@@ -0,0 +1,78 @@
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::Result;
+
+use crate::download::DownloadOptions;
+
+/// A named preset of the scheduler settings, from slow and close to how the
+/// Spotify client streams to as fast as the connection allows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Profile {
+    /// One track at a time with long, irregular pauses and few requests.
+    Stealth,
+    /// Two tracks at a time with short, irregular pauses.
+    Balanced,
+    /// Eight tracks at a time without pauses or a request limit.
+    Fast,
+}
+
+impl FromStr for Profile {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "stealth" => Ok(Profile::Stealth),
+            "balanced" => Ok(Profile::Balanced),
+            "fast" => Ok(Profile::Fast),
+            _ => Err(anyhow::anyhow!(
+                "Unsupported profile, expected stealth, balanced or fast"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Profile::Stealth => "stealth",
+            Profile::Balanced => "balanced",
+            Profile::Fast => "fast",
+        })
+    }
+}
+
+impl Profile {
+    /// Tracks downloaded at the same time.
+    pub fn parallel(self) -> usize {
+        match self {
+            Profile::Stealth => 1,
+            Profile::Balanced => 2,
+            Profile::Fast => 8,
+        }
+    }
+
+    /// Sets the pauses, backoff and request limit of the profile. The
+    /// parallelism is passed to [`DownloadOptions::new`] instead, so `-t`
+    /// can still override it.
+    pub fn apply(self, options: &mut DownloadOptions) {
+        let (delay_factor, delay_jitter, retry_backoff, tracks_per_minute) = match self {
+            Profile::Stealth => (0.3, 0.5, Duration::from_secs(30), Some(10)),
+            Profile::Balanced => (0.1, 0.5, Duration::from_secs(15), Some(30)),
+            Profile::Fast => (0.0, 0.0, Duration::from_secs(5), None),
+        };
+        options.delay_factor = delay_factor;
+        options.delay_jitter = delay_jitter;
+        options.retry_backoff = retry_backoff;
+        options.tracks_per_minute = tracks_per_minute;
+    }
+}
+
+/// The pause after a track of `duration`: `factor` times its duration,
+/// moved by up to `jitter` times that either way, where `roll` in `[0, 1)`
+/// picks how far.
+pub fn track_delay(duration: Duration, factor: f64, jitter: f64, roll: f64) -> Duration {
+    let spread = 1.0 + jitter * (2.0 * roll - 1.0);
+    duration.mul_f64((factor * spread).max(0.0))
+}
@@ -0,0 +1,18 @@
+use console::Alignment;
+
+/// Display columns of progress bar messages by default, which leaves room
+/// for the bar and its counters on a 120 column terminal.
+pub const DEFAULT_LABEL_WIDTH: usize = 50;
+
+/// `text` fit into exactly `width` display columns: cut with an ellipsis
+/// when it is wider, padded with spaces when it is narrower, so the bars
+/// line up. CJK characters and most emoji take two columns and are never
+/// cut in half. Line breaks and tabs become spaces.
+pub fn fit_label(text: &str, width: usize) -> String {
+    let text: String = text
+        .chars()
+        .map(|c| if c.is_control() { ' ' } else { c })
+        .collect();
+    let truncated = console::truncate_str(&text, width, "…");
+    console::pad_str(&truncated, width, Alignment::Left, None).into_owned()
+}
@@ -0,0 +1,198 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::path::Path;
+
+use anyhow::Result;
+use base64::Engine;
+use bytes::Bytes;
+use http::{Method, Request};
+use librespot::core::http_client::HttpClient;
+
+use crate::download::{RunSummary, TrackReport};
+use crate::sync::PlaylistDiff;
+
+/// Edge length of the cover thumbnails embedded in the report.
+const THUMBNAIL_SIZE: u32 = 64;
+
+const STYLE: &str = "body{font-family:system-ui,sans-serif;margin:2em;color:#222}\
+table{border-collapse:collapse;width:100%;margin-bottom:2em}\
+td,th{padding:4px 8px;border-bottom:1px solid #ddd;text-align:left;vertical-align:middle}\
+img{width:48px;height:48px;display:block}\
+.downloaded{color:#1a7f37}.failed{color:#cf222e}.error{font-family:monospace;font-size:90%}";
+
+/// Writes a single HTML page summarizing a run: every downloaded and failed
+/// track with its album cover, the reasons of the failures, the playlist
+/// diffs of a sync and the copies to replicas. Covers are embedded as small
+/// thumbnails, so the page has no outside references and can be mailed or
+/// served as it is.
+pub async fn write_html_report(
+    path: &Path,
+    client: &HttpClient,
+    summary: &RunSummary,
+    diffs: &[&PlaylistDiff],
+) -> Result<()> {
+    let thumbnails = fetch_thumbnails(client, summary).await;
+    let generated = chrono::Local::now().format("%Y-%m-%d %H:%M").to_string();
+    let html = render_html(summary, diffs, &thumbnails, &generated);
+    tokio::fs::write(path, html).await?;
+    Ok(())
+}
+
+/// The report itself. `thumbnails` maps cover URLs to the image sources to
+/// show for them; tracks without an entry get no cover.
+pub fn render_html(
+    summary: &RunSummary,
+    diffs: &[&PlaylistDiff],
+    thumbnails: &HashMap<String, String>,
+    generated: &str,
+) -> String {
+    let mut html = String::new();
+    let _ = write!(
+        html,
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\">\
+         <title>spotify-dl run {generated}</title><style>{STYLE}</style></head><body>\n\
+         <h1>spotify-dl run {generated}</h1>\n\
+         <p>{} downloaded, {} skipped, {} failed</p>\n",
+        summary.downloaded.len(),
+        summary.skipped,
+        summary.failed.len()
+    );
+    if summary.skipped > 0 {
+        let _ = writeln!(html, "<p>Skipped: {}</p>", summary.skip_reasons);
+    }
+
+    if !summary.downloaded.is_empty() || !summary.failed.is_empty() {
+        html.push_str(
+            "<h2>Tracks</h2>\n<table><tr><th></th><th>Track</th><th>Album</th>\
+             <th>Status</th><th>Reason</th></tr>\n",
+        );
+        let tracks = summary
+            .failed
+            .iter()
+            .map(|track| (track, "failed"))
+            .chain(summary.downloaded.iter().map(|track| (track, "downloaded")));
+        for (track, status) in tracks {
+            push_track_row(&mut html, track, status, thumbnails);
+        }
+        html.push_str("</table>\n");
+    }
+
+    if !diffs.is_empty() {
+        html.push_str("<h2>Playlist changes</h2>\n");
+        for diff in diffs {
+            let _ = writeln!(html, "<h3>{}</h3>", escape(&diff.to_string()));
+            for (label, tracks) in [("Added", &diff.added), ("Removed", &diff.removed)] {
+                if tracks.is_empty() || diff.first_sync {
+                    continue;
+                }
+                let _ = write!(html, "<p>{label}:</p><ul>");
+                for track in tracks {
+                    let _ = write!(html, "<li>{}</li>", escape(track));
+                }
+                html.push_str("</ul>\n");
+            }
+        }
+    }
+
+    if !summary.replicas.is_empty() {
+        html.push_str(
+            "<h2>Replicas</h2>\n<table><tr><th>Destination</th><th>Copied</th>\
+             <th>Failed</th></tr>\n",
+        );
+        for replica in &summary.replicas {
+            let _ = writeln!(
+                html,
+                "<tr><td>{}</td><td>{}</td><td>{}</td></tr>",
+                escape(&replica.destination.display().to_string()),
+                replica.copied,
+                replica.failed.len()
+            );
+            for failure in &replica.failed {
+                let _ = writeln!(
+                    html,
+                    "<tr><td colspan=\"3\" class=\"error\">{}: {}</td></tr>",
+                    escape(&failure.file.display().to_string()),
+                    escape(&failure.error)
+                );
+            }
+        }
+        html.push_str("</table>\n");
+    }
+
+    html.push_str("</body></html>\n");
+    html
+}
+
+fn push_track_row(
+    html: &mut String,
+    track: &TrackReport,
+    status: &str,
+    thumbnails: &HashMap<String, String>,
+) {
+    let cover = track
+        .cover_url
+        .as_ref()
+        .and_then(|url| thumbnails.get(url))
+        .map(|source| format!("<img src=\"{}\" alt=\"\">", escape(source)))
+        .unwrap_or_default();
+    let _ = writeln!(
+        html,
+        "<tr><td>{cover}</td><td title=\"{}\">{}</td><td>{}</td>\
+         <td class=\"{status}\">{status}</td><td class=\"error\">{}</td></tr>",
+        escape(&track.uri),
+        escape(&track.name),
+        escape(track.album.as_deref().unwrap_or_default()),
+        escape(track.error.as_deref().unwrap_or_default())
+    );
+}
+
+/// Downloads each album cover once and shrinks it to a JPEG data URI.
+/// Covers that fail to load are left out.
+async fn fetch_thumbnails(client: &HttpClient, summary: &RunSummary) -> HashMap<String, String> {
+    let mut thumbnails = HashMap::new();
+    let tracks = summary.downloaded.iter().chain(&summary.failed);
+    for url in tracks.filter_map(|track| track.cover_url.as_ref()) {
+        if thumbnails.contains_key(url) {
+            continue;
+        }
+        match fetch_thumbnail(client, url).await {
+            Ok(thumbnail) => {
+                thumbnails.insert(url.clone(), thumbnail);
+            }
+            Err(err) => tracing::warn!(error = %err, "Failed to load cover {} for the report", url),
+        }
+    }
+    thumbnails
+}
+
+async fn fetch_thumbnail(client: &HttpClient, url: &str) -> Result<String> {
+    let request = Request::builder()
+        .method(Method::GET)
+        .uri(url)
+        .body(Bytes::new())?;
+    let cover = client.request_body(request).await?;
+    let thumbnail = image::load_from_memory(&cover)?
+        .thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE)
+        .to_rgb8();
+    let mut jpeg = Vec::new();
+    image::codecs::jpeg::JpegEncoder::new(&mut jpeg).encode_image(&thumbnail)?;
+    Ok(format!(
+        "data:image/jpeg;base64,{}",
+        base64::engine::general_purpose::STANDARD.encode(jpeg)
+    ))
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
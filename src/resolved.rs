@@ -0,0 +1,156 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::Result;
+use librespot::core::SpotifyUri;
+use librespot::core::session::Session;
+use serde::{Deserialize, Serialize};
+
+use crate::input;
+use crate::track::{Track, get_tracks};
+
+/// Name of the file in the destination that keeps the resolved inputs.
+pub const RESOLVED_FILE: &str = ".spotify-dl-resolved.json";
+
+/// How long a resolved input is reused. Spotify only tells a playlist's
+/// revision together with its tracks, so a cached list can't be checked
+/// without fetching it again; instead it is trusted for a while, which
+/// covers an estimate followed by the download or a run started again
+/// right after it stopped.
+pub const MAX_AGE: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Debug, Serialize, Deserialize)]
+struct StoredTrack {
+    uri: String,
+    #[serde(default)]
+    playlist: Option<String>,
+    #[serde(default)]
+    album: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ResolvedInput {
+    /// Seconds since the epoch.
+    resolved_at: u64,
+    tracks: Vec<StoredTrack>,
+}
+
+/// The tracks each input URI resolved to on earlier runs, so they don't
+/// have to be resolved again right away.
+pub struct ResolvedInputs {
+    path: PathBuf,
+    inputs: HashMap<String, ResolvedInput>,
+}
+
+impl ResolvedInputs {
+    pub fn load(path: PathBuf) -> Self {
+        let inputs = fs::read_to_string(&path)
+            .ok()
+            .and_then(|contents| serde_json::from_str(&contents).ok())
+            .unwrap_or_default();
+        ResolvedInputs { path, inputs }
+    }
+
+    /// The tracks `input` resolved to, unless that was longer ago than
+    /// [`MAX_AGE`] before `now`.
+    pub fn lookup(&self, input: &str, now: SystemTime) -> Option<Vec<Track>> {
+        let resolved = self
+            .inputs
+            .get(input)
+            .filter(|resolved| resolved.is_fresh(now))?;
+        resolved.tracks.iter().map(StoredTrack::to_track).collect()
+    }
+
+    pub fn insert(&mut self, input: &str, tracks: &[Track], now: SystemTime) {
+        let resolved_at = now
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or_default();
+        let tracks = tracks.iter().filter_map(StoredTrack::from_track).collect();
+        self.inputs.insert(
+            input.to_string(),
+            ResolvedInput {
+                resolved_at,
+                tracks,
+            },
+        );
+    }
+
+    /// Resolves the inputs like [`get_tracks`], reusing recent results
+    /// unless `refresh` is set, and remembers the new ones.
+    pub async fn get_tracks(
+        &mut self,
+        inputs: Vec<String>,
+        session: &Session,
+        refresh: bool,
+    ) -> Result<Vec<Track>> {
+        let now = SystemTime::now();
+        let mut tracks = Vec::new();
+        let mut resolved_any = false;
+        // The same link pasted with other query parameters, or a short link
+        // to it, is the same input.
+        for input in input::canonical_uris(&inputs, session).await? {
+            match self.lookup(&input, now).filter(|_| !refresh) {
+                Some(cached) => {
+                    tracing::info!("Reusing the {} tracks {} resolved to", cached.len(), input);
+                    tracks.extend(cached);
+                }
+                None => {
+                    let resolved = get_tracks(vec![input.clone()], session).await?;
+                    self.insert(&input, &resolved, now);
+                    resolved_any = true;
+                    tracks.extend(resolved);
+                }
+            }
+        }
+        if resolved_any && let Err(err) = self.save() {
+            tracing::warn!(error = %err, "Failed to save the resolved inputs");
+        }
+        Ok(tracks)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let now = SystemTime::now();
+        let fresh: HashMap<&String, &ResolvedInput> = self
+            .inputs
+            .iter()
+            .filter(|(_, resolved)| resolved.is_fresh(now))
+            .collect();
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&self.path, serde_json::to_string(&fresh)?)?;
+        Ok(())
+    }
+}
+
+impl ResolvedInput {
+    fn is_fresh(&self, now: SystemTime) -> bool {
+        let resolved_at = UNIX_EPOCH + Duration::from_secs(self.resolved_at);
+        now.duration_since(resolved_at)
+            .is_ok_and(|age| age <= MAX_AGE)
+    }
+}
+
+impl StoredTrack {
+    fn from_track(track: &Track) -> Option<Self> {
+        Some(StoredTrack {
+            uri: track.id.to_uri().ok()?,
+            playlist: track.playlist().and_then(|uri| uri.to_uri().ok()),
+            album: track.album().and_then(|uri| uri.to_uri().ok()),
+        })
+    }
+
+    fn to_track(&self) -> Option<Track> {
+        let id = SpotifyUri::from_uri(&self.uri).ok()?;
+        let playlist = self.playlist.as_deref().map(SpotifyUri::from_uri);
+        let album = self.album.as_deref().map(SpotifyUri::from_uri);
+        Some(match (playlist, album) {
+            (Some(Ok(playlist)), _) => Track::from_playlist(id, playlist),
+            (_, Some(Ok(album))) => Track::from_album(id, album),
+            _ => Track::from_id(id),
+        })
+    }
+}
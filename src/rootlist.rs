@@ -0,0 +1,133 @@
+use anyhow::Result;
+use librespot::core::SpotifyUri;
+use librespot::core::session::Session;
+use librespot::protocol::playlist4_external::SelectedListContent;
+use protobuf::Message;
+use std::path::PathBuf;
+
+use crate::utils::clean_invalid_characters;
+
+/// A folder of the user's playlist library. The root folder has no name.
+#[derive(Debug, Default)]
+pub struct Folder {
+    pub name: String,
+    pub playlists: Vec<SpotifyUri>,
+    pub folders: Vec<Folder>,
+}
+
+impl Folder {
+    /// Every playlist in this folder and its subfolders, with the relative
+    /// directory its folders map to.
+    pub fn playlists_by_dir(&self) -> Vec<(PathBuf, SpotifyUri)> {
+        let mut playlists = Vec::new();
+        self.collect(PathBuf::new(), &mut playlists);
+        playlists
+    }
+
+    fn collect(&self, dir: PathBuf, playlists: &mut Vec<(PathBuf, SpotifyUri)>) {
+        for playlist in &self.playlists {
+            playlists.push((dir.clone(), playlist.clone()));
+        }
+        for folder in &self.folders {
+            folder.collect(dir.join(dir_name(&folder.name)), playlists);
+        }
+    }
+}
+
+/// Name of the directory a folder or playlist is mirrored to.
+pub fn dir_name(name: &str) -> String {
+    let name = clean_invalid_characters(name);
+    match name.trim() {
+        "" | "." | ".." => "_".to_string(),
+        name => name.to_string(),
+    }
+}
+
+/// Fetches the logged in user's playlists together with the folders they
+/// are organized in.
+pub async fn fetch_rootlist(session: &Session) -> Result<Folder> {
+    const PAGE: usize = 120;
+    let mut uris = Vec::new();
+    loop {
+        let response = session
+            .spclient()
+            .get_rootlist(uris.len(), Some(PAGE))
+            .await?;
+        let content = SelectedListContent::parse_from_bytes(&response)?;
+        let items = content
+            .contents
+            .as_ref()
+            .map(|contents| contents.items.as_slice())
+            .unwrap_or_default();
+        uris.extend(items.iter().filter_map(|item| item.uri.clone()));
+        let length = content.length.unwrap_or_default().max(0) as usize;
+        if items.is_empty() || uris.len() >= length {
+            break;
+        }
+    }
+    Ok(parse_rootlist(&uris))
+}
+
+/// Builds the folder tree from the flat rootlist, where folders are
+/// delimited by `spotify:start-group:<id>:<name>` and
+/// `spotify:end-group:<id>` entries.
+pub fn parse_rootlist(uris: &[String]) -> Folder {
+    let mut stack = vec![Folder::default()];
+    for uri in uris {
+        if let Some(group) = uri.strip_prefix("spotify:start-group:") {
+            let name = group.split_once(':').map_or(group, |(_, name)| name);
+            stack.push(Folder {
+                name: decode_name(name),
+                ..Default::default()
+            });
+        } else if uri.starts_with("spotify:end-group:") {
+            if stack.len() > 1
+                && let Some(folder) = stack.pop()
+                && let Some(parent) = stack.last_mut()
+            {
+                parent.folders.push(folder);
+            }
+        } else if let Ok(playlist @ SpotifyUri::Playlist { .. }) = SpotifyUri::from_uri(uri)
+            && let Some(folder) = stack.last_mut()
+        {
+            folder.playlists.push(playlist);
+        }
+    }
+    // Close folders whose end was cut off.
+    while stack.len() > 1 {
+        if let Some(folder) = stack.pop()
+            && let Some(parent) = stack.last_mut()
+        {
+            parent.folders.push(folder);
+        }
+    }
+    stack.pop().unwrap_or_default()
+}
+
+/// Folder names are URL encoded, with `+` for spaces.
+fn decode_name(name: &str) -> String {
+    let bytes = name.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' => {
+                let escaped = bytes
+                    .get(i + 1..i + 3)
+                    .and_then(|hex| std::str::from_utf8(hex).ok())
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+                match escaped {
+                    Some(byte) => {
+                        decoded.push(byte);
+                        i += 2;
+                    }
+                    None => decoded.push(b'%'),
+                }
+            }
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
@@ -0,0 +1,82 @@
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::Result;
+use chrono::{Local, NaiveTime, Timelike};
+
+/// A daily time window, in local time, during which tracks are downloaded.
+/// Windows may cross midnight, e.g. `22:00-06:00`. Equal start and end times
+/// cover the whole day.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScheduleWindow {
+    start: NaiveTime,
+    end: NaiveTime,
+}
+
+impl FromStr for ScheduleWindow {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let unsupported = || anyhow::anyhow!("Unsupported schedule window, expected HH:MM-HH:MM");
+        let (start, end) = s.split_once('-').ok_or_else(unsupported)?;
+        let parse = |time: &str| NaiveTime::parse_from_str(time.trim(), "%H:%M");
+        Ok(ScheduleWindow {
+            start: parse(start).map_err(|_| unsupported())?,
+            end: parse(end).map_err(|_| unsupported())?,
+        })
+    }
+}
+
+impl fmt::Display for ScheduleWindow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}-{}",
+            self.start.format("%H:%M"),
+            self.end.format("%H:%M")
+        )
+    }
+}
+
+impl ScheduleWindow {
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        if self.start <= self.end {
+            self.start == self.end || (self.start <= time && time < self.end)
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
+
+    /// How long it is from `time` until the window opens, zero when it is
+    /// open.
+    pub fn until_open(&self, time: NaiveTime) -> Duration {
+        if self.contains(time) {
+            return Duration::ZERO;
+        }
+        let seconds = |time: NaiveTime| time.num_seconds_from_midnight() as u64;
+        let day = 24 * 60 * 60;
+        Duration::from_secs((seconds(self.start) + day - seconds(time)) % day)
+    }
+
+    /// Sleeps until the window is open. Checks the clock again after waking
+    /// up, so changes to the local time (e.g. daylight saving) are followed.
+    pub async fn wait(&self) {
+        let mut announced = false;
+        loop {
+            let wait = self.until_open(Local::now().time());
+            if wait.is_zero() {
+                return;
+            }
+            if !announced {
+                println!(
+                    "Outside the schedule window {}, pausing downloads until {}",
+                    self,
+                    self.start.format("%H:%M")
+                );
+                announced = true;
+            }
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
@@ -0,0 +1,338 @@
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use bytes::Bytes;
+use http::{HeaderMap, Method, Response, StatusCode, header};
+use http_body_util::{BodyExt, Full};
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper_util::rt::TokioIo;
+use librespot::core::session::Session;
+use serde::{Deserialize, Serialize};
+use tokio::net::TcpListener;
+use tokio::sync::Notify;
+
+use crate::download::{DownloadOptions, Downloader, TrackOutcome};
+use crate::history::{HISTORY_FILE, PlaylistHistory};
+use crate::input::find_links;
+use crate::library::{LIBRARY_FILE, LibraryIndex};
+use crate::track::get_tracks;
+
+/// Finished downloads kept for the page, older ones are dropped.
+const MAX_FINISHED: usize = 50;
+
+/// Largest body an enqueue request may have.
+const MAX_BODY: usize = 4096;
+
+const PAGE: &str = include_str!("serve.html");
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Queued,
+    Resolving,
+    Downloading,
+    Done,
+    Failed,
+}
+
+/// The body of a POST to `/api/enqueue`.
+#[derive(Debug, Deserialize)]
+struct EnqueueRequest {
+    link: String,
+}
+
+/// A link pasted into the page and what became of it.
+#[derive(Debug, Clone, Serialize)]
+pub struct Entry {
+    pub id: usize,
+    pub input: String,
+    pub status: Status,
+    /// Tracks the input resolved to, once it was resolved.
+    pub total: usize,
+    pub downloaded: usize,
+    pub skipped: usize,
+    pub failed: usize,
+    pub error: Option<String>,
+}
+
+#[derive(Default)]
+struct Entries {
+    next_id: usize,
+    pending: VecDeque<Entry>,
+    active: Option<Entry>,
+    finished: VecDeque<Entry>,
+}
+
+/// The downloads of `serve`, worked through one at a time in the order they
+/// were added.
+#[derive(Default)]
+pub struct WebQueue {
+    entries: Mutex<Entries>,
+    added: Notify,
+}
+
+/// What the page shows, as served on `/api/state`.
+#[derive(Debug, Serialize)]
+pub struct QueueState {
+    pub active: Option<Entry>,
+    pub queued: Vec<Entry>,
+    /// Most recent first.
+    pub finished: Vec<Entry>,
+}
+
+impl WebQueue {
+    /// Adds a Spotify URI or URL, or text with links in it, to the queue and
+    /// returns its id.
+    pub fn enqueue(&self, input: &str) -> Result<usize> {
+        let input = input.trim();
+        if find_links(input).is_empty() {
+            return Err(anyhow::anyhow!(
+                "Unsupported link, expected a Spotify URI, open.spotify.com or spotify.link URL"
+            ));
+        }
+        let mut entries = self.entries.lock().unwrap();
+        let id = entries.next_id;
+        entries.next_id += 1;
+        entries.pending.push_back(Entry {
+            id,
+            input: input.to_string(),
+            status: Status::Queued,
+            total: 0,
+            downloaded: 0,
+            skipped: 0,
+            failed: 0,
+            error: None,
+        });
+        drop(entries);
+        self.added.notify_one();
+        Ok(id)
+    }
+
+    pub fn state(&self) -> QueueState {
+        let entries = self.entries.lock().unwrap();
+        QueueState {
+            active: entries.active.clone(),
+            queued: entries.pending.iter().cloned().collect(),
+            finished: entries.finished.iter().cloned().collect(),
+        }
+    }
+
+    /// Waits for the next queued input and makes it the active one.
+    async fn next(&self) -> String {
+        loop {
+            {
+                let mut entries = self.entries.lock().unwrap();
+                if let Some(mut entry) = entries.pending.pop_front() {
+                    entry.status = Status::Resolving;
+                    let input = entry.input.clone();
+                    entries.active = Some(entry);
+                    return input;
+                }
+            }
+            self.added.notified().await;
+        }
+    }
+
+    fn update_active(&self, update: impl FnOnce(&mut Entry)) {
+        if let Some(entry) = self.entries.lock().unwrap().active.as_mut() {
+            update(entry);
+        }
+    }
+
+    fn finish_active(&self, error: Option<String>) {
+        let mut entries = self.entries.lock().unwrap();
+        if let Some(mut entry) = entries.active.take() {
+            entry.status = match error {
+                Some(_) => Status::Failed,
+                None => Status::Done,
+            };
+            entry.error = error;
+            entries.finished.push_front(entry);
+            entries.finished.truncate(MAX_FINISHED);
+        }
+    }
+
+    /// Answers a request to the web UI: the page itself, the queue state
+    /// as JSON, or a link to enqueue as the JSON body of a POST. Enqueueing
+    /// takes `application/json` only and refuses requests from other
+    /// origins, so another site open in the browser can't post a form or a
+    /// plain `fetch` to the page and queue downloads.
+    pub fn route(
+        &self,
+        method: &Method,
+        path: &str,
+        headers: &HeaderMap,
+        body: &[u8],
+    ) -> Response<Full<Bytes>> {
+        match (method, path) {
+            (&Method::GET, "/") => respond(StatusCode::OK, "text/html; charset=utf-8", PAGE),
+            (&Method::GET, "/api/state") => match serde_json::to_string(&self.state()) {
+                Ok(json) => respond(StatusCode::OK, "application/json", json),
+                Err(err) => respond(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "text/plain",
+                    err.to_string(),
+                ),
+            },
+            (&Method::POST, "/api/enqueue") => {
+                if !is_json(headers) {
+                    return respond(
+                        StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                        "text/plain",
+                        "Unsupported content type, expected application/json",
+                    );
+                }
+                if !is_same_origin(headers) {
+                    return respond(StatusCode::FORBIDDEN, "text/plain", "Cross-origin request");
+                }
+                let request: EnqueueRequest = match serde_json::from_slice(body) {
+                    Ok(request) => request,
+                    Err(err) => {
+                        return respond(StatusCode::BAD_REQUEST, "text/plain", err.to_string());
+                    }
+                };
+                match self.enqueue(&request.link) {
+                    Ok(id) => respond(StatusCode::ACCEPTED, "application/json", id.to_string()),
+                    Err(err) => respond(StatusCode::BAD_REQUEST, "text/plain", err.to_string()),
+                }
+            }
+            _ => respond(StatusCode::NOT_FOUND, "text/plain", "Not found"),
+        }
+    }
+}
+
+fn is_json(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(';').next())
+        .is_some_and(|value| value.trim().eq_ignore_ascii_case("application/json"))
+}
+
+/// Whether the request has no `Origin` or one naming the host it was sent
+/// to, as the page's own requests do.
+fn is_same_origin(headers: &HeaderMap) -> bool {
+    let Some(origin) = headers.get(header::ORIGIN) else {
+        return true;
+    };
+    let origin_host = origin
+        .to_str()
+        .ok()
+        .and_then(|origin| origin.split_once("://"))
+        .map(|(_, host)| host);
+    let host = headers
+        .get(header::HOST)
+        .and_then(|host| host.to_str().ok());
+    matches!((origin_host, host), (Some(origin), Some(host)) if origin.eq_ignore_ascii_case(host))
+}
+
+fn respond(
+    status: StatusCode,
+    content_type: &str,
+    body: impl Into<Bytes>,
+) -> Response<Full<Bytes>> {
+    let mut response = Response::new(Full::new(body.into()));
+    *response.status_mut() = status;
+    if let Ok(content_type) = content_type.parse() {
+        response
+            .headers_mut()
+            .insert(header::CONTENT_TYPE, content_type);
+    }
+    response
+}
+
+/// Serves the web UI on `addr` and downloads whatever is enqueued through it
+/// into `options.destination`, until the process is stopped.
+pub async fn serve(
+    addr: SocketAddr,
+    queue: Arc<WebQueue>,
+    session: Session,
+    options: DownloadOptions,
+) -> Result<()> {
+    let listener = TcpListener::bind(addr).await?;
+    println!("Serving the web UI on http://{}", listener.local_addr()?);
+    tokio::spawn(download_enqueued(Arc::clone(&queue), session, options));
+
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let queue = Arc::clone(&queue);
+        tokio::spawn(async move {
+            let service = service_fn(move |request: http::Request<hyper::body::Incoming>| {
+                let queue = Arc::clone(&queue);
+                async move {
+                    let (parts, body) = request.into_parts();
+                    let body = http_body_util::Limited::new(body, MAX_BODY)
+                        .collect()
+                        .await
+                        .map(|collected| collected.to_bytes())
+                        .unwrap_or_default();
+                    Ok::<_, hyper::Error>(queue.route(
+                        &parts.method,
+                        parts.uri.path(),
+                        &parts.headers,
+                        &body,
+                    ))
+                }
+            });
+            if let Err(err) = http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), service)
+                .await
+            {
+                tracing::debug!(error = %err, "Web UI connection failed");
+            }
+        });
+    }
+}
+
+async fn download_enqueued(queue: Arc<WebQueue>, session: Session, options: DownloadOptions) {
+    loop {
+        let input = queue.next().await;
+        let error = download_input(&queue, &session, &options, input)
+            .await
+            .err()
+            .map(|err| err.to_string());
+        queue.finish_active(error);
+    }
+}
+
+async fn download_input(
+    queue: &Arc<WebQueue>,
+    session: &Session,
+    options: &DownloadOptions,
+    input: String,
+) -> Result<()> {
+    let tracks = get_tracks(vec![input], session).await?;
+    queue.update_active(|entry| {
+        entry.status = Status::Downloading;
+        entry.total = tracks.len();
+    });
+
+    let history = tracks
+        .iter()
+        .any(|track| track.playlist().is_some())
+        .then(|| {
+            let history = PlaylistHistory::load(options.destination.join(HISTORY_FILE));
+            Arc::new(tokio::sync::Mutex::new(history))
+        });
+    let library = LibraryIndex::load(options.destination.join(LIBRARY_FILE));
+    let listener_queue = Arc::clone(queue);
+    Downloader::new(
+        session.clone(),
+        history,
+        Arc::new(tokio::sync::Mutex::new(library)),
+    )
+    .with_outcome_listener(Box::new(move |_, outcome| {
+        listener_queue.update_active(|entry| match outcome {
+            TrackOutcome::Downloaded => entry.downloaded += 1,
+            TrackOutcome::Skipped => entry.skipped += 1,
+            TrackOutcome::Failed => entry.failed += 1,
+            TrackOutcome::Deferred => {}
+        });
+    }))
+    .download_tracks(tracks, options)
+    .await?;
+    Ok(())
+}
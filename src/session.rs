@@ -1,3 +1,5 @@
+use std::path::{Path, PathBuf};
+
 use anyhow::Result;
 use librespot::core::cache::Cache;
 use librespot::core::config::SessionConfig;
@@ -7,10 +9,30 @@ use librespot::oauth::OAuthClientBuilder;
 
 const SPOTIFY_CLIENT_ID: &str = "65b708073fc0480ea92a077233ca87bd";
 const SPOTIFY_REDIRECT_URI: &str = "http://127.0.0.1:8898/login";
+/// Directory of the audio cache, next to the credentials.
+const AUDIO_CACHE_DIR: &str = "audio";
+/// Beyond this size, the least recently used audio files are evicted.
+const AUDIO_CACHE_SIZE_LIMIT: u64 = 4 * 1024 * 1024 * 1024;
+
+fn credentials_store() -> Option<PathBuf> {
+    dirs::home_dir().map(|p| p.join(".spotify-dl"))
+}
 
-pub async fn create_session() -> Result<Session> {
-    let credentials_store = dirs::home_dir().map(|p| p.join(".spotify-dl"));
-    let cache = Cache::new(credentials_store, None, None, None)?;
+/// Where librespot keeps the encrypted audio files it streamed, so a track
+/// downloaded again, in another format or after a failed encode, is read
+/// from disk instead of streamed from Spotify.
+pub fn audio_cache_dir() -> Option<PathBuf> {
+    credentials_store().map(|p| p.join(AUDIO_CACHE_DIR))
+}
+
+pub async fn create_session(audio_cache: bool) -> Result<Session> {
+    let audio_location = audio_cache.then(audio_cache_dir).flatten();
+    let cache = Cache::new(
+        credentials_store(),
+        None,
+        audio_location,
+        Some(AUDIO_CACHE_SIZE_LIMIT),
+    )?;
 
     let session_config = SessionConfig::default();
 
@@ -26,6 +48,13 @@ pub async fn create_session() -> Result<Session> {
     Ok(session)
 }
 
+/// A session that never connects, for downloads that only stream simulated
+/// audio.
+#[cfg(feature = "simulate")]
+pub fn offline_session() -> Session {
+    Session::new(SessionConfig::default(), None)
+}
+
 fn load_credentials() -> Result<Credentials> {
     OAuthClientBuilder::new(SPOTIFY_CLIENT_ID, SPOTIFY_REDIRECT_URI, vec!["streaming"])
         .build()
@@ -34,3 +63,27 @@ fn load_credentials() -> Result<Credentials> {
         .map(Credentials::with_access_token)
         .map_err(Into::into)
 }
+
+/// Deletes the cached audio files and returns how many bytes they took.
+pub fn clear_audio_cache() -> Result<u64> {
+    let Some(dir) = audio_cache_dir().filter(|dir| dir.exists()) else {
+        return Ok(0);
+    };
+    let size = dir_size(&dir)?;
+    std::fs::remove_dir_all(&dir)?;
+    Ok(size)
+}
+
+fn dir_size(dir: &Path) -> Result<u64> {
+    let mut size = 0;
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        size += if metadata.is_dir() {
+            dir_size(&entry.path())?
+        } else {
+            metadata.len()
+        };
+    }
+    Ok(size)
+}
@@ -0,0 +1,135 @@
+use std::time::Duration;
+
+use anyhow::Result;
+
+use crate::encoder::Samples;
+
+/// How far around each split point the quietest spot is looked for.
+const SEARCH_WINDOW: Duration = Duration::from_secs(20);
+/// Length of the stretches of audio compared for loudness.
+const QUIET_WINDOW: Duration = Duration::from_millis(250);
+/// Length of the parts when only `--split-longer-than` is given.
+pub const DEFAULT_SPLIT_EVERY: Duration = Duration::from_secs(10 * 60);
+/// The shortest `--split-every`, so split points never cross each other.
+pub const MIN_SPLIT_EVERY: Duration = Duration::from_secs(60);
+
+/// A length as seconds, or a number followed by `s`, `m` or `h`, e.g. `30m`.
+pub fn parse_length(s: &str) -> Result<Duration> {
+    let s = s.trim();
+    let (number, unit) = match s.find(|c: char| !c.is_ascii_digit()) {
+        Some(index) => s.split_at(index),
+        None => (s, "s"),
+    };
+    let seconds = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        _ => 0,
+    };
+    number
+        .parse::<u64>()
+        .ok()
+        .filter(|&number| number > 0 && seconds > 0)
+        .map(|number| Duration::from_secs(number * seconds))
+        .ok_or(anyhow::anyhow!(
+            "Unsupported length, expected e.g. 90s, 30m or 1h"
+        ))
+}
+
+/// A `--split-every` length, which can't be under [`MIN_SPLIT_EVERY`].
+pub fn parse_split_every(s: &str) -> Result<Duration> {
+    let every = parse_length(s)?;
+    if every < MIN_SPLIT_EVERY {
+        return Err(anyhow::anyhow!(
+            "Unsupported split length, the minimum is 1m"
+        ));
+    }
+    Ok(every)
+}
+
+/// How many files a track of `duration` is split into, one per started
+/// `every`.
+pub fn part_count(duration: Duration, every: Duration) -> usize {
+    duration.as_millis().div_ceil(every.as_millis().max(1)) as usize
+}
+
+/// The file stem of one part, numbered so the parts sort in order.
+pub fn part_stem(stem: &str, part: usize, count: usize) -> String {
+    let width = count.to_string().len();
+    format!("{stem} (part {part:0width$} of {count})")
+}
+
+/// Cuts the samples into `count` parts at the quietest spot within
+/// [`SEARCH_WINDOW`] of every multiple of `every`, so the cuts fall into
+/// pauses rather than into the middle of a word or note.
+pub fn split_samples(samples: Samples, every: Duration, count: usize) -> Vec<Samples> {
+    let channels = samples.channels.max(1) as usize;
+    let rate = samples.sample_rate as f64;
+    let frames = samples.samples.len() / channels;
+    let to_frames = |duration: Duration| (duration.as_secs_f64() * rate) as usize;
+
+    let mut cuts = Vec::new();
+    let mut previous = 0;
+    for part in 1..count {
+        // Every part keeps at least a frame, so there are always `count`.
+        let last = frames.saturating_sub(count - part);
+        let nominal = to_frames(every * part as u32).min(last);
+        let cut = quietest_frame(
+            &samples.samples,
+            channels,
+            nominal,
+            to_frames(SEARCH_WINDOW),
+            to_frames(QUIET_WINDOW).max(1),
+        )
+        .max(previous + 1)
+        .min(last)
+        .max(previous);
+        cuts.push(cut);
+        previous = cut;
+    }
+
+    let mut parts = Vec::with_capacity(cuts.len() + 1);
+    let mut rest = samples.samples;
+    for cut in cuts.into_iter().rev() {
+        let tail = rest.split_off(cut * channels);
+        parts.push(tail);
+    }
+    parts.push(rest);
+    parts
+        .into_iter()
+        .rev()
+        .map(|part| {
+            Samples::new(
+                part,
+                samples.sample_rate,
+                samples.channels,
+                samples.bits_per_sample,
+            )
+        })
+        .collect()
+}
+
+/// The middle of the quietest `window` frames within `search` frames of
+/// `around`.
+fn quietest_frame(
+    samples: &[i32],
+    channels: usize,
+    around: usize,
+    search: usize,
+    window: usize,
+) -> usize {
+    let frames = samples.len() / channels;
+    let start = around.saturating_sub(search);
+    let end = (around + search).min(frames.saturating_sub(window));
+    let loudness = |first: usize| {
+        samples[first * channels..(first + window) * channels]
+            .iter()
+            .map(|&sample| (sample as i64).unsigned_abs())
+            .sum::<u64>()
+    };
+    (start..=end.max(start))
+        .step_by((window / 2).max(1))
+        .filter(|&first| first + window <= frames)
+        .min_by_key(|&first| (loudness(first), first.abs_diff(around)))
+        .map_or(around, |first| first + window / 2)
+}
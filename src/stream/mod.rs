@@ -1,8 +1,11 @@
 pub mod channel_sink;
+#[cfg(feature = "simulate")]
+pub mod simulate;
+#[allow(clippy::module_inception)]
 pub mod stream;
 
 // Re-export the Stream type for easier access
-pub use stream::Stream;
+pub use stream::{REQUESTED_BITRATE, Stream, streamed_bitrate};
 
 pub enum StreamEvent {
     Write {
@@ -0,0 +1,154 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::Result;
+use librespot::core::SpotifyUri;
+
+use crate::stream::{StreamError, StreamEvent, StreamEventChannel};
+use crate::track::{Track, TrackMetadata};
+
+const SAMPLE_RATE: usize = 44100;
+const CHANNELS: usize = 2;
+/// Frames handed out per write, about what the player hands out.
+const CHUNK_FRAMES: usize = 4096;
+
+/// Conditions for streams that synthesize a tone instead of fetching audio
+/// from Spotify, to exercise retries, timeouts and the queue offline. Given
+/// as comma separated `key=value` pairs:
+///
+/// - `duration`: length of every track in seconds (default 5)
+/// - `latency`: milliseconds before every chunk of audio (default 0)
+/// - `errors`: how many streams of each track fail to load (default 0)
+/// - `stalls`: how many streams of each track after those stop delivering
+///   halfway through (default 0)
+/// - `truncations`: how many streams of each track after those end halfway
+///   through without an error, like a dropped connection (default 0)
+///
+/// e.g. `latency=20,stalls=1`. Streams past the errors, stalls and
+/// truncations succeed.
+#[derive(Debug, Clone)]
+pub struct Simulation {
+    pub duration: Duration,
+    pub latency: Duration,
+    pub errors: usize,
+    pub stalls: usize,
+    pub truncations: usize,
+    /// Streams started per track, shared by all clones.
+    attempts: Arc<Mutex<HashMap<SpotifyUri, usize>>>,
+}
+
+impl Default for Simulation {
+    fn default() -> Self {
+        Simulation {
+            duration: Duration::from_secs(5),
+            latency: Duration::ZERO,
+            errors: 0,
+            stalls: 0,
+            truncations: 0,
+            attempts: Arc::default(),
+        }
+    }
+}
+
+impl FromStr for Simulation {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut simulation = Simulation::default();
+        for pair in s.split(',').filter(|pair| !pair.is_empty()) {
+            let (key, value) = pair.split_once('=').ok_or(anyhow::anyhow!(
+                "Unsupported simulation, expected key=value"
+            ))?;
+            let value: u64 = value
+                .parse()
+                .map_err(|_| anyhow::anyhow!("Unsupported simulation value: {}", pair))?;
+            match key {
+                "duration" => simulation.duration = Duration::from_secs(value),
+                "latency" => simulation.latency = Duration::from_millis(value),
+                "errors" => simulation.errors = value as usize,
+                "stalls" => simulation.stalls = value as usize,
+                "truncations" => simulation.truncations = value as usize,
+                _ => return Err(anyhow::anyhow!("Unsupported simulation key: {}", key)),
+            }
+        }
+        Ok(simulation)
+    }
+}
+
+impl Simulation {
+    /// How many streams of the track were started so far.
+    pub fn attempts(&self, track: &SpotifyUri) -> usize {
+        self.attempts
+            .lock()
+            .unwrap()
+            .get(track)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    pub fn metadata(&self, track: &Track) -> TrackMetadata {
+        TrackMetadata::simulated(track.id.clone(), self.duration)
+    }
+
+    pub fn stream(&self, track: &Track) -> StreamEventChannel {
+        let attempt = {
+            let mut attempts = self.attempts.lock().unwrap();
+            let attempts = attempts.entry(track.id.clone()).or_default();
+            *attempts += 1;
+            *attempts
+        };
+        let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+        if attempt <= self.errors {
+            let error = format!("Simulated failure to load {}", track.id);
+            let _ = tx.send(StreamEvent::Error(StreamError::LoadError(error)));
+            return rx;
+        }
+        let stalls = attempt <= self.errors + self.stalls;
+        let truncated = !stalls && attempt <= self.errors + self.stalls + self.truncations;
+
+        let total_frames = (self.duration.as_secs_f64() * SAMPLE_RATE as f64) as usize;
+        let total = total_frames * CHANNELS * std::mem::size_of::<i32>();
+        let latency = self.latency;
+        tokio::spawn(async move {
+            let mut frame = 0;
+            while frame < total_frames {
+                if stalls && frame >= total_frames / 2 {
+                    // Hold the stream open without delivering anything, like
+                    // a stuck channel, until it is abandoned.
+                    tx.closed().await;
+                    return;
+                }
+                if truncated && frame >= total_frames / 2 {
+                    // Dropping the sender ends the stream without Finished.
+                    return;
+                }
+                if !latency.is_zero() {
+                    tokio::time::sleep(latency).await;
+                }
+                let end = (frame + CHUNK_FRAMES).min(total_frames);
+                let content = (frame..end)
+                    .flat_map(|i| {
+                        let sample = ((i as f64 * 0.05).sin() * i32::MAX as f64 * 0.5) as i32;
+                        [sample; CHANNELS]
+                    })
+                    .collect();
+                frame = end;
+                let bytes = frame * CHANNELS * std::mem::size_of::<i32>();
+                if tx
+                    .send(StreamEvent::Write {
+                        bytes,
+                        total,
+                        content,
+                    })
+                    .is_err()
+                {
+                    return;
+                }
+            }
+            let _ = tx.send(StreamEvent::Finished);
+        });
+        rx
+    }
+}
@@ -2,19 +2,47 @@ use std::sync::Arc;
 use std::time::Duration;
 
 use anyhow::Result;
+use librespot::audio::AudioFetchParams;
 use librespot::core::Session;
+use librespot::metadata::audio::AudioFileFormat;
 use librespot::playback::config::{Bitrate, PlayerConfig};
 use librespot::playback::mixer::NoOpVolume;
 use librespot::playback::player::{Player, PlayerEvent};
 use tokio::sync::mpsc::UnboundedSender;
 
 use crate::stream::channel_sink::{ChannelSink, SinkEvent};
+#[cfg(feature = "simulate")]
+use crate::stream::simulate::Simulation;
 use crate::stream::{StreamError, StreamEvent, StreamEventChannel};
 use crate::track::Track;
 
+/// The bitrate tracks are requested at, in kbps.
+pub const REQUESTED_BITRATE: u32 = 320;
+
+/// The bitrate librespot ends up streaming for a track offered in these
+/// formats. It asks for 320 kbps and falls back to the best Ogg Vorbis or MP3
+/// file there is, which is all a free account or some older releases get.
+/// `None` when none of the formats can be streamed.
+pub fn streamed_bitrate<'a>(formats: impl IntoIterator<Item = &'a AudioFileFormat>) -> Option<u32> {
+    formats
+        .into_iter()
+        .filter_map(|format| match format {
+            AudioFileFormat::OGG_VORBIS_320 | AudioFileFormat::MP3_320 => Some(320),
+            AudioFileFormat::MP3_256 => Some(256),
+            AudioFileFormat::OGG_VORBIS_160 | AudioFileFormat::MP3_160 => Some(160),
+            AudioFileFormat::OGG_VORBIS_96 | AudioFileFormat::MP3_96 => Some(96),
+            _ => None,
+        })
+        .max()
+}
+
 pub struct Stream {
     player_config: PlayerConfig,
     session: Session,
+    /// The first wait before loading the track is retried.
+    retry_backoff: Duration,
+    #[cfg(feature = "simulate")]
+    simulation: Option<Simulation>,
 }
 
 impl Stream {
@@ -26,10 +54,57 @@ impl Stream {
         Stream {
             player_config: config,
             session,
+            retry_backoff: Duration::from_secs(10),
+            #[cfg(feature = "simulate")]
+            simulation: None,
         }
     }
 
+    /// Waits `backoff` before the first retry of loading the track,
+    /// doubling it for later ones up to three times `backoff`.
+    pub fn with_retry_backoff(mut self, backoff: Duration) -> Self {
+        self.retry_backoff = backoff;
+        self
+    }
+
+    /// A stream that synthesizes its audio under the given conditions
+    /// instead of fetching it.
+    #[cfg(feature = "simulate")]
+    pub fn simulated(session: Session, simulation: Simulation) -> Self {
+        Stream {
+            simulation: Some(simulation),
+            ..Self::new(session)
+        }
+    }
+
+    /// Makes librespot read much further ahead of the decoder and request
+    /// larger ranges of the audio file at a time, instead of a few seconds.
+    /// The file is still fetched by librespot's own fetcher, not in
+    /// concurrent ranged requests.
+    ///
+    /// Must be called before the first stream is created.
+    pub fn enable_chunked_fetching() -> Result<()> {
+        let minimum_download_size = 512 * 1024;
+        let minimum_throughput = 64 * 1024;
+        AudioFetchParams::set(AudioFetchParams {
+            minimum_download_size,
+            minimum_throughput,
+            read_ahead_before_playback: Duration::from_secs(10),
+            read_ahead_during_playback: Duration::from_secs(600),
+            prefetch_threshold_factor: 16.0,
+            download_timeout: Duration::from_secs(
+                (minimum_download_size / minimum_throughput) as u64,
+            ),
+            ..Default::default()
+        })
+        .map_err(|_| anyhow::anyhow!("Audio fetch parameters were already initialized"))
+    }
+
     pub async fn stream(&self, track: Track) -> Result<StreamEventChannel> {
+        #[cfg(feature = "simulate")]
+        if let Some(simulation) = &self.simulation {
+            return Ok(simulation.stream(&track));
+        }
         let metadata = track.metadata(&self.session).await?;
         let (sink, mut channel) = ChannelSink::new(metadata);
         let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
@@ -41,6 +116,7 @@ impl Stream {
             move || Box::new(sink),
         );
 
+        let retry_backoff = self.retry_backoff;
         tokio::spawn(async move {
             match tryhard::retry_fn(|| async { Self::load(player.clone(), &track).await })
                 .retries(3)
@@ -65,8 +141,8 @@ impl Stream {
                         .await;
                     }
                 })
-                .exponential_backoff(Duration::from_secs(10))
-                .max_delay(Duration::from_secs(30))
+                .exponential_backoff(retry_backoff)
+                .max_delay(retry_backoff * 3)
                 .await
             {
                 Ok(_) => tracing::info!("Track loaded successfully: {:?}", &track.id),
@@ -86,6 +162,8 @@ impl Stream {
 
             tracing::info!("Streaming track: {:?}", &track.id);
 
+            // Leaving the loop drops the sink's receiver, which makes its next
+            // write fail and stops the player.
             while let Some(event) = channel.recv().await {
                 match event {
                     SinkEvent::Write {
@@ -93,6 +171,10 @@ impl Stream {
                         total,
                         content,
                     } => {
+                        if tx.is_closed() {
+                            tracing::info!("Stream of {:?} was abandoned", &track.id);
+                            break;
+                        }
                         Self::send_event(
                             &tx,
                             StreamEvent::Write {
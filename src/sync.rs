@@ -0,0 +1,319 @@
+use anyhow::Result;
+use librespot::core::SpotifyUri;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+
+use crate::history::PlaylistHistory;
+use crate::library::LibraryIndex;
+use crate::track::{PlaylistDetails, Track};
+use crate::utils::clean_invalid_characters;
+
+/// Difference between the recorded state of a playlist and its current one.
+#[derive(Debug, Serialize)]
+pub struct PlaylistDiff {
+    pub playlist: String,
+    /// There was no recorded state to compare against.
+    pub first_sync: bool,
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    /// Tracks that are still in the playlist but were moved.
+    pub reordered: Vec<String>,
+}
+
+impl PlaylistDiff {
+    pub fn compute(playlist: String, previous: Option<&[String]>, current: &[String]) -> Self {
+        let Some(previous) = previous else {
+            return PlaylistDiff {
+                playlist,
+                first_sync: true,
+                added: current.to_vec(),
+                removed: Vec::new(),
+                reordered: Vec::new(),
+            };
+        };
+
+        let previous_positions: HashMap<&String, usize> = previous
+            .iter()
+            .enumerate()
+            .rev()
+            .map(|(position, track)| (track, position))
+            .collect();
+        let current_tracks: HashSet<&String> = current.iter().collect();
+
+        let added = current
+            .iter()
+            .filter(|track| !previous_positions.contains_key(track))
+            .cloned()
+            .collect();
+        let removed = previous
+            .iter()
+            .filter(|track| !current_tracks.contains(track))
+            .cloned()
+            .collect();
+
+        // The tracks kept in the longest run that is still in the old order
+        // stayed in place, everything else in between was moved.
+        let kept: Vec<(&String, usize)> = current
+            .iter()
+            .filter_map(|track| previous_positions.get(track).map(|&p| (track, p)))
+            .collect();
+        let in_place = longest_increasing_run(&kept.iter().map(|(_, p)| *p).collect::<Vec<_>>());
+        let reordered = kept
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| !in_place.contains(index))
+            .map(|(_, (track, _))| (*track).clone())
+            .collect();
+
+        PlaylistDiff {
+            playlist,
+            first_sync: false,
+            added,
+            removed,
+            reordered,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.reordered.is_empty()
+    }
+}
+
+impl fmt::Display for PlaylistDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.first_sync {
+            return write!(
+                f,
+                "{}: first sync, {} tracks",
+                self.playlist,
+                self.added.len()
+            );
+        }
+        write!(
+            f,
+            "{}: {} added, {} removed, {} reordered",
+            self.playlist,
+            self.added.len(),
+            self.removed.len(),
+            self.reordered.len()
+        )
+    }
+}
+
+/// What applying a playlist diff changed locally.
+#[derive(Debug, Default)]
+pub struct SyncChanges {
+    /// Tracks still in the playlist whose files were deleted by hand.
+    pub tombstoned: Vec<SpotifyUri>,
+    /// Files of tracks removed from the playlist that were deleted.
+    pub deleted: Vec<PathBuf>,
+}
+
+/// Brings the history and library in line with a playlist's diff, looking
+/// only at the tracks the diff is about instead of walking the destination.
+///
+/// Tracks kept in the playlist whose indexed files are gone get a tombstone,
+/// so the library must have been relinked with
+/// [`LibraryIndex::relink_missing`] for files moved since it was indexed.
+/// With `delete_removed`, the files downloaded for the playlist of tracks
+/// removed from it are deleted, unless another playlist or an input of this
+/// run still wants them.
+pub fn apply_diff(
+    playlist: &SpotifyUri,
+    current: &[String],
+    diff: &PlaylistDiff,
+    history: &mut PlaylistHistory,
+    library: &mut LibraryIndex,
+    delete_removed: bool,
+    requested: &HashSet<String>,
+) -> Result<SyncChanges> {
+    let mut changes = SyncChanges::default();
+    let added: HashSet<&String> = diff.added.iter().collect();
+
+    for uri in current.iter().filter(|uri| !added.contains(uri)) {
+        let Ok(track) = SpotifyUri::from_uri(uri) else {
+            continue;
+        };
+        if history.has_downloaded(playlist, &track) && library.is_missing(&track) {
+            history.record_tombstone(&track)?;
+            changes.tombstoned.push(track);
+        }
+    }
+
+    if delete_removed {
+        for uri in &diff.removed {
+            let Ok(track) = SpotifyUri::from_uri(uri) else {
+                continue;
+            };
+            // Only the playlist's own copy goes: the same track downloaded
+            // as part of an album or on its own is left alone.
+            let files = history.remove_download(playlist, &track)?;
+            if requested.contains(uri) || history.downloaded_elsewhere(playlist, &track) {
+                continue;
+            }
+            for path in files {
+                library.remove_file(&path)?;
+                match fs::remove_file(&path) {
+                    Ok(()) => changes.deleted.push(path),
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                    Err(e) => return Err(e.into()),
+                }
+            }
+        }
+    }
+
+    Ok(changes)
+}
+
+/// Cover image of the playlist whose tracks fill a folder.
+pub const PLAYLIST_COVER_FILE: &str = "playlist.jpg";
+/// Name, URI and description of the playlist whose tracks fill a folder.
+pub const PLAYLIST_INFO_FILE: &str = "playlist.txt";
+/// Extension of the cover saved next to an M3U playlist.
+const COVER_EXTENSION: &str = "jpg";
+
+/// Writes `playlist.txt` with the playlist's name, URI and description, and
+/// `playlist.jpg` with its cover, into a folder that holds one playlist.
+pub fn write_playlist_details(dir: &Path, details: &PlaylistDetails) -> Result<()> {
+    write_details(
+        &dir.join(PLAYLIST_INFO_FILE),
+        &dir.join(PLAYLIST_COVER_FILE),
+        details,
+    )
+}
+
+/// Like [`write_playlist_details`] for a folder that holds several
+/// playlists, so the files are named after the playlist instead:
+/// `<playlist name>.txt` and `<playlist name>.jpg`.
+pub fn write_shared_playlist_details(dir: &Path, details: &PlaylistDetails) -> Result<()> {
+    let stem = clean_invalid_characters(details.name.as_str());
+    write_details(
+        &dir.join(format!("{stem}.txt")),
+        &dir.join(format!("{stem}.{COVER_EXTENSION}")),
+        details,
+    )
+}
+
+fn write_details(info_path: &Path, cover_path: &Path, details: &PlaylistDetails) -> Result<()> {
+    if let Some(parent) = info_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut info = format!("{}\n{}\n", details.name, details.uri);
+    if !details.description.is_empty() {
+        info.push_str(&format!("\n{}\n", details.description));
+    }
+    fs::write(info_path, info)?;
+    if let Some(cover) = &details.cover {
+        fs::write(cover_path, cover)?;
+    }
+    Ok(())
+}
+
+/// File name of the M3U playlist written for a Spotify playlist.
+pub fn m3u_file_name(playlist_name: &str) -> String {
+    format!("{}.m3u8", clean_invalid_characters(playlist_name))
+}
+
+/// Writes the downloaded tracks in playlist order, relative to the playlist
+/// file. With the playlist's details, the header names the playlist and the
+/// cover saved next to it, and carries the description as comments. Returns
+/// the number of entries.
+pub fn write_m3u(
+    path: &Path,
+    tracks: &[String],
+    library: &LibraryIndex,
+    details: Option<&PlaylistDetails>,
+) -> Result<usize> {
+    let base = path.parent().unwrap_or(Path::new(""));
+    let mut contents = Vec::new();
+    writeln!(contents, "#EXTM3U")?;
+    if let Some(details) = details {
+        writeln!(contents, "#PLAYLIST:{}", details.name)?;
+        if let Some(cover) = &details.cover {
+            let cover_path = path.with_extension(COVER_EXTENSION);
+            fs::write(&cover_path, cover)?;
+            if let Some(name) = cover_path.file_name() {
+                writeln!(contents, "#EXTIMG:{}", name.to_string_lossy())?;
+            }
+        }
+        writeln!(contents, "# {}", details.uri)?;
+        for line in details.description.lines() {
+            writeln!(contents, "# {}", line)?;
+        }
+    }
+    let mut entries = 0;
+    for uri in tracks {
+        let Ok(track) = SpotifyUri::from_uri(uri) else {
+            continue;
+        };
+        if let Some(file) = library.path_of(&track) {
+            writeln!(contents, "{}", relative_path(file, base).display())?;
+            entries += 1;
+        }
+    }
+    fs::write(path, contents)?;
+    Ok(entries)
+}
+
+/// `path` as seen from `base`, stepping up with `..` where needed. Both must
+/// be relative to the same directory.
+fn relative_path(path: &Path, base: &Path) -> PathBuf {
+    let path_components: Vec<_> = path.components().collect();
+    let base_components: Vec<_> = base.components().collect();
+    let common = path_components
+        .iter()
+        .zip(&base_components)
+        .take_while(|(a, b)| a == b)
+        .count();
+    if common == 0 && path.has_root() {
+        return path.to_path_buf();
+    }
+    base_components[common..]
+        .iter()
+        .map(|_| Component::ParentDir)
+        .chain(path_components[common..].iter().copied())
+        .collect()
+}
+
+/// Groups the playlist tracks by their playlist, keeping the playlist order.
+pub fn playlist_snapshots(tracks: &[Track]) -> Vec<(SpotifyUri, Vec<String>)> {
+    let mut snapshots: Vec<(SpotifyUri, Vec<String>)> = Vec::new();
+    for track in tracks {
+        let (Some(playlist), Ok(uri)) = (track.playlist(), track.id.to_uri()) else {
+            continue;
+        };
+        match snapshots.iter_mut().find(|(id, _)| *id == playlist) {
+            Some((_, uris)) => uris.push(uri),
+            None => snapshots.push((playlist, vec![uri])),
+        }
+    }
+    snapshots
+}
+
+/// Returns the indices of a longest strictly increasing subsequence.
+fn longest_increasing_run(values: &[usize]) -> HashSet<usize> {
+    // tails[k] is the index of the smallest tail of a run of length k + 1
+    let mut tails: Vec<usize> = Vec::new();
+    let mut predecessors: Vec<Option<usize>> = vec![None; values.len()];
+    for (index, value) in values.iter().enumerate() {
+        let length = tails.partition_point(|&tail| values[tail] < *value);
+        predecessors[index] = length.checked_sub(1).map(|previous| tails[previous]);
+        if length == tails.len() {
+            tails.push(index);
+        } else {
+            tails[length] = index;
+        }
+    }
+
+    let mut run = HashSet::new();
+    let mut next = tails.last().copied();
+    while let Some(index) = next {
+        run.insert(index);
+        next = predecessors[index];
+    }
+    run
+}
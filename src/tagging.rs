@@ -0,0 +1,101 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::Result;
+use bytes::Bytes;
+use tokio::sync::Semaphore;
+
+use crate::encoder::Format;
+use crate::encoder::tags::{self, CoverSize, DEFAULT_COVER_QUALITY};
+use crate::track::TrackMetadata;
+
+/// How covers are prepared before they are embedded.
+#[derive(Debug, Clone, Copy)]
+pub struct CoverOptions {
+    /// Re-encode PNG covers as JPEG.
+    pub convert_png: bool,
+    /// Scale covers to this size.
+    pub size: Option<CoverSize>,
+    /// JPEG quality of scaled covers.
+    pub quality: u8,
+}
+
+impl Default for CoverOptions {
+    fn default() -> Self {
+        CoverOptions {
+            convert_png: false,
+            size: None,
+            quality: DEFAULT_COVER_QUALITY,
+        }
+    }
+}
+
+impl CoverOptions {
+    /// The cover converted and scaled as asked, or as it was where that
+    /// fails.
+    fn prepare(&self, mut cover: Bytes, path: &Path) -> Bytes {
+        if self.convert_png {
+            cover = tags::convert_png_cover(cover.clone()).unwrap_or_else(|err| {
+                tracing::warn!(error = %err, "Embedding the PNG cover of {} as is", path.display());
+                cover
+            });
+        }
+        if let Some(size) = self.size {
+            cover = tags::resize_cover(cover.clone(), size, self.quality).unwrap_or_else(|err| {
+                tracing::warn!(error = %err, "Embedding the cover of {} unresized", path.display());
+                cover
+            });
+        }
+        cover
+    }
+}
+
+/// A file to tag from the metadata of its track.
+pub struct TagJob {
+    pub metadata: TrackMetadata,
+    /// Replaces the title, e.g. with the part of a split track.
+    pub title: Option<String>,
+    pub path: PathBuf,
+    pub format: Format,
+    pub covers: CoverOptions,
+}
+
+/// Tags files on a few blocking threads instead of on the download tasks,
+/// so a slow disk or network share doesn't hold up streaming, and a tagging
+/// job that panics fails its file rather than the task waiting for it.
+#[derive(Clone)]
+pub struct TagPool {
+    permits: Arc<Semaphore>,
+}
+
+impl TagPool {
+    /// A pool tagging up to `threads` files at a time.
+    pub fn new(threads: usize) -> Self {
+        TagPool {
+            permits: Arc::new(Semaphore::new(threads.max(1))),
+        }
+    }
+
+    /// Fetches the cover, prepares it and stores the tags like
+    /// [`tags::store_verified_tags`], returning the fields that didn't read
+    /// back.
+    pub async fn tag(&self, job: TagJob) -> Result<Vec<&'static str>> {
+        let permits = Arc::clone(&self.permits);
+        tokio::spawn(async move {
+            let mut tags = job.metadata.tags().await?;
+            if let Some(title) = job.title {
+                tags.title = title;
+            }
+            let _permit = permits.acquire_owned().await?;
+            tokio::task::spawn_blocking(move || {
+                tags.album_cover = tags
+                    .album_cover
+                    .take()
+                    .map(|cover| job.covers.prepare(cover, &job.path));
+                tags::write_verified_tags(&job.path, &tags, job.format)
+            })
+            .await?
+        })
+        .await?
+    }
+}
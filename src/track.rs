@@ -1,17 +1,26 @@
+use std::collections::HashMap;
 use std::future::Future;
 use std::pin::Pin;
 use std::sync::Arc;
+use std::time::{Duration, SystemTime};
 
 use anyhow::Result;
 use bytes::Bytes;
-use lazy_static::lazy_static;
+use http::{Method, Request};
 use librespot::core::session::Session;
-use librespot::core::SpotifyUri;
+use librespot::core::{FileId, SpotifyUri};
 use librespot::metadata::Metadata;
 use librespot::metadata::image::Image;
-use regex::Regex;
+use librespot::protocol::canvaz::Type;
+use librespot::protocol::canvaz::entity_canvaz_response::Canvaz;
+use librespot::protocol::extended_metadata::{BatchedEntityRequest, EntityRequest, ExtensionQuery};
+use librespot::protocol::extension_kind::ExtensionKind;
+use protobuf::{EnumOrUnknown, Message};
 
+use crate::chapters::{Chapter, parse_chapters};
 use crate::encoder::tags::Tags;
+use crate::input;
+use crate::mtime;
 use crate::utils::clean_invalid_characters;
 
 pub type AsyncFn<T> =
@@ -25,7 +34,7 @@ trait TrackCollection {
 #[tracing::instrument(name = "get_tracks", skip(session), level = "debug")]
 pub async fn get_tracks(spotify_ids: Vec<String>, session: &Session) -> Result<Vec<Track>> {
     let mut tracks: Vec<Track> = Vec::new();
-    for id in spotify_ids {
+    for id in input::canonical_uris(&spotify_ids, session).await? {
         tracing::debug!("Getting tracks for: {}", id);
         let id = parse_uri_or_url(&id).ok_or(anyhow::anyhow!("Invalid track"))?;
         let new_tracks = match &id {
@@ -48,35 +57,23 @@ pub async fn get_tracks(spotify_ids: Vec<String>, session: &Session) -> Result<V
 }
 
 fn parse_uri_or_url(track: &str) -> Option<SpotifyUri> {
-    parse_uri(track).or_else(|| parse_url(track))
-}
-
-fn parse_uri(track_uri: &str) -> Option<SpotifyUri> {
-    let res = SpotifyUri::from_uri(track_uri);
+    let res = input::parse_link(track);
     tracing::info!("Parsed URI: {:?}", res);
-    res.ok()
-}
-
-fn parse_url(track_url: &str) -> Option<SpotifyUri> {
-    let results = SPOTIFY_URL_REGEX.captures(track_url)?;
-    let uri = format!(
-        "spotify:{}:{}",
-        results.get(1)?.as_str(),
-        results.get(2)?.as_str()
-    );
-    SpotifyUri::from_uri(&uri).ok()
+    res
 }
 
 #[derive(Clone, Debug)]
 pub struct Track {
     pub id: SpotifyUri,
     playlist: Option<SpotifyUri>,
+    album: Option<SpotifyUri>,
 }
 
-lazy_static! {
-    static ref SPOTIFY_URL_REGEX: Regex =
-        Regex::new(r"https://open\.spotify\.com(?:/intl-[a-z]{2})?/(\w+)/([a-zA-Z0-9]+)").unwrap();
-}
+/// The size Spotify names the biggest rendition of a playlist cover.
+const LARGE_PICTURE: &str = "large";
+
+/// Metadata requests in flight at once when looking up many tracks.
+pub(crate) const CONCURRENT_METADATA_REQUESTS: usize = 8;
 
 impl Track {
     pub fn new(track: &str) -> Result<Self> {
@@ -84,6 +81,7 @@ impl Track {
         Ok(Track {
             id,
             playlist: None,
+            album: None,
         })
     }
 
@@ -91,6 +89,7 @@ impl Track {
         Track {
             id,
             playlist: None,
+            album: None,
         }
     }
 
@@ -98,6 +97,15 @@ impl Track {
         Track {
             id: track_id,
             playlist: Some(playlist_id),
+            album: None,
+        }
+    }
+
+    pub fn from_album(track_id: SpotifyUri, album_id: SpotifyUri) -> Self {
+        Track {
+            id: track_id,
+            playlist: None,
+            album: Some(album_id),
         }
     }
 
@@ -105,7 +113,99 @@ impl Track {
         self.playlist.clone()
     }
 
+    /// The album this track was resolved from, when an album was requested.
+    pub fn album(&self) -> Option<SpotifyUri> {
+        self.album.clone()
+    }
+
+    /// URL of the track's Canvas loop video, if the artist uploaded one.
+    pub async fn canvas_url(&self, session: &Session) -> Result<Option<String>> {
+        let request = BatchedEntityRequest {
+            entity_request: vec![EntityRequest {
+                entity_uri: self.id.to_uri()?,
+                query: vec![ExtensionQuery {
+                    extension_kind: EnumOrUnknown::new(ExtensionKind::CANVAZ),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let response = session.spclient().get_extended_metadata(request).await?;
+
+        let Some(data) = response
+            .extended_metadata
+            .iter()
+            .flat_map(|array| &array.extension_data)
+            .find_map(|data| data.extension_data.as_ref())
+        else {
+            return Ok(None);
+        };
+        let canvas = Canvaz::parse_from_bytes(&data.value)?;
+        let is_video = matches!(
+            canvas.type_.enum_value(),
+            Ok(Type::VIDEO | Type::VIDEO_LOOPING | Type::VIDEO_LOOPING_RANDOM)
+        );
+        Ok(Some(canvas.url).filter(|url| is_video && !url.is_empty()))
+    }
+
+    /// Length of the track or episode, from its metadata alone.
+    pub async fn duration(&self, session: &Session) -> Result<Duration> {
+        let duration = match self.id {
+            SpotifyUri::Episode { .. } => {
+                librespot::metadata::Episode::get(session, &self.id)
+                    .await
+                    .map_err(|_| anyhow::anyhow!("Failed to get episode metadata"))?
+                    .duration
+            }
+            _ => {
+                librespot::metadata::Track::get(session, &self.id)
+                    .await
+                    .map_err(|_| anyhow::anyhow!("Failed to get metadata"))?
+                    .duration
+            }
+        };
+        Ok(Duration::from_millis(duration.max(0) as u64))
+    }
+
+    /// Bitrate in kbps of the best file librespot can stream for the track,
+    /// `None` when Spotify offers none it can decode.
+    pub async fn streamed_bitrate(&self, session: &Session) -> Result<Option<u32>> {
+        let bitrate = match self.id {
+            SpotifyUri::Episode { .. } => {
+                let episode = librespot::metadata::Episode::get(session, &self.id)
+                    .await
+                    .map_err(|_| anyhow::anyhow!("Failed to get episode metadata"))?;
+                crate::stream::streamed_bitrate(episode.audio.keys())
+            }
+            _ => {
+                let track = librespot::metadata::Track::get(session, &self.id)
+                    .await
+                    .map_err(|_| anyhow::anyhow!("Failed to get metadata"))?;
+                let mut bitrate = crate::stream::streamed_bitrate(track.files.keys());
+                // Like librespot, fall back to the track's alternatives, which
+                // stand in for it where it is restricted.
+                for alternative in track.alternatives.iter() {
+                    if bitrate.is_some() {
+                        break;
+                    }
+                    if let Ok(alternative) =
+                        librespot::metadata::Track::get(session, alternative).await
+                    {
+                        bitrate = crate::stream::streamed_bitrate(alternative.files.keys());
+                    }
+                }
+                bitrate
+            }
+        };
+        Ok(bitrate)
+    }
+
     pub async fn metadata(&self, session: &Session) -> Result<TrackMetadata> {
+        if let SpotifyUri::Episode { .. } = self.id {
+            return self.episode_metadata(session).await;
+        }
+
         let metadata = librespot::metadata::Track::get(session, &self.id)
             .await
             .map_err(|_| anyhow::anyhow!("Failed to get metadata"))?;
@@ -143,6 +243,50 @@ impl Track {
             image_retriever,
         ))
     }
+
+    /// Podcast episodes are tagged with their show as artist and album, and
+    /// carry the chapters listed in their description.
+    async fn episode_metadata(&self, session: &Session) -> Result<TrackMetadata> {
+        let episode = librespot::metadata::Episode::get(session, &self.id)
+            .await
+            .map_err(|_| anyhow::anyhow!("Failed to get episode metadata"))?;
+
+        let cover = episode.covers.first().cloned();
+        let image_id = cover.as_ref().map(|cover| cover.id);
+        let session = session.clone();
+        let image_retriever: AsyncFn<Bytes> = Arc::new(move || {
+            let session = session.clone();
+            Box::pin(async move { session.spclient().get_image(&image_id?).await.ok() })
+        });
+
+        let duration = Duration::from_millis(episode.duration.max(0) as u64);
+        let chapters = parse_chapters(&episode.description)
+            .into_iter()
+            .filter(|chapter| chapter.start < duration)
+            .collect();
+
+        Ok(TrackMetadata {
+            id: episode.id.clone(),
+            artists: vec![ArtistMetadata {
+                name: episode.show_name.clone(),
+            }],
+            track_name: episode.name.clone(),
+            album: AlbumMetadata {
+                name: episode.show_name.clone(),
+                cover,
+                disc_count: 1,
+                released: mtime::from_timestamp_ms(episode.publish_time.as_timestamp_ms()),
+            },
+            duration: episode.duration,
+            isrc: None,
+            track_number: episode.number.max(0) as u32,
+            disc_number: 1,
+            preview: None,
+            chapters,
+            genre: None,
+            image_retriever,
+        })
+    }
 }
 
 #[async_trait::async_trait]
@@ -179,7 +323,7 @@ impl TrackCollection for Album {
             .expect("Failed to get album");
         album
             .tracks()
-            .map(|track| Track::from_id(track.clone()))
+            .map(|track| Track::from_album(track.clone(), self.id.clone()))
             .collect()
     }
 }
@@ -203,6 +347,87 @@ impl Playlist {
             .await
             .is_ok()
     }
+
+    pub async fn name(&self, session: &Session) -> Result<String> {
+        let playlist = librespot::metadata::Playlist::get(session, &self.id).await?;
+        Ok(playlist.name().to_string())
+    }
+
+    /// When each track was added to the playlist. A track added more than
+    /// once keeps the first time.
+    pub async fn added_dates(&self, session: &Session) -> Result<HashMap<SpotifyUri, SystemTime>> {
+        let playlist = librespot::metadata::Playlist::get(session, &self.id).await?;
+        let mut added = HashMap::new();
+        for item in playlist.contents.items.iter() {
+            let timestamp = item.attributes.timestamp.as_timestamp_ms();
+            if let Some(time) = mtime::from_timestamp_ms(timestamp) {
+                added.entry(item.id.clone()).or_insert(time);
+            }
+        }
+        Ok(added)
+    }
+
+    /// The name, description and cover image of the playlist. Uploaded
+    /// covers are image files; the mosaics Spotify generates only have URLs.
+    pub async fn details(&self, session: &Session) -> Result<PlaylistDetails> {
+        let playlist = librespot::metadata::Playlist::get(session, &self.id).await?;
+        let attributes = &playlist.attributes;
+        let cover = if !attributes.picture.is_empty() {
+            let file_id = FileId::from_raw(&attributes.picture);
+            session.spclient().get_image(&file_id).await.ok()
+        } else if let Some(picture) = attributes
+            .picture_sizes
+            .iter()
+            .find(|size| size.target_name == LARGE_PICTURE)
+            .or(attributes.picture_sizes.first())
+        {
+            let request = Request::builder()
+                .method(Method::GET)
+                .uri(&picture.url)
+                .body(Bytes::new())?;
+            session.http_client().request_body(request).await.ok()
+        } else {
+            None
+        };
+
+        let uri = self.id.to_uri()?;
+        let description = attributes.description.clone();
+        // The metadata channel often leaves out descriptions the Web API has.
+        #[cfg(feature = "webapi")]
+        let description = match description.is_empty() {
+            true => web_api_description(session, &uri).await,
+            false => description,
+        };
+
+        Ok(PlaylistDetails {
+            uri,
+            name: attributes.name.clone(),
+            description,
+            cover,
+        })
+    }
+}
+
+#[cfg(feature = "webapi")]
+async fn web_api_description(session: &Session, uri: &str) -> String {
+    let id = uri.rsplit(':').next().unwrap_or_default();
+    match crate::api::playlist(session, id).await {
+        Ok(playlist) => {
+            crate::api::unescape_html(playlist.description.as_deref().unwrap_or_default())
+        }
+        Err(err) => {
+            tracing::warn!(error = %err, "Failed to fetch the description of {}", uri);
+            String::new()
+        }
+    }
+}
+
+/// What identifies a playlist besides its tracks.
+pub struct PlaylistDetails {
+    pub uri: String,
+    pub name: String,
+    pub description: String,
+    pub cover: Option<Bytes>,
 }
 
 #[async_trait::async_trait]
@@ -220,10 +445,21 @@ impl TrackCollection for Playlist {
 
 #[derive(Clone)]
 pub struct TrackMetadata {
+    pub id: SpotifyUri,
     pub artists: Vec<ArtistMetadata>,
     pub track_name: String,
     pub album: AlbumMetadata,
     pub duration: i32,
+    pub isrc: Option<String>,
+    /// Position on its disc, starting at 1.
+    pub track_number: u32,
+    pub disc_number: u32,
+    /// The unencrypted 30 second MP3 clip, if the track has one.
+    pub preview: Option<FileId>,
+    /// Chapters of a podcast episode, empty for songs.
+    pub chapters: Vec<Chapter>,
+    /// Spotify has no genres for tracks, this only comes from overrides.
+    pub genre: Option<String>,
     image_retriever: AsyncFn<Bytes>,
 }
 
@@ -241,14 +477,53 @@ impl TrackMetadata {
         let album = AlbumMetadata::from(album);
 
         TrackMetadata {
+            id: track.id.clone(),
             artists,
             track_name: track.name.clone(),
             album,
             duration: track.duration,
+            track_number: track.number.max(0) as u32,
+            disc_number: track.disc_number.max(1) as u32,
+            preview: track.previews.values().next().copied(),
+            isrc: track
+                .external_ids
+                .iter()
+                .find(|external_id| external_id.external_type == "isrc")
+                .map(|external_id| external_id.id.clone()),
+            chapters: Vec::new(),
+            genre: None,
             image_retriever,
         }
     }
 
+    /// Made-up metadata for a simulated stream of the given length.
+    #[cfg(feature = "simulate")]
+    pub fn simulated(id: SpotifyUri, duration: std::time::Duration) -> Self {
+        let uri = id.to_uri().unwrap_or_default();
+        let name = uri.rsplit(':').next().unwrap_or_default().to_string();
+        TrackMetadata {
+            id,
+            artists: vec![ArtistMetadata {
+                name: "Simulated Artist".to_string(),
+            }],
+            track_name: name,
+            album: AlbumMetadata {
+                name: "Simulated Album".to_string(),
+                cover: None,
+                disc_count: 1,
+                released: None,
+            },
+            duration: duration.as_millis() as i32,
+            isrc: None,
+            track_number: 1,
+            disc_number: 1,
+            preview: None,
+            chapters: Vec::new(),
+            genre: None,
+            image_retriever: Arc::new(|| Box::pin(async { None })),
+        }
+    }
+
     pub fn approx_size(&self) -> usize {
         let duration = self.duration / 1000;
         let sample_rate = 44100;
@@ -264,13 +539,20 @@ impl TrackMetadata {
             artists: self.artists.iter().map(|a| a.name.clone()).collect(),
             album_title: self.album.name.clone(),
             album_cover: (self.image_retriever)().await,
+            spotify_uri: self.id.to_uri().ok(),
+            isrc: self.isrc.clone(),
+            track_number: Some(self.track_number).filter(|number| *number > 0),
+            disc_number: Some(self.disc_number),
+            disc_count: Some(self.album.disc_count),
+            chapters: self.chapters.clone(),
+            genre: self.genre.clone(),
         };
         Ok(tags)
     }
 }
 
-impl ToString for TrackMetadata {
-    fn to_string(&self) -> String {
+impl std::fmt::Display for TrackMetadata {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         if self.artists.len() > 3 {
             let artists_name = self
                 .artists
@@ -279,10 +561,10 @@ impl ToString for TrackMetadata {
                 .map(|artist| artist.name.clone())
                 .collect::<Vec<String>>()
                 .join(", ");
-            return clean_invalid_characters(format!(
+            return f.write_str(&clean_invalid_characters(format!(
                 "{}, and others - {}",
                 artists_name, self.track_name
-            ));
+            )));
         }
 
         let artists_name = self
@@ -291,7 +573,10 @@ impl ToString for TrackMetadata {
             .map(|artist| artist.name.clone())
             .collect::<Vec<String>>()
             .join(", ");
-        clean_invalid_characters(format!("{} - {}", artists_name, self.track_name))
+        f.write_str(&clean_invalid_characters(format!(
+            "{} - {}",
+            artists_name, self.track_name
+        )))
     }
 }
 
@@ -312,6 +597,9 @@ impl From<librespot::metadata::Artist> for ArtistMetadata {
 pub struct AlbumMetadata {
     pub name: String,
     pub cover: Option<Image>,
+    pub disc_count: u32,
+    /// When the album or show episode came out, if Spotify knows.
+    pub released: Option<SystemTime>,
 }
 
 impl From<librespot::metadata::Album> for AlbumMetadata {
@@ -319,6 +607,8 @@ impl From<librespot::metadata::Album> for AlbumMetadata {
         AlbumMetadata {
             name: album.name.clone(),
             cover: album.covers.first().cloned(),
+            disc_count: album.discs.len().max(1) as u32,
+            released: mtime::from_timestamp_ms(album.date.as_timestamp_ms()),
         }
     }
 }
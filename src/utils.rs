@@ -1,6 +1,9 @@
-use std::path::PathBuf;
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
 
 use anyhow::Result;
+use sha2::{Digest, Sha256};
 
 pub(crate) fn clean_invalid_characters<S>(input: S) -> String
 where
@@ -28,3 +31,47 @@ pub(crate) fn get_dot_path() -> Result<PathBuf> {
     std::fs::create_dir_all(&path)?;
     Ok(path)
 }
+
+/// Hex encoded SHA-256 of the file's contents.
+pub(crate) fn hash_file(path: &Path) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect())
+}
+
+/// Copies a file through a `.part` file next to `to`, so readers of the
+/// destination never see a half written file.
+pub(crate) async fn copy_file(from: &Path, to: &Path) -> Result<()> {
+    if let Some(parent) = to.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    let mut part = to.as_os_str().to_owned();
+    part.push(".part");
+    let part = PathBuf::from(part);
+    let copied = match tokio::fs::copy(from, &part).await {
+        Ok(_) => tokio::fs::rename(&part, to).await,
+        Err(err) => Err(err),
+    };
+    if let Err(err) = copied {
+        let _ = tokio::fs::remove_file(&part).await;
+        return Err(err.into());
+    }
+    Ok(())
+}
+
+/// Moves a file, falling back to copy and delete when `to` is on another
+/// filesystem than `from`.
+pub(crate) async fn move_file(from: &Path, to: &Path) -> Result<()> {
+    if let Some(parent) = to.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    if tokio::fs::rename(from, to).await.is_err() {
+        tokio::fs::copy(from, to).await?;
+        tokio::fs::remove_file(from).await?;
+    }
+    Ok(())
+}
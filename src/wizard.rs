@@ -0,0 +1,117 @@
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+use spotify_dl::config::Config;
+use spotify_dl::encoder::Format;
+use spotify_dl::session::create_session;
+
+pub const DEFAULT_TEMPLATE: &str = "{artists} - {title}";
+
+/// Asks for the settings that would otherwise take a handful of flags on
+/// every run, logs in to Spotify and writes the config file.
+pub async fn run() -> anyhow::Result<Config> {
+    let current = Config::load()?.unwrap_or_default();
+    println!("Welcome to spotify-dl! Press enter to keep the value in brackets.\n");
+
+    let default_destination = current
+        .destination
+        .clone()
+        .or_else(dirs::audio_dir)
+        .or_else(|| std::env::current_dir().ok())
+        .unwrap_or_default();
+    let destination = ask(
+        "Where should downloaded music be saved?",
+        &default_destination.to_string_lossy(),
+    )?;
+
+    let format = loop {
+        let format = ask(
+            "Which format do you want, mp3 (smaller files) or flac (lossless)?",
+            current.format.as_deref().unwrap_or("mp3"),
+        )?;
+        match format.parse::<Format>() {
+            Ok(_) => break format,
+            Err(_) => println!("Please answer mp3 or flac."),
+        }
+    };
+
+    println!(
+        "\nFiles are named after a template. Available placeholders: {{artists}}, {{artist}}, {{title}}, {{album}}, {{track}}, {{disc}}, {{discs}}, {{disc_folder}} and {{position}}.\nUse / to put files into folders, e.g. {{artist}}/{{album}}/{{disc_folder}}/{{track}} {{title}}."
+    );
+    let template = ask(
+        "File name template",
+        current.template.as_deref().unwrap_or(DEFAULT_TEMPLATE),
+    )?;
+
+    let parallel = loop {
+        let parallel = ask(
+            "How many songs should be downloaded at the same time? 1 mimics normal listening.",
+            &current.parallel.unwrap_or(1).to_string(),
+        )?;
+        match parallel.parse::<usize>() {
+            Ok(parallel) if parallel > 0 => break parallel,
+            _ => println!("Please enter a number of at least 1."),
+        }
+    };
+
+    let match_by_tag = confirm(
+        "Recognise songs you renamed or moved, so they are not downloaded again?",
+        current.match_by_tag,
+    )?;
+    let verify = confirm(
+        "Check every file after it was written? Slower, but catches broken files.",
+        current.verify,
+    )?;
+
+    let config = Config {
+        destination: Some(PathBuf::from(destination)),
+        format: Some(format),
+        template: Some(template).filter(|template| template != DEFAULT_TEMPLATE),
+        parallel: Some(parallel),
+        match_by_tag,
+        verify,
+        // Not asked for, kept as it was set in the file.
+        timeout_factor: current.timeout_factor,
+        format_destinations: current.format_destinations,
+    };
+
+    println!(
+        "\nLogging in to Spotify. If you are not logged in yet, a browser window will ask you to."
+    );
+    create_session(false).await?;
+
+    let path = config.save()?;
+    println!("All set! Settings were saved to {}.", path.display());
+    println!("Run spotify-dl again with a song, album or playlist link to start downloading.");
+    Ok(config)
+}
+
+fn read_answer(prompt: &str) -> io::Result<String> {
+    print!("{}: ", prompt);
+    io::stdout().flush()?;
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    Ok(answer.trim().to_string())
+}
+
+fn ask(question: &str, default: &str) -> io::Result<String> {
+    let answer = read_answer(&format!("{} [{}]", question, default))?;
+    Ok(if answer.is_empty() {
+        default.to_string()
+    } else {
+        answer
+    })
+}
+
+fn confirm(question: &str, default: bool) -> io::Result<bool> {
+    let hint = if default { "Y/n" } else { "y/N" };
+    loop {
+        let answer = read_answer(&format!("{} [{}]", question, hint))?;
+        match answer.to_ascii_lowercase().as_str() {
+            "" => return Ok(default),
+            "y" | "yes" => return Ok(true),
+            "n" | "no" => return Ok(false),
+            _ => println!("Please answer y or n."),
+        }
+    }
+}
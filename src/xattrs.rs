@@ -0,0 +1,64 @@
+use std::io;
+use std::path::Path;
+
+use anyhow::Result;
+
+const URI_ATTRIBUTE: &str = "spotify_dl.uri";
+const PLAYLIST_ATTRIBUTE: &str = "spotify_dl.playlist";
+
+/// Records the Spotify URI of the file's track and the playlist it was
+/// downloaded from, if any, in the file system rather than in the tags:
+/// extended attributes on Linux and macOS, alternate data streams on Windows.
+/// That works whatever the file format supports for tags.
+pub fn write_provenance(path: &Path, uri: &str, playlist: Option<&str>) -> Result<()> {
+    set(path, URI_ATTRIBUTE, uri)?;
+    if let Some(playlist) = playlist {
+        set(path, PLAYLIST_ATTRIBUTE, playlist)?;
+    }
+    Ok(())
+}
+
+/// The track URI and playlist recorded by `write_provenance`.
+pub fn read_provenance(path: &Path) -> Option<(String, Option<String>)> {
+    let uri = get(path, URI_ATTRIBUTE)?;
+    Some((uri, get(path, PLAYLIST_ATTRIBUTE)))
+}
+
+// Linux only allows arbitrary attributes in the user namespace.
+#[cfg(unix)]
+fn set(path: &Path, name: &str, value: &str) -> io::Result<()> {
+    xattr::set(path, format!("user.{name}"), value.as_bytes())
+}
+
+#[cfg(unix)]
+fn get(path: &Path, name: &str) -> Option<String> {
+    let value = xattr::get(path, format!("user.{name}")).ok()??;
+    String::from_utf8(value).ok()
+}
+
+#[cfg(windows)]
+fn stream_path(path: &Path, name: &str) -> std::path::PathBuf {
+    let mut stream = path.as_os_str().to_owned();
+    stream.push(format!(":{name}"));
+    stream.into()
+}
+
+#[cfg(windows)]
+fn set(path: &Path, name: &str, value: &str) -> io::Result<()> {
+    std::fs::write(stream_path(path, name), value)
+}
+
+#[cfg(windows)]
+fn get(path: &Path, name: &str) -> Option<String> {
+    std::fs::read_to_string(stream_path(path, name)).ok()
+}
+
+#[cfg(not(any(unix, windows)))]
+fn set(_path: &Path, _name: &str, _value: &str) -> io::Result<()> {
+    Err(io::Error::from(io::ErrorKind::Unsupported))
+}
+
+#[cfg(not(any(unix, windows)))]
+fn get(_path: &Path, _name: &str) -> Option<String> {
+    None
+}
@@ -0,0 +1,56 @@
+use spotify_dl::api::{AudioFeaturesResponse, PlaylistPage, unescape_html};
+
+#[test]
+fn reads_playlist_pages() {
+    let page: PlaylistPage = serde_json::from_str(
+        r#"{
+            "items": [
+                {
+                    "added_at": "2024-03-01T12:00:00Z",
+                    "added_by": {"id": "alice", "display_name": "Alice"},
+                    "track": {"uri": "spotify:track:4uLU6hMCjMI75M1A2tKUQC"}
+                },
+                {"added_at": null, "added_by": null, "track": null}
+            ],
+            "next": "https://api.spotify.com/v1/playlists/abc/tracks?offset=100"
+        }"#,
+    )
+    .unwrap();
+    assert_eq!(page.items.len(), 2);
+    let first = &page.items[0];
+    assert_eq!(first.added_at.as_deref(), Some("2024-03-01T12:00:00Z"));
+    assert_eq!(first.added_by.as_ref().unwrap().id, "alice");
+    assert_eq!(
+        first.track.as_ref().unwrap().uri,
+        "spotify:track:4uLU6hMCjMI75M1A2tKUQC"
+    );
+    assert!(page.items[1].track.is_none());
+    assert!(page.next.is_some());
+}
+
+#[test]
+fn reads_audio_features_and_names_keys() {
+    let response: AudioFeaturesResponse = serde_json::from_str(
+        r#"{"audio_features": [
+            {
+                "uri": "spotify:track:4uLU6hMCjMI75M1A2tKUQC",
+                "tempo": 113.1, "key": 1, "mode": 0, "time_signature": 4,
+                "danceability": 0.7, "energy": 0.8, "valence": 0.6,
+                "acousticness": 0.1, "instrumentalness": 0.0, "loudness": -5.2
+            },
+            null
+        ]}"#,
+    )
+    .unwrap();
+    let features: Vec<_> = response.audio_features.into_iter().flatten().collect();
+    assert_eq!(features.len(), 1);
+    assert_eq!(features[0].key_name().as_deref(), Some("C#m"));
+}
+
+#[test]
+fn unescapes_descriptions() {
+    assert_eq!(
+        unescape_html("Rock &amp; roll &quot;classics&quot; &#x2F; 60&#x27;s"),
+        "Rock & roll \"classics\" / 60's"
+    );
+}
@@ -0,0 +1,24 @@
+use librespot::metadata::audio::AudioFileFormat;
+use spotify_dl::stream::{REQUESTED_BITRATE, streamed_bitrate};
+
+#[test]
+fn picks_the_best_streamable_format() {
+    let formats = [
+        AudioFileFormat::OGG_VORBIS_96,
+        AudioFileFormat::OGG_VORBIS_320,
+        AudioFileFormat::AAC_24,
+    ];
+    assert_eq!(streamed_bitrate(&formats), Some(REQUESTED_BITRATE));
+}
+
+#[test]
+fn free_tier_formats_fall_back() {
+    let formats = [AudioFileFormat::OGG_VORBIS_160, AudioFileFormat::MP3_96];
+    assert_eq!(streamed_bitrate(&formats), Some(160));
+}
+
+#[test]
+fn formats_librespot_cannot_decode_are_ignored() {
+    let formats = [AudioFileFormat::AAC_48, AudioFileFormat::FLAC_FLAC];
+    assert_eq!(streamed_bitrate(&formats), None);
+}
@@ -0,0 +1,74 @@
+use std::sync::Arc;
+use std::sync::atomic::AtomicU64;
+use std::time::Duration;
+
+use spotify_dl::budget::{parse_size, streamed_bytes};
+use spotify_dl::stream::simulate::Simulation;
+
+mod common;
+
+#[test]
+fn parses_sizes_with_units() {
+    assert_eq!(parse_size("1500").unwrap(), 1500);
+    assert_eq!(parse_size("2G").unwrap(), 2_000_000_000);
+    assert_eq!(parse_size("500MB").unwrap(), 500_000_000);
+    assert_eq!(parse_size("1.5g").unwrap(), 1_500_000_000);
+    assert_eq!(parse_size("64k").unwrap(), 64_000);
+    for invalid in ["", "0", "G", "2X", "-1G"] {
+        assert!(parse_size(invalid).is_err(), "{invalid}");
+    }
+}
+
+#[test]
+fn estimates_streamed_bytes_at_320_kbps() {
+    assert_eq!(streamed_bytes(Duration::from_secs(60)), 2_400_000);
+}
+
+#[tokio::test]
+async fn defers_tracks_past_the_data_budget() {
+    let dir = common::scratch_dir();
+    let destination = dir.path();
+    let mut options = common::download_options(destination);
+    // Room for two one second tracks, not three.
+    options.data_budget = Some(streamed_bytes(Duration::from_secs(1)) * 5 / 2);
+    let tracks = [
+        "spotify:track:4uLU6hMCjMI75M1A2tKUQC",
+        "spotify:track:7GhIk7Il098yCjg4BQjzvb",
+        "spotify:track:0VjIjW4GlUZAMYd2vXMi3b",
+    ]
+    .map(common::track)
+    .to_vec();
+    let simulation: Simulation = "duration=1".parse().unwrap();
+
+    let summary = common::simulated_downloader(&simulation, destination)
+        .download_tracks(tracks, &options)
+        .await
+        .unwrap();
+    assert_eq!(summary.downloaded.len(), 2);
+    assert_eq!(summary.deferred, 1);
+    assert!(summary.failed.is_empty());
+}
+
+#[tokio::test]
+async fn downloaders_of_a_run_share_the_data_budget() {
+    let dir = common::scratch_dir();
+    let destination = dir.path();
+    let mut options = common::download_options(destination);
+    options.data_budget = Some(streamed_bytes(Duration::from_secs(1)) * 3 / 2);
+    let simulation: Simulation = "duration=1".parse().unwrap();
+    let data_used = Arc::new(AtomicU64::new(0));
+
+    let mut deferred = 0;
+    for uri in [
+        "spotify:track:4uLU6hMCjMI75M1A2tKUQC",
+        "spotify:track:7GhIk7Il098yCjg4BQjzvb",
+    ] {
+        let summary = common::simulated_downloader(&simulation, destination)
+            .with_data_used(Arc::clone(&data_used))
+            .download_tracks(vec![common::track(uri)], &options)
+            .await
+            .unwrap();
+        deferred += summary.deferred;
+    }
+    assert_eq!(deferred, 1);
+}
@@ -0,0 +1,48 @@
+use std::time::Duration;
+
+use spotify_dl::chapters::{Chapter, format_timestamp, parse_chapters};
+
+fn chapter(seconds: u64, title: &str) -> Chapter {
+    Chapter {
+        start: Duration::from_secs(seconds),
+        title: title.to_string(),
+    }
+}
+
+#[test]
+fn reads_timestamped_lines_from_the_description() {
+    let description = "This week we talk about everything.\n\
+        \n\
+        00:00 Intro\n\
+        (05:12) - The news\n\
+        - 47:30: Listener questions\n\
+        [1:02:03] | Outro\n\
+        \n\
+        Follow us everywhere. See you at 9:30!";
+
+    assert_eq!(
+        parse_chapters(description),
+        vec![
+            chapter(0, "Intro"),
+            chapter(312, "The news"),
+            chapter(2850, "Listener questions"),
+            chapter(3723, "Outro"),
+        ]
+    );
+}
+
+#[test]
+fn ignores_descriptions_without_a_chapter_list() {
+    assert!(parse_chapters("Doors open at 19:30, see you there.").is_empty());
+    assert!(parse_chapters("10:00 Second\n05:00 First").is_empty());
+    assert!(parse_chapters("00:00 Intro\n00:75 Not a time").is_empty());
+}
+
+#[test]
+fn formats_chapter_starts_with_milliseconds() {
+    assert_eq!(format_timestamp(Duration::from_secs(3723)), "01:02:03.000");
+    assert_eq!(
+        format_timestamp(Duration::from_millis(1500)),
+        "00:00:01.500"
+    );
+}
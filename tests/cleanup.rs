@@ -0,0 +1,93 @@
+use std::fs::{self, File};
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+use spotify_dl::cleanup::{is_part_file, remove_stale_parts};
+use spotify_dl::download::DownloadOptions;
+use spotify_dl::encoder::Format;
+use spotify_dl::stream::simulate::Simulation;
+
+mod common;
+
+fn write_aged(path: &PathBuf, age: Duration) {
+    fs::write(path, b"audio").unwrap();
+    File::options()
+        .write(true)
+        .open(path)
+        .unwrap()
+        .set_modified(SystemTime::now() - age)
+        .unwrap();
+}
+
+#[test]
+fn recognizes_part_files() {
+    assert!(is_part_file(&PathBuf::from("Artist - Title.flac.part")));
+    assert!(!is_part_file(&PathBuf::from("Artist - Title.flac")));
+    assert!(!is_part_file(&PathBuf::from("part")));
+}
+
+#[test]
+fn removes_only_stale_part_files() {
+    let scratch = common::scratch_dir();
+    let dir = scratch.path().to_path_buf();
+    fs::create_dir_all(dir.join("Album")).unwrap();
+    let stale = dir.join("Album").join("Old.flac.part");
+    let fresh = dir.join("New.flac.part");
+    let audio = dir.join("Old.flac");
+    write_aged(&stale, Duration::from_secs(3 * 60 * 60));
+    write_aged(&fresh, Duration::from_secs(60));
+    write_aged(&audio, Duration::from_secs(3 * 60 * 60));
+
+    let summary = remove_stale_parts(
+        &[dir.clone(), dir.join("missing")],
+        Duration::from_secs(60 * 60),
+    )
+    .unwrap();
+
+    assert_eq!(summary.removed, vec![stale.clone()]);
+    assert_eq!(summary.bytes, 5);
+    assert!(!stale.exists());
+    assert!(fresh.exists());
+    assert!(audio.exists());
+}
+
+#[tokio::test]
+async fn failed_downloads_leave_no_part_files() {
+    let scratch = common::scratch_dir();
+    let dir = scratch.path();
+    // Files can't be moved into a destination that is a file.
+    let destination = dir.join("destination");
+    fs::write(&destination, b"").unwrap();
+    let temp_dir = dir.join("Album");
+    fs::create_dir_all(&temp_dir).unwrap();
+    let mut options = common::download_options(&destination);
+    options.temp_dir = Some(temp_dir.clone());
+    let tracks = vec![common::track("spotify:track:4uLU6hMCjMI75M1A2tKUQC")];
+    let simulation: Simulation = "duration=3".parse().unwrap();
+
+    let result = common::simulated_downloader(&simulation, dir)
+        .download_tracks(tracks, &options)
+        .await;
+
+    assert!(result.is_err());
+    assert_eq!(fs::read_dir(&temp_dir).unwrap().count(), 0);
+}
+
+#[test]
+fn part_files_keep_the_targets_folders_in_the_temp_dir() {
+    let mut options = DownloadOptions::new(Some("/music".to_string()), 1, Format::Flac, false);
+    options.temp_dir = Some(PathBuf::from("/tmp/spotify-dl"));
+    let first = options.part_path(&PathBuf::from("/music/Artist A/Intro.flac"));
+    let second = options.part_path(&PathBuf::from("/music/Artist B/Intro.flac"));
+    assert_eq!(
+        first,
+        PathBuf::from("/tmp/spotify-dl/Artist A/Intro.flac.part")
+    );
+    assert_ne!(first, second);
+
+    options.temp_dir = None;
+    assert_eq!(
+        options.part_path(&PathBuf::from("/music/Artist A/Intro.flac")),
+        PathBuf::from("/music/Artist A/Intro.flac.part")
+    );
+}
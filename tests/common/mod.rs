@@ -0,0 +1,48 @@
+//! Fixtures shared by the integration tests.
+
+// Each test crate uses only some of them.
+#![allow(dead_code)]
+
+use std::path::Path;
+use std::sync::Arc;
+
+use librespot::core::SpotifyUri;
+use spotify_dl::download::{DownloadOptions, Downloader};
+use spotify_dl::encoder::Format;
+use spotify_dl::library::LibraryIndex;
+use spotify_dl::session::offline_session;
+use spotify_dl::stream::simulate::Simulation;
+use spotify_dl::track::Track;
+use tempfile::TempDir;
+use tokio::sync::Mutex;
+
+/// A fresh directory under the system temp dir. It is deleted with
+/// everything in it when the returned [`TempDir`] is dropped, also when the
+/// test fails.
+pub fn scratch_dir() -> TempDir {
+    tempfile::Builder::new()
+        .prefix("spotify-dl-")
+        .tempdir()
+        .unwrap()
+}
+
+/// The options of a run writing FLAC files to `destination`, two at a time.
+pub fn download_options(destination: &Path) -> DownloadOptions {
+    DownloadOptions::new(
+        Some(destination.to_string_lossy().to_string()),
+        2,
+        Format::Flac,
+        false,
+    )
+}
+
+/// A downloader streaming `simulation` instead of Spotify, which indexes
+/// the files it writes in `library.json` in `dir`.
+pub fn simulated_downloader(simulation: &Simulation, dir: &Path) -> Downloader {
+    let library = Arc::new(Mutex::new(LibraryIndex::load(dir.join("library.json"))));
+    Downloader::new(offline_session(), None, library).with_simulation(simulation.clone())
+}
+
+pub fn track(uri: &str) -> Track {
+    Track::from_id(SpotifyUri::from_uri(uri).unwrap())
+}
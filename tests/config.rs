@@ -0,0 +1,32 @@
+use std::path::PathBuf;
+
+use spotify_dl::config::{Config, FormatDestination};
+use spotify_dl::encoder::Format;
+
+#[test]
+fn formats_fall_back_to_the_general_destination() {
+    let config: Config = serde_json::from_str(
+        r#"{"destination": "/music", "format_destinations": {"flac": "/archive"}}"#,
+    )
+    .unwrap();
+    assert_eq!(
+        config.destination_for(Format::Flac),
+        Some(&PathBuf::from("/archive"))
+    );
+    assert_eq!(
+        config.destination_for(Format::Mp3),
+        Some(&PathBuf::from("/music"))
+    );
+    assert_eq!(Config::default().destination_for(Format::Flac), None);
+}
+
+#[test]
+fn parses_format_destinations() {
+    let given: FormatDestination = "mp3=/portable/music".parse().unwrap();
+    assert_eq!(given.format, Format::Mp3);
+    assert_eq!(given.destination, PathBuf::from("/portable/music"));
+
+    assert!("/portable".parse::<FormatDestination>().is_err());
+    assert!("mp3=".parse::<FormatDestination>().is_err());
+    assert!("wav=/archive".parse::<FormatDestination>().is_err());
+}
@@ -0,0 +1,48 @@
+use librespot::core::SpotifyUri;
+use spotify_dl::dupes::{DupesReport, PlaylistTracks};
+
+fn track(id: &str) -> SpotifyUri {
+    SpotifyUri::from_uri(&format!("spotify:track:{id}")).unwrap()
+}
+
+fn playlist(name: &str, tracks: &[&str]) -> PlaylistTracks {
+    PlaylistTracks {
+        name: name.to_string(),
+        tracks: tracks.iter().map(|id| track(id)).collect(),
+    }
+}
+
+#[test]
+fn finds_tracks_in_several_playlists() {
+    let a = "4uLU6hMCjMI75M1A2tKUQC";
+    let b = "7GhIk7Il098yCjg4BQjzvb";
+    let c = "3n3Ppam7vgaVa1iaRUc9Lp";
+    let playlists = [
+        playlist("Morning", &[a, b]),
+        playlist("Evening", &[b, c, b]),
+        playlist("Night", &[c, a]),
+    ];
+    let report = DupesReport::of(&playlists);
+
+    assert_eq!(report.tracks, vec![track(a), track(b), track(c)]);
+    let shared: Vec<_> = report
+        .shared
+        .iter()
+        .map(|shared| (shared.id.clone(), shared.playlists.clone()))
+        .collect();
+    assert_eq!(
+        shared,
+        vec![
+            (track(a), vec![0, 2]),
+            (track(b), vec![0, 1]),
+            (track(c), vec![1, 2]),
+        ]
+    );
+}
+
+#[test]
+fn repeats_within_one_playlist_are_not_shared() {
+    let a = "4uLU6hMCjMI75M1A2tKUQC";
+    let report = DupesReport::of(&[playlist("Morning", &[a, a]), playlist("Evening", &[])]);
+    assert!(report.shared.is_empty());
+}
@@ -0,0 +1,82 @@
+use chrono::NaiveDate;
+use spotify_dl::filter::{CapKeep, TrackFacts, TrackFilter, parse_release_date};
+
+fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+    NaiveDate::from_ymd_opt(year, month, day).unwrap()
+}
+
+#[test]
+fn parses_years_and_dates() {
+    assert_eq!(parse_release_date("2020").unwrap(), date(2020, 1, 1));
+    assert_eq!(parse_release_date("2020-06-15").unwrap(), date(2020, 6, 15));
+    assert!(parse_release_date("June 2020").is_err());
+}
+
+#[test]
+fn keeps_recent_and_popular_tracks() {
+    let filter = TrackFilter {
+        released_after: Some(date(2020, 1, 1)),
+        min_popularity: Some(30),
+        ..Default::default()
+    };
+    assert!(filter.keeps(date(2020, 1, 1), Some(30)));
+    assert!(!filter.keeps(date(2019, 12, 31), Some(90)));
+    assert!(!filter.keeps(date(2024, 3, 1), Some(29)));
+    // Episodes have no popularity.
+    assert!(filter.keeps(date(2024, 3, 1), None));
+}
+
+fn facts(artist: &str, album: &str, popularity: i32) -> Option<TrackFacts> {
+    Some(TrackFacts {
+        released: None,
+        popularity: Some(popularity),
+        artist: Some(artist.to_string()),
+        album: Some(album.to_string()),
+    })
+}
+
+fn crate_of_tracks() -> Vec<(String, Option<TrackFacts>)> {
+    vec![
+        ("t1".to_string(), facts("a", "x", 10)),
+        ("t2".to_string(), facts("a", "x", 90)),
+        ("t3".to_string(), facts("b", "y", 50)),
+        ("t4".to_string(), facts("a", "z", 70)),
+        ("t5".to_string(), None),
+        ("t2".to_string(), facts("a", "x", 90)),
+    ]
+}
+
+#[test]
+fn caps_keep_the_first_listed_tracks() {
+    let filter = TrackFilter {
+        max_per_artist: Some(2),
+        max_per_album: Some(1),
+        ..Default::default()
+    };
+    assert_eq!(
+        filter.cap(&crate_of_tracks()),
+        // t2 is over the album cap, so its second listing goes too, and
+        // t5 has no metadata to go by.
+        vec![true, false, true, true, true, false]
+    );
+}
+
+#[test]
+fn caps_keep_the_most_popular_tracks() {
+    let filter = TrackFilter {
+        max_per_artist: Some(1),
+        keep: CapKeep::Popular,
+        ..Default::default()
+    };
+    assert_eq!(
+        filter.cap(&crate_of_tracks()),
+        vec![false, true, true, false, true, true]
+    );
+}
+
+#[test]
+fn parses_cap_orders() {
+    assert_eq!("first".parse::<CapKeep>().unwrap(), CapKeep::First);
+    assert_eq!("popular".parse::<CapKeep>().unwrap(), CapKeep::Popular);
+    assert!("random".parse::<CapKeep>().is_err());
+}
@@ -0,0 +1,106 @@
+use spotify_dl::encoder::verify::audio_duration;
+use spotify_dl::encoder::{Format, Samples, get_encoder, get_stream_encoder};
+
+mod common;
+
+/// Not a multiple of any block or frame size, so both encoders have to pad
+/// the end of the track.
+const FRAMES: usize = 44100 * 2 + 123;
+
+fn tone() -> Samples {
+    let samples = (0..FRAMES)
+        .flat_map(|i| {
+            let sample = ((i as f64 * 0.05).sin() * i32::MAX as f64 * 0.5) as i32;
+            [sample, sample]
+        })
+        .collect();
+    Samples {
+        samples,
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn flac_keeps_the_exact_track_length() {
+    let stream = get_encoder(Format::Flac).encode(tone()).await.unwrap();
+
+    let mut reader = claxon::FlacReader::new(stream.stream.as_slice()).unwrap();
+    let info = reader.streaminfo();
+    assert_eq!(info.samples, Some(FRAMES as u64));
+    assert_eq!(info.min_block_size, info.max_block_size);
+
+    let decoded = reader.samples().count();
+    assert_eq!(decoded, FRAMES * 2);
+}
+
+#[test]
+fn streamed_flac_decodes_to_every_sample() {
+    let samples = tone().samples;
+    let mut encoder = get_stream_encoder(Format::Flac, 44100, 2).unwrap();
+    let mut stream = Vec::new();
+    // Chunks that don't line up with the block size, like the ones the
+    // player hands out.
+    for chunk in samples.chunks(1000) {
+        stream.extend(encoder.encode(chunk).unwrap());
+    }
+    stream.extend(encoder.finish().unwrap());
+
+    let mut reader = claxon::FlacReader::new(stream.as_slice()).unwrap();
+    assert_eq!(reader.streaminfo().samples, None);
+    let decoded = reader.samples().map(Result::unwrap).collect::<Vec<_>>();
+    let expected = samples
+        .iter()
+        .map(|&sample| sample >> 8)
+        .collect::<Vec<_>>();
+    assert_eq!(decoded, expected);
+}
+
+#[cfg(feature = "mp3")]
+#[tokio::test]
+async fn mp3_records_encoder_delay_and_padding() {
+    const SAMPLES_PER_FRAME: usize = 1152;
+
+    let stream = get_encoder(Format::Mp3).encode(tone()).await.unwrap();
+    let data = stream.stream.as_slice();
+
+    let info = data
+        .windows(4)
+        .position(|window| window == b"Info" || window == b"Xing")
+        .expect("no Info tag in the first frame");
+    assert!(info < 64, "Info tag is not in the first frame");
+    let frames = u32::from_be_bytes(data[info + 8..info + 12].try_into().unwrap()) as usize;
+
+    let lame = info + 120;
+    assert_eq!(&data[lame..lame + 4], b"LAME");
+    let delay_padding = &data[lame + 21..lame + 24];
+    let delay = ((delay_padding[0] as usize) << 4) | ((delay_padding[1] as usize) >> 4);
+    let padding = (((delay_padding[1] & 0x0f) as usize) << 8) | delay_padding[2] as usize;
+    assert!(delay > 0);
+
+    assert_eq!(frames * SAMPLES_PER_FRAME - delay - padding, FRAMES);
+}
+
+#[tokio::test]
+async fn encoded_files_report_the_track_length() {
+    let dir = common::scratch_dir();
+    let expected = std::time::Duration::from_secs_f64(FRAMES as f64 / 44100.0);
+
+    let formats = [
+        Format::Flac,
+        #[cfg(feature = "mp3")]
+        Format::Mp3,
+    ];
+    for format in formats {
+        let stream = get_encoder(format).encode(tone()).await.unwrap();
+        let path = dir.path().join("track").with_extension(format.extension());
+        std::fs::write(&path, &stream.stream).unwrap();
+
+        let actual = audio_duration(&path, format).unwrap().unwrap();
+        // MP3 adds the encoder delay and padding of less than two frames.
+        assert!(
+            actual.abs_diff(expected).as_secs_f64() < 0.1,
+            "{:?}",
+            format
+        );
+    }
+}
@@ -0,0 +1,149 @@
+use std::collections::HashSet;
+
+use librespot::core::SpotifyUri;
+use spotify_dl::history::{HISTORY_FILE, PlaylistHistory};
+use spotify_dl::library::{LIBRARY_FILE, LibraryIndex};
+use spotify_dl::sync::{
+    PLAYLIST_INFO_FILE, PlaylistDiff, apply_diff, write_playlist_details,
+    write_shared_playlist_details,
+};
+use spotify_dl::track::PlaylistDetails;
+
+mod common;
+
+fn uri(uri: &str) -> SpotifyUri {
+    SpotifyUri::from_uri(uri).unwrap()
+}
+
+#[test]
+fn positions_continue_across_syncs() {
+    let dir = common::scratch_dir();
+    let path = dir.path().join("history.json");
+    let playlist = uri("spotify:playlist:37i9dQZEVXbMDoHDwVN2tF");
+    let first = "spotify:track:4uLU6hMCjMI75M1A2tKUQC".to_string();
+    let second = "spotify:track:7GhIk7Il098yCjg4BQjzvb".to_string();
+    let added = "spotify:track:0VjIjW4GlUZAMYd2vXMi3b".to_string();
+
+    let mut history = PlaylistHistory::load(path.clone());
+    history
+        .assign_positions(&playlist, &[first.clone(), second.clone()])
+        .unwrap();
+
+    // The added track goes first and the first one was removed, yet the
+    // remaining track keeps its number.
+    let mut history = PlaylistHistory::load(path.clone());
+    history
+        .assign_positions(&playlist, &[added.clone(), second.clone()])
+        .unwrap();
+    assert_eq!(history.position(&playlist, &uri(&first)), Some(1));
+    assert_eq!(history.position(&playlist, &uri(&second)), Some(2));
+    assert_eq!(history.position(&playlist, &uri(&added)), Some(3));
+
+    let other = uri("spotify:playlist:37i9dQZF1DXcBWIGoYBM5M");
+    assert_eq!(history.position(&other, &uri(&second)), None);
+}
+
+#[test]
+fn sync_delete_only_deletes_the_playlists_copy() {
+    let dir = common::scratch_dir();
+    let destination = dir.path();
+    std::fs::create_dir_all(destination.join("Album")).unwrap();
+    let playlist = uri("spotify:playlist:37i9dQZEVXbMDoHDwVN2tF");
+    let removed = "spotify:track:4uLU6hMCjMI75M1A2tKUQC".to_string();
+    let playlist_copy = destination.join("Song.flac");
+    let album_copy = destination.join("Album").join("Song.flac");
+    std::fs::write(&playlist_copy, b"playlist").unwrap();
+    std::fs::write(&album_copy, b"album").unwrap();
+
+    let mut history = PlaylistHistory::load(destination.join(HISTORY_FILE));
+    history.record_download(&playlist, &uri(&removed)).unwrap();
+    history
+        .record_file(&playlist, &uri(&removed), &playlist_copy)
+        .unwrap();
+    history
+        .record_snapshot(&playlist, vec![removed.clone()])
+        .unwrap();
+    let mut library = LibraryIndex::load(destination.join(LIBRARY_FILE));
+    library
+        .record(&playlist_copy, &uri(&removed), None)
+        .unwrap();
+    library.record(&album_copy, &uri(&removed), None).unwrap();
+
+    let diff = PlaylistDiff::compute(playlist.to_string(), history.snapshot(&playlist), &[]);
+    let changes = apply_diff(
+        &playlist,
+        &[],
+        &diff,
+        &mut history,
+        &mut library,
+        true,
+        &HashSet::new(),
+    )
+    .unwrap();
+
+    assert_eq!(changes.deleted, vec![playlist_copy.clone()]);
+    assert!(!playlist_copy.exists());
+    assert!(album_copy.exists());
+    assert_eq!(library.path_of(&uri(&removed)), Some(album_copy.as_path()));
+}
+
+#[test]
+fn moved_files_are_not_taken_for_deleted() {
+    let dir = common::scratch_dir();
+    let destination = dir.path();
+    let playlist = uri("spotify:playlist:37i9dQZEVXbMDoHDwVN2tF");
+    let track = "spotify:track:4uLU6hMCjMI75M1A2tKUQC".to_string();
+    let file = destination.join("Song.flac");
+    std::fs::write(&file, b"song").unwrap();
+
+    let mut history = PlaylistHistory::load(destination.join(HISTORY_FILE));
+    history.record_download(&playlist, &uri(&track)).unwrap();
+    history
+        .record_snapshot(&playlist, vec![track.clone()])
+        .unwrap();
+    let mut library = LibraryIndex::load(destination.join(LIBRARY_FILE));
+    library.record(&file, &uri(&track), None).unwrap();
+
+    std::fs::create_dir_all(destination.join("Sorted")).unwrap();
+    let moved = destination.join("Sorted").join("Renamed.flac");
+    std::fs::rename(&file, &moved).unwrap();
+
+    let current = vec![track.clone()];
+    library.relink_missing(destination, &current).unwrap();
+    let diff = PlaylistDiff::compute(playlist.to_string(), history.snapshot(&playlist), &current);
+    let changes = apply_diff(
+        &playlist,
+        &current,
+        &diff,
+        &mut history,
+        &mut library,
+        false,
+        &HashSet::new(),
+    )
+    .unwrap();
+
+    assert!(changes.tombstoned.is_empty());
+    assert!(!history.is_tombstoned(&uri(&track)));
+    assert_eq!(library.path_of(&uri(&track)), Some(moved.as_path()));
+}
+
+#[test]
+fn playlists_sharing_a_folder_get_details_named_after_them() {
+    let dir = common::scratch_dir();
+    let details = |name: &str| PlaylistDetails {
+        uri: "spotify:playlist:37i9dQZEVXbMDoHDwVN2tF".to_string(),
+        name: name.to_string(),
+        description: String::new(),
+        cover: Some(bytes::Bytes::from_static(b"jpeg")),
+    };
+
+    write_playlist_details(dir.path(), &details("Alone")).unwrap();
+    write_shared_playlist_details(dir.path(), &details("Road Trip")).unwrap();
+    write_shared_playlist_details(dir.path(), &details("Workout")).unwrap();
+
+    let info = |name: &str| std::fs::read_to_string(dir.path().join(name)).unwrap();
+    assert!(info(PLAYLIST_INFO_FILE).starts_with("Alone\n"));
+    assert!(info("Road Trip.txt").starts_with("Road Trip\n"));
+    assert!(info("Workout.txt").starts_with("Workout\n"));
+    assert!(dir.path().join("Workout.jpg").exists());
+}
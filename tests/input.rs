@@ -0,0 +1,125 @@
+use librespot::core::SpotifyUri;
+use spotify_dl::input::{Link, canonical_uris, find_links, parse_link};
+use spotify_dl::session::offline_session;
+
+const PLAYLIST: &str = "spotify:playlist:37i9dQZF1DXcBWIGoYBM5M";
+const ALBUM: &str = "spotify:album:4aawyAB9vmqN3uQ7FjRGTy";
+const TRACK: &str = "spotify:track:4uLU6hMCjMI75M1A2tKUQC";
+
+fn uri(uri: &str) -> Link {
+    Link::Uri(uri.to_string())
+}
+
+#[test]
+fn finds_uris_and_urls() {
+    assert_eq!(find_links(PLAYLIST), [uri(PLAYLIST)]);
+    assert_eq!(
+        find_links("https://open.spotify.com/album/4aawyAB9vmqN3uQ7FjRGTy"),
+        [uri(ALBUM)]
+    );
+    assert_eq!(
+        find_links("spotify:user:spotify:playlist:37i9dQZF1DXcBWIGoYBM5M"),
+        [uri(PLAYLIST)]
+    );
+}
+
+#[test]
+fn ignores_query_parameters_and_fragments() {
+    assert_eq!(
+        find_links(
+            "https://open.spotify.com/playlist/37i9dQZF1DXcBWIGoYBM5M?si=1a2b3c4d&pt=abc#top"
+        ),
+        [uri(PLAYLIST)]
+    );
+}
+
+#[test]
+fn ignores_locale_prefixes_and_other_url_forms() {
+    for url in [
+        "https://open.spotify.com/intl-de/track/4uLU6hMCjMI75M1A2tKUQC",
+        "https://open.spotify.com/intl-pt-BR/track/4uLU6hMCjMI75M1A2tKUQC?si=x",
+        "https://open.spotify.com/embed/track/4uLU6hMCjMI75M1A2tKUQC",
+        "http://open.spotify.com/track/4uLU6hMCjMI75M1A2tKUQC",
+        "open.spotify.com/track/4uLU6hMCjMI75M1A2tKUQC",
+        "https://play.spotify.com/track/4uLU6hMCjMI75M1A2tKUQC",
+    ] {
+        assert_eq!(find_links(url), [uri(TRACK)], "{url}");
+    }
+    assert_eq!(
+        find_links("https://open.spotify.com/user/someone/playlist/37i9dQZF1DXcBWIGoYBM5M"),
+        [uri(PLAYLIST)]
+    );
+}
+
+#[test]
+fn finds_short_links() {
+    assert_eq!(
+        find_links("https://spotify.link/AbC123xyz"),
+        [Link::Short("https://spotify.link/AbC123xyz".to_string())]
+    );
+    assert_eq!(
+        find_links("spotify.link/AbC123xyz?si=1"),
+        [Link::Short("https://spotify.link/AbC123xyz".to_string())]
+    );
+}
+
+#[test]
+fn finds_links_in_text() {
+    let message = "omg listen to this 🔥 https://open.spotify.com/track/4uLU6hMCjMI75M1A2tKUQC?si=abc, \
+                   and the album (spotify:album:4aawyAB9vmqN3uQ7FjRGTy)! also https://spotify.link/Xyz9";
+    assert_eq!(
+        find_links(message),
+        [
+            uri(TRACK),
+            uri(ALBUM),
+            Link::Short("https://spotify.link/Xyz9".to_string())
+        ]
+    );
+}
+
+#[test]
+fn finds_several_links_in_one_argument() {
+    assert_eq!(
+        find_links(&format!("{TRACK},{ALBUM} {PLAYLIST}")),
+        [uri(TRACK), uri(ALBUM), uri(PLAYLIST)]
+    );
+}
+
+#[test]
+fn finds_nothing_without_links() {
+    assert!(find_links("").is_empty());
+    assert!(find_links("https://example.com/track/4uLU6hMCjMI75M1A2tKUQC").is_empty());
+    assert!(find_links("https://open.spotify.com/").is_empty());
+    assert!(find_links("spotify:local:Artist:Album:Title:180").is_empty());
+}
+
+#[test]
+fn parses_the_first_link() {
+    assert_eq!(
+        parse_link("see https://open.spotify.com/intl-fr/album/4aawyAB9vmqN3uQ7FjRGTy?si=1"),
+        SpotifyUri::from_uri(ALBUM).ok()
+    );
+    assert_eq!(parse_link(TRACK), SpotifyUri::from_uri(TRACK).ok());
+    assert_eq!(parse_link("https://spotify.link/AbC123xyz"), None);
+    assert_eq!(parse_link("nothing here"), None);
+}
+
+#[tokio::test]
+async fn canonicalizes_and_deduplicates_inputs() {
+    let inputs = vec![
+        format!("{TRACK} https://open.spotify.com/intl-de/album/4aawyAB9vmqN3uQ7FjRGTy?si=1"),
+        "https://open.spotify.com/track/4uLU6hMCjMI75M1A2tKUQC?si=other".to_string(),
+        PLAYLIST.to_string(),
+        format!("{PLAYLIST},{TRACK}"),
+    ];
+    assert_eq!(
+        canonical_uris(&inputs, &offline_session()).await.unwrap(),
+        [TRACK, ALBUM, PLAYLIST]
+    );
+}
+
+#[tokio::test]
+async fn inputs_without_links_are_errors() {
+    let inputs = vec!["just some chat message".to_string()];
+    assert!(canonical_uris(&inputs, &offline_session()).await.is_err());
+}
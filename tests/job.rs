@@ -0,0 +1,62 @@
+use librespot::core::SpotifyUri;
+use spotify_dl::config::Config;
+use spotify_dl::job::{Job, job_arguments};
+use spotify_dl::track::Track;
+
+mod common;
+
+fn uri(uri: &str) -> SpotifyUri {
+    SpotifyUri::from_uri(uri).unwrap()
+}
+
+#[test]
+fn job_arguments_leave_out_inputs_and_the_job_file() {
+    let arguments = [
+        "-f",
+        "flac",
+        "--save-job",
+        "job.json",
+        "spotify:playlist:37i9dQZEVXbMDoHDwVN2tF",
+        "--released-after",
+        "2020",
+        "--save-job=other.json",
+    ]
+    .map(String::from);
+    let inputs = vec!["spotify:playlist:37i9dQZEVXbMDoHDwVN2tF".to_string()];
+    assert_eq!(
+        job_arguments(arguments, &inputs),
+        ["-f", "flac", "--released-after", "2020"]
+    );
+}
+
+#[test]
+fn jobs_keep_where_tracks_came_from() {
+    let dir = common::scratch_dir();
+    let path = dir.path().join("job.json");
+    let playlist = uri("spotify:playlist:37i9dQZEVXbMDoHDwVN2tF");
+    let album = uri("spotify:album:6N9PS4QXF1D0OWPk0Sxtb4");
+    let tracks = vec![
+        Track::from_playlist(
+            uri("spotify:track:4uLU6hMCjMI75M1A2tKUQC"),
+            playlist.clone(),
+        ),
+        Track::from_album(uri("spotify:track:7GhIk7Il098yCjg4BQjzvb"), album.clone()),
+        Track::from_id(uri("spotify:track:0VjIjW4GlUZAMYd2vXMi3b")),
+    ];
+    let config = Config {
+        format: Some("flac".to_string()),
+        ..Default::default()
+    };
+    Job::new(vec!["--verify".to_string()], config, &tracks)
+        .save(&path)
+        .unwrap();
+
+    let job = Job::load(&path).unwrap();
+    assert_eq!(job.arguments, ["--verify"]);
+    assert_eq!(job.config.format.as_deref(), Some("flac"));
+    let loaded = job.tracks();
+    assert_eq!(loaded.len(), 3);
+    assert_eq!(loaded[0].playlist(), Some(playlist));
+    assert_eq!(loaded[1].album(), Some(album));
+    assert_eq!(loaded[2].id, tracks[2].id);
+}
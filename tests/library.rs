@@ -0,0 +1,69 @@
+use librespot::core::SpotifyUri;
+use spotify_dl::library::{LIBRARY_FILE, LibraryIndex, find_loosely_named, loose_name};
+
+mod common;
+
+#[test]
+fn loose_names_ignore_case_accents_and_normalization() {
+    let composed = "Beyonc\u{e9} - Halo";
+    let decomposed = "Beyonce\u{301} - Halo";
+    assert_ne!(composed, decomposed);
+    assert_eq!(loose_name(composed), loose_name(decomposed));
+    assert_eq!(loose_name(composed), loose_name("BEYONCE - HALO"));
+    assert_eq!(
+        loose_name("Sigur Rós - Hoppípolla"),
+        "sigur ros - hoppipolla"
+    );
+    assert_eq!(loose_name("Mø - Lean On"), "mo - lean on");
+    assert_eq!(
+        loose_name("Die Ärzte - Schrei nach Liebe"),
+        "die arzte - schrei nach liebe"
+    );
+    assert_ne!(loose_name("Halo"), loose_name("Hello"));
+}
+
+#[test]
+fn finds_files_named_before_the_policy_changed() {
+    let scratch = common::scratch_dir();
+    let dir = scratch.path();
+    std::fs::write(dir.join("Beyonce - Halo.FLAC"), b"").unwrap();
+    std::fs::write(dir.join("Beyonce - Halo.mp3"), b"").unwrap();
+
+    assert_eq!(
+        find_loosely_named(&dir.join("Beyoncé - Halo.flac")),
+        Some(dir.join("Beyonce - Halo.FLAC"))
+    );
+    assert_eq!(
+        find_loosely_named(&dir.join("Beyoncé - Crazy in Love.flac")),
+        None
+    );
+}
+
+#[test]
+fn recorded_files_are_written_in_batches() {
+    let scratch = common::scratch_dir();
+    let dir = scratch.path();
+    let track = SpotifyUri::from_uri("spotify:track:4uLU6hMCjMI75M1A2tKUQC").unwrap();
+    let first = dir.join("First.flac");
+    let second = dir.join("Second.flac");
+    std::fs::write(&first, b"first").unwrap();
+    std::fs::write(&second, b"second").unwrap();
+
+    let mut library = LibraryIndex::load(dir.join(LIBRARY_FILE));
+    library.record(&first, &track, None).unwrap();
+    library.record(&second, &track, None).unwrap();
+    assert!(!dir.join(LIBRARY_FILE).exists());
+    library.flush().unwrap();
+
+    let mut library = LibraryIndex::load(dir.join(LIBRARY_FILE));
+    assert_eq!(library.path_of(&track), Some(first.as_path()));
+    std::fs::remove_file(&first).unwrap();
+    assert_eq!(library.path_of(&track), Some(second.as_path()));
+    assert!(library.remove_file(&second).unwrap());
+    assert!(!library.contains(&track));
+    drop(library);
+
+    let library = LibraryIndex::load(dir.join(LIBRARY_FILE));
+    assert!(!library.contains(&track));
+    assert!(library.path_of(&track).is_none());
+}
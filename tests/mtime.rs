@@ -0,0 +1,42 @@
+use std::time::{Duration, UNIX_EPOCH};
+
+use spotify_dl::mtime::{FileMtime, from_timestamp_ms, set_mtime};
+
+mod common;
+
+#[test]
+fn parses_file_mtimes() {
+    assert_eq!(
+        "release-date".parse::<FileMtime>().unwrap(),
+        FileMtime::ReleaseDate
+    );
+    assert_eq!(
+        "added-date".parse::<FileMtime>().unwrap(),
+        FileMtime::AddedDate
+    );
+    assert_eq!("now".parse::<FileMtime>().unwrap(), FileMtime::Now);
+    assert!("added".parse::<FileMtime>().is_err());
+}
+
+#[test]
+fn unknown_timestamps_have_no_time() {
+    assert_eq!(
+        from_timestamp_ms(1_600_000_000_000),
+        Some(UNIX_EPOCH + Duration::from_secs(1_600_000_000))
+    );
+    assert_eq!(from_timestamp_ms(0), None);
+    assert_eq!(from_timestamp_ms(-1), None);
+}
+
+#[test]
+fn sets_the_modification_time() {
+    let dir = common::scratch_dir();
+    let path = dir.path().join("track.flac");
+    std::fs::write(&path, b"audio").unwrap();
+    let added = UNIX_EPOCH + Duration::from_secs(1_500_000_000);
+
+    set_mtime(&path, added).unwrap();
+
+    let modified = std::fs::metadata(&path).unwrap().modified().unwrap();
+    assert_eq!(modified, added);
+}
@@ -0,0 +1,31 @@
+use librespot::core::SpotifyUri;
+use spotify_dl::overrides::Overrides;
+
+#[test]
+fn reads_corrections_by_track_uri() {
+    let overrides = Overrides::parse(
+        r#"
+        ["spotify:track:4uLU6hMCjMI75M1A2tKUQC"]
+        title = "Never Gonna Give You Up"
+        artists = ["Rick Astley"]
+        genre = "Synth-pop"
+        "#,
+    )
+    .unwrap();
+
+    let id = SpotifyUri::from_uri("spotify:track:4uLU6hMCjMI75M1A2tKUQC").unwrap();
+    let corrections = overrides.get(&id).unwrap();
+    assert_eq!(
+        corrections.title.as_deref(),
+        Some("Never Gonna Give You Up")
+    );
+    assert_eq!(corrections.artists, Some(vec!["Rick Astley".to_string()]));
+    assert_eq!(corrections.album, None);
+    assert_eq!(corrections.genre.as_deref(), Some("Synth-pop"));
+}
+
+#[test]
+fn rejects_unknown_fields_and_uris() {
+    assert!(Overrides::parse("[\"spotify:track:4uLU6hMCjMI75M1A2tKUQC\"]\nyear = 1987").is_err());
+    assert!(Overrides::parse("[\"not a uri\"]\ntitle = \"Title\"").is_err());
+}
@@ -0,0 +1,49 @@
+use std::time::Duration;
+
+use spotify_dl::download::DownloadOptions;
+use spotify_dl::encoder::Format;
+use spotify_dl::profile::{Profile, track_delay};
+
+#[test]
+fn parses_profiles() {
+    assert_eq!("stealth".parse::<Profile>().unwrap(), Profile::Stealth);
+    assert_eq!("balanced".parse::<Profile>().unwrap(), Profile::Balanced);
+    assert_eq!("fast".parse::<Profile>().unwrap(), Profile::Fast);
+    assert!("turbo".parse::<Profile>().is_err());
+}
+
+#[test]
+fn profiles_set_the_scheduler() {
+    let mut options = DownloadOptions::new(None, Profile::Fast.parallel(), Format::Flac, false);
+    Profile::Fast.apply(&mut options);
+    assert_eq!(options.parallel, 8);
+    assert_eq!(options.delay_factor, 0.0);
+    assert_eq!(options.tracks_per_minute, None);
+
+    let mut options = DownloadOptions::new(None, 4, Format::Flac, false);
+    Profile::Stealth.apply(&mut options);
+    // -t wins over the profile's parallelism, the rest comes from it.
+    assert_eq!(options.parallel, 4);
+    assert_eq!(options.delay_factor, 0.3);
+    assert_eq!(options.retry_backoff, Duration::from_secs(30));
+    assert_eq!(options.tracks_per_minute, Some(10));
+}
+
+#[test]
+fn only_single_downloads_pause_by_default() {
+    let options = DownloadOptions::new(None, 1, Format::Flac, false);
+    assert_eq!(options.delay_factor, 0.2);
+    assert_eq!(options.delay_jitter, 0.0);
+    let options = DownloadOptions::new(None, 3, Format::Flac, false);
+    assert_eq!(options.delay_factor, 0.0);
+}
+
+#[test]
+fn jitter_spreads_the_delay_both_ways() {
+    let track = Duration::from_secs(200);
+    assert_eq!(track_delay(track, 0.2, 0.0, 0.9), Duration::from_secs(40));
+    assert_eq!(track_delay(track, 0.2, 0.5, 0.0), Duration::from_secs(20));
+    assert_eq!(track_delay(track, 0.2, 0.5, 0.5), Duration::from_secs(40));
+    assert!(track_delay(track, 0.2, 0.5, 0.999) < Duration::from_secs(60));
+    assert_eq!(track_delay(track, 0.0, 0.5, 0.7), Duration::ZERO);
+}
@@ -0,0 +1,34 @@
+use console::measure_text_width;
+use spotify_dl::progress::fit_label;
+
+#[test]
+fn short_labels_are_padded() {
+    assert_eq!(fit_label("Encoding a", 12), "Encoding a  ");
+}
+
+#[test]
+fn long_labels_are_cut_with_an_ellipsis() {
+    assert_eq!(
+        fit_label("Downloading Artist - Title", 16),
+        "Downloading Art…"
+    );
+}
+
+#[test]
+fn wide_characters_are_measured_by_their_display_width() {
+    let label = fit_label("Encoding YOASOBI - 夜に駆ける (Official)", 24);
+    assert_eq!(measure_text_width(&label), 24);
+    assert_eq!(label, "Encoding YOASOBI - 夜に…");
+
+    for title in ["夜に駆ける夜に駆ける", "🎧🎶🎵🎤🎸🎹", "Ａｂｃｄｅｆｇ"]
+    {
+        for width in 10..16 {
+            assert_eq!(measure_text_width(&fit_label(title, width)), width);
+        }
+    }
+}
+
+#[test]
+fn line_breaks_do_not_break_the_layout() {
+    assert_eq!(fit_label("Title\nwith\tbreaks", 20), "Title with breaks   ");
+}
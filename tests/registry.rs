@@ -0,0 +1,82 @@
+use std::path::Path;
+use std::sync::Mutex;
+
+use anyhow::Result;
+use spotify_dl::encoder::tags::{Tags, read_spotify_uri, store_tags};
+use spotify_dl::encoder::{
+    EncodedStream, Encoder, Format, FormatPlugin, Samples, get_encoder, register_format,
+};
+
+/// Raw little endian PCM, with the tags kept in memory.
+struct RawFormat {
+    stored_uri: Mutex<Option<String>>,
+}
+
+static RAW: RawFormat = RawFormat {
+    stored_uri: Mutex::new(None),
+};
+
+#[async_trait::async_trait]
+impl Encoder for RawFormat {
+    async fn encode(&self, samples: Samples) -> Result<EncodedStream> {
+        let bytes = samples
+            .samples
+            .iter()
+            .flat_map(|sample| sample.to_le_bytes())
+            .collect();
+        Ok(EncodedStream::new(bytes))
+    }
+}
+
+impl FormatPlugin for RawFormat {
+    fn name(&self) -> &'static str {
+        "raw"
+    }
+
+    fn extension(&self) -> &'static str {
+        "pcm"
+    }
+
+    fn store_tags(&self, _path: &Path, tags: &Tags) -> Result<()> {
+        *self.stored_uri.lock().unwrap() = tags.spotify_uri.clone();
+        Ok(())
+    }
+
+    fn read_spotify_uri(&self, _path: &Path) -> Option<String> {
+        self.stored_uri.lock().unwrap().clone()
+    }
+}
+
+#[tokio::test]
+async fn registered_formats_work_like_built_in_ones() {
+    assert!("raw".parse::<Format>().is_err());
+    let format = register_format(&RAW).unwrap();
+
+    assert_eq!("raw".parse::<Format>().unwrap(), format);
+    assert_eq!(Format::from_extension("PCM"), Some(format));
+    assert_eq!(format.extension(), "pcm");
+
+    let samples = Samples::new(vec![1, -1], 44100, 2, 32);
+    let stream = get_encoder(format).encode(samples).await.unwrap();
+    assert_eq!(stream.stream, [1, 0, 0, 0, 255, 255, 255, 255]);
+
+    let path = Path::new("track.pcm");
+    let tags = Tags {
+        title: "Title".to_string(),
+        artists: vec!["Artist".to_string()],
+        album_title: "Album".to_string(),
+        album_cover: None,
+        spotify_uri: Some("spotify:track:4uLU6hMCjMI75M1A2tKUQC".to_string()),
+        isrc: None,
+        track_number: None,
+        disc_number: None,
+        disc_count: None,
+        chapters: Vec::new(),
+        genre: None,
+    };
+    store_tags(path, &tags, format).await.unwrap();
+    assert_eq!(read_spotify_uri(path, format), tags.spotify_uri);
+
+    // Names and extensions can't be taken twice, nor from built-in formats.
+    assert!(register_format(&RAW).is_err());
+}
@@ -0,0 +1,44 @@
+use std::collections::HashMap;
+
+use spotify_dl::download::{RunSummary, SkipReason, TrackReport};
+use spotify_dl::report::render_html;
+use spotify_dl::sync::PlaylistDiff;
+
+fn track(name: &str, error: Option<&str>) -> TrackReport {
+    TrackReport {
+        uri: "spotify:track:4uLU6hMCjMI75M1A2tKUQC".to_string(),
+        name: name.to_string(),
+        album: Some("Whenever You Need Somebody".to_string()),
+        cover_url: Some("https://i.scdn.co/image/ab67616d".to_string()),
+        error: error.map(ToString::to_string),
+    }
+}
+
+#[test]
+fn lists_tracks_failures_and_diffs() {
+    let mut summary = RunSummary {
+        downloaded: vec![track("Rick Astley - Never Gonna Give You Up", None)],
+        failed: vec![track("Rick & Morty <Theme>", Some("Stream stalled"))],
+        ..Default::default()
+    };
+    summary.record_skip(SkipReason::Exists, 2);
+    summary.record_skip(SkipReason::Filtered, 1);
+    let previous = vec!["spotify:track:a".to_string()];
+    let current = vec!["spotify:track:b".to_string()];
+    let diff = PlaylistDiff::compute("Mix".to_string(), Some(&previous), &current);
+    let thumbnails = HashMap::from([(
+        "https://i.scdn.co/image/ab67616d".to_string(),
+        "data:image/jpeg;base64,AAAA".to_string(),
+    )]);
+
+    let html = render_html(&summary, &[&diff], &thumbnails, "2026-10-15 03:00");
+
+    assert!(html.contains("1 downloaded, 3 skipped, 1 failed"));
+    assert!(html.contains("Skipped: already exists: 2, filtered: 1"));
+    assert!(html.contains("Rick &amp; Morty &lt;Theme&gt;"));
+    assert!(html.contains("Stream stalled"));
+    assert!(html.contains("<img src=\"data:image/jpeg;base64,AAAA\""));
+    assert!(html.contains("Mix: 1 added, 1 removed, 0 reordered"));
+    assert!(html.contains("<li>spotify:track:b</li>"));
+    assert!(!html.contains("https://"));
+}
@@ -0,0 +1,47 @@
+use std::time::{Duration, SystemTime};
+
+use librespot::core::SpotifyUri;
+use spotify_dl::resolved::{MAX_AGE, ResolvedInputs};
+use spotify_dl::track::Track;
+
+mod common;
+
+const PLAYLIST: &str = "spotify:playlist:37i9dQZF1DXcBWIGoYBM5M";
+
+fn tracks() -> Vec<Track> {
+    let playlist = SpotifyUri::from_uri(PLAYLIST).unwrap();
+    ["4uLU6hMCjMI75M1A2tKUQC", "7GhIk7Il098yCjg4BQjzvb"]
+        .iter()
+        .map(|id| {
+            let id = SpotifyUri::from_uri(&format!("spotify:track:{id}")).unwrap();
+            Track::from_playlist(id, playlist.clone())
+        })
+        .collect()
+}
+
+#[test]
+fn reuses_recent_expansions_only() {
+    let dir = common::scratch_dir();
+    let path = dir.path().join("resolved.json");
+    let now = SystemTime::now();
+    let mut resolved = ResolvedInputs::load(path.clone());
+    resolved.insert(PLAYLIST, &tracks(), now);
+    resolved.save().unwrap();
+
+    let reloaded = ResolvedInputs::load(path.clone());
+    let cached = reloaded.lookup(PLAYLIST, now + MAX_AGE / 2).unwrap();
+    assert_eq!(cached.len(), 2);
+    assert_eq!(cached[0].id, tracks()[0].id);
+    assert_eq!(cached[1].playlist(), tracks()[1].playlist());
+
+    assert!(
+        reloaded
+            .lookup(PLAYLIST, now + MAX_AGE + Duration::from_secs(1))
+            .is_none()
+    );
+    assert!(
+        reloaded
+            .lookup("spotify:album:1DFixLWuPkv3KT3TnV35m3", now)
+            .is_none()
+    );
+}
@@ -0,0 +1,40 @@
+use std::time::Duration;
+
+use chrono::NaiveTime;
+use spotify_dl::schedule::ScheduleWindow;
+
+fn at(hour: u32, minute: u32) -> NaiveTime {
+    NaiveTime::from_hms_opt(hour, minute, 0).unwrap()
+}
+
+#[test]
+fn windows_may_cross_midnight() {
+    let night: ScheduleWindow = "23:00-07:00".parse().unwrap();
+    assert!(night.contains(at(23, 30)));
+    assert!(night.contains(at(3, 0)));
+    assert!(!night.contains(at(7, 0)));
+    assert!(!night.contains(at(12, 0)));
+
+    let offpeak: ScheduleWindow = "01:00-07:00".parse().unwrap();
+    assert!(offpeak.contains(at(1, 0)));
+    assert!(!offpeak.contains(at(0, 59)));
+    assert_eq!(offpeak.to_string(), "01:00-07:00");
+}
+
+#[test]
+fn waits_until_the_next_opening() {
+    let offpeak: ScheduleWindow = "01:00-07:00".parse().unwrap();
+    assert_eq!(offpeak.until_open(at(2, 0)), Duration::ZERO);
+    assert_eq!(offpeak.until_open(at(0, 30)), Duration::from_secs(30 * 60));
+    assert_eq!(
+        offpeak.until_open(at(7, 0)),
+        Duration::from_secs(18 * 60 * 60)
+    );
+}
+
+#[test]
+fn rejects_malformed_windows() {
+    assert!("01:00".parse::<ScheduleWindow>().is_err());
+    assert!("1am-7am".parse::<ScheduleWindow>().is_err());
+    assert!("25:00-07:00".parse::<ScheduleWindow>().is_err());
+}
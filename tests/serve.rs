@@ -0,0 +1,139 @@
+use http::{HeaderMap, HeaderValue, Method, StatusCode, header};
+use http_body_util::BodyExt;
+use spotify_dl::serve::{Status, WebQueue};
+
+async fn request(queue: &WebQueue, method: Method, path: &str, body: &str) -> (StatusCode, String) {
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/json"),
+    );
+    send(queue, method, path, &headers, body).await
+}
+
+async fn send(
+    queue: &WebQueue,
+    method: Method,
+    path: &str,
+    headers: &HeaderMap,
+    body: &str,
+) -> (StatusCode, String) {
+    let response = queue.route(&method, path, headers, body.as_bytes());
+    let status = response.status();
+    let bytes = response.into_body().collect().await.unwrap().to_bytes();
+    (status, String::from_utf8(bytes.to_vec()).unwrap())
+}
+
+#[test]
+fn enqueue_accepts_spotify_links_only() {
+    let queue = WebQueue::default();
+    assert_eq!(
+        queue
+            .enqueue("spotify:playlist:37i9dQZEVXbMDoHDwVN2tF")
+            .unwrap(),
+        0
+    );
+    assert_eq!(
+        queue
+            .enqueue(" https://open.spotify.com/album/4aawyAB9vmqN3uQ7FjRGTy\n")
+            .unwrap(),
+        1
+    );
+    assert!(queue.enqueue("https://example.com/album/1").is_err());
+    assert!(queue.enqueue("").is_err());
+
+    let state = queue.state();
+    assert!(state.active.is_none());
+    let queued: Vec<_> = state
+        .queued
+        .iter()
+        .map(|entry| entry.input.as_str())
+        .collect();
+    assert_eq!(
+        queued,
+        [
+            "spotify:playlist:37i9dQZEVXbMDoHDwVN2tF",
+            "https://open.spotify.com/album/4aawyAB9vmqN3uQ7FjRGTy"
+        ]
+    );
+    assert!(
+        state
+            .queued
+            .iter()
+            .all(|entry| entry.status == Status::Queued)
+    );
+}
+
+#[tokio::test]
+async fn route_serves_the_page_and_the_queue() {
+    let queue = WebQueue::default();
+
+    let (status, page) = request(&queue, Method::GET, "/", "").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(page.contains("/api/enqueue"));
+
+    let (status, _) = request(
+        &queue,
+        Method::POST,
+        "/api/enqueue",
+        r#"{"link": "spotify:track:4uLU6hMCjMI75M1A2tKUQC"}"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    let (status, error) = request(
+        &queue,
+        Method::POST,
+        "/api/enqueue",
+        r#"{"link": "not a link"}"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(error.starts_with("Unsupported link"));
+
+    let (status, state) = request(&queue, Method::GET, "/api/state", "").await;
+    assert_eq!(status, StatusCode::OK);
+    let state: serde_json::Value = serde_json::from_str(&state).unwrap();
+    assert_eq!(state["active"], serde_json::Value::Null);
+    assert_eq!(
+        state["queued"][0]["input"],
+        "spotify:track:4uLU6hMCjMI75M1A2tKUQC"
+    );
+    assert_eq!(state["queued"][0]["status"], "queued");
+    assert_eq!(state["finished"], serde_json::json!([]));
+
+    let (status, _) = request(&queue, Method::GET, "/api/missing", "").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn enqueue_refuses_cross_site_requests() {
+    let queue = WebQueue::default();
+    let link = "spotify:track:4uLU6hMCjMI75M1A2tKUQC";
+
+    // What a form on another site can post without a preflight.
+    let mut headers = HeaderMap::new();
+    headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("text/plain"));
+    let (status, _) = send(&queue, Method::POST, "/api/enqueue", &headers, link).await;
+    assert_eq!(status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+    let body = format!(r#"{{"link": "{link}"}}"#);
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/json"),
+    );
+    headers.insert(header::HOST, HeaderValue::from_static("nas.local:8080"));
+    headers.insert(
+        header::ORIGIN,
+        HeaderValue::from_static("https://example.com"),
+    );
+    let (status, _) = send(&queue, Method::POST, "/api/enqueue", &headers, &body).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert!(queue.state().queued.is_empty());
+
+    headers.insert(
+        header::ORIGIN,
+        HeaderValue::from_static("http://nas.local:8080"),
+    );
+    let (status, _) = send(&queue, Method::POST, "/api/enqueue", &headers, &body).await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+}
@@ -0,0 +1,128 @@
+use std::path::Path;
+use std::time::Duration;
+
+use common::track;
+use spotify_dl::download::RunSummary;
+use spotify_dl::stream::simulate::Simulation;
+
+mod common;
+
+const TRACKS: [&str; 3] = [
+    "spotify:track:4uLU6hMCjMI75M1A2tKUQC",
+    "spotify:track:7GhIk7Il098yCjg4BQjzvb",
+    "spotify:track:0VjIjW4GlUZAMYd2vXMi3b",
+];
+
+async fn download(
+    simulation: &Simulation,
+    tracks: &[&str],
+    destination: &Path,
+    retries: usize,
+) -> RunSummary {
+    let mut options = common::download_options(destination);
+    options.retries = retries;
+    options.stall_timeout = Duration::from_millis(200);
+    common::simulated_downloader(simulation, destination)
+        .download_tracks(tracks.iter().map(|uri| track(uri)).collect(), &options)
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn downloads_simulated_tracks_in_parallel() {
+    let dir = common::scratch_dir();
+    let destination = dir.path();
+    let simulation: Simulation = "duration=1,latency=1".parse().unwrap();
+
+    let summary = download(&simulation, &TRACKS, destination, 3).await;
+    assert_eq!(summary.downloaded.len(), 3);
+    assert!(summary.failed.is_empty());
+    let files = std::fs::read_dir(destination)
+        .unwrap()
+        .filter(|entry| entry.as_ref().unwrap().path().extension() == Some("flac".as_ref()))
+        .count();
+    assert_eq!(files, 3);
+
+    // Everything is in place, so a second run streams nothing.
+    let summary = download(&simulation, &TRACKS, destination, 3).await;
+    assert_eq!(summary.skipped, 3);
+    assert_eq!(simulation.attempts(&track(TRACKS[0]).id), 1);
+}
+
+#[tokio::test]
+async fn stalled_streams_are_restarted() {
+    let dir = common::scratch_dir();
+    let destination = dir.path();
+    let simulation: Simulation = "duration=1,stalls=2".parse().unwrap();
+
+    let summary = download(&simulation, &TRACKS[..1], destination, 3).await;
+    assert_eq!(summary.downloaded.len(), 1);
+    assert_eq!(simulation.attempts(&track(TRACKS[0]).id), 3);
+}
+
+#[tokio::test]
+async fn tracks_fail_once_the_restarts_are_used_up() {
+    let dir = common::scratch_dir();
+    let destination = dir.path();
+    let simulation: Simulation = "duration=1,stalls=5".parse().unwrap();
+
+    let summary = download(&simulation, &TRACKS[..1], destination, 1).await;
+    assert!(summary.downloaded.is_empty());
+    assert_eq!(summary.failed.len(), 1);
+    assert_eq!(simulation.attempts(&track(TRACKS[0]).id), 2);
+}
+
+#[tokio::test]
+async fn load_errors_fail_the_track() {
+    let dir = common::scratch_dir();
+    let destination = dir.path();
+    let simulation: Simulation = "errors=1".parse().unwrap();
+
+    let summary = download(&simulation, &TRACKS[..1], destination, 3).await;
+    assert_eq!(summary.failed.len(), 1);
+    assert!(
+        summary.failed[0]
+            .error
+            .as_deref()
+            .unwrap()
+            .contains("Simulated failure")
+    );
+}
+
+#[tokio::test]
+async fn broken_files_are_kept_until_their_replacement_is_in_place() {
+    let dir = common::scratch_dir();
+    let destination = dir.path();
+    let simulation: Simulation = "duration=1".parse().unwrap();
+    download(&simulation, &TRACKS[..1], destination, 3).await;
+    let file = std::fs::read_dir(destination)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .find(|path| path.extension() == Some("flac".as_ref()))
+        .unwrap();
+    std::fs::write(&file, b"cut off").unwrap();
+
+    let failing: Simulation = "errors=1".parse().unwrap();
+    let summary = download(&failing, &TRACKS[..1], destination, 3).await;
+    assert_eq!(summary.failed.len(), 1);
+    assert_eq!(std::fs::read(&file).unwrap(), b"cut off");
+
+    let summary = download(&simulation, &TRACKS[..1], destination, 3).await;
+    assert_eq!(summary.downloaded.len(), 1);
+    assert!(std::fs::metadata(&file).unwrap().len() > 16 * 1024);
+}
+
+#[test]
+fn parses_simulations() {
+    let simulation: Simulation = "duration=2,latency=20,errors=1,stalls=3,truncations=2"
+        .parse()
+        .unwrap();
+    assert_eq!(simulation.duration, Duration::from_secs(2));
+    assert_eq!(simulation.latency, Duration::from_millis(20));
+    assert_eq!((simulation.errors, simulation.stalls), (1, 3));
+    assert_eq!(simulation.truncations, 2);
+
+    assert!("jitter=5".parse::<Simulation>().is_err());
+    assert!("latency".parse::<Simulation>().is_err());
+    assert!("latency=fast".parse::<Simulation>().is_err());
+}
@@ -0,0 +1,95 @@
+use std::time::Duration;
+
+use spotify_dl::encoder::Samples;
+use spotify_dl::split::{parse_length, parse_split_every, part_count, part_stem, split_samples};
+use spotify_dl::stream::simulate::Simulation;
+
+mod common;
+
+#[test]
+fn parses_lengths_with_units() {
+    assert_eq!(parse_length("90").unwrap(), Duration::from_secs(90));
+    assert_eq!(parse_length("90s").unwrap(), Duration::from_secs(90));
+    assert_eq!(parse_length("30m").unwrap(), Duration::from_secs(30 * 60));
+    assert_eq!(
+        parse_length("2h").unwrap(),
+        Duration::from_secs(2 * 60 * 60)
+    );
+    for invalid in ["", "0m", "m", "10d", "1.5h", "-5m"] {
+        assert!(parse_length(invalid).is_err(), "{invalid}");
+    }
+    assert!(parse_split_every("30s").is_err());
+    assert_eq!(parse_split_every("1m").unwrap(), Duration::from_secs(60));
+}
+
+#[test]
+fn counts_and_numbers_parts() {
+    let minutes = |minutes: u64| Duration::from_secs(minutes * 60);
+    assert_eq!(part_count(minutes(30), minutes(10)), 3);
+    assert_eq!(part_count(minutes(31), minutes(10)), 4);
+    assert_eq!(part_stem("Episode", 2, 3), "Episode (part 2 of 3)");
+    assert_eq!(part_stem("Episode", 2, 12), "Episode (part 02 of 12)");
+}
+
+#[test]
+fn cuts_at_the_quietest_spot_near_each_split_point() {
+    // A minute and a half of noise with a pause ten seconds past the minute
+    // and another further away than the search reaches.
+    let rate = 1000;
+    let frames = 90 * rate;
+    let pause = 70 * rate..71 * rate;
+    let far_pause = 85 * rate..86 * rate;
+    let samples = (0..frames)
+        .flat_map(|frame| {
+            let quiet = pause.contains(&frame) || far_pause.contains(&frame);
+            let sample = if quiet { 0 } else { 1 << 20 };
+            [sample, -sample]
+        })
+        .collect();
+    let samples = Samples::new(samples, rate as u32, 2, 32);
+
+    let parts = split_samples(samples, Duration::from_secs(60), 2);
+    assert_eq!(parts.len(), 2);
+    let cut = parts[0].samples.len() / 2;
+    assert!(pause.contains(&cut), "cut at {cut}");
+    assert_eq!(
+        parts.iter().map(|part| part.samples.len()).sum::<usize>(),
+        frames * 2
+    );
+}
+
+#[tokio::test]
+async fn downloads_long_tracks_as_numbered_parts() {
+    let dir = common::scratch_dir();
+    let destination = dir.path();
+    let simulation: Simulation = "duration=3".parse().unwrap();
+    let mut options = common::download_options(destination);
+    options.split_longer_than = Some(Duration::from_secs(2));
+    options.split_every = Duration::from_secs(1);
+    let track = || common::track("spotify:track:4uLU6hMCjMI75M1A2tKUQC");
+    let download = || async {
+        common::simulated_downloader(&simulation, destination)
+            .download_tracks(vec![track()], &options)
+            .await
+            .unwrap()
+    };
+
+    let summary = download().await;
+    assert_eq!(summary.downloaded.len(), 1);
+    let mut files: Vec<_> = std::fs::read_dir(destination)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+        .filter(|name| name.ends_with(".flac"))
+        .collect();
+    files.sort();
+    assert_eq!(files.len(), 3);
+    for (index, file) in files.iter().enumerate() {
+        let suffix = format!("(part {} of 3).flac", index + 1);
+        assert!(file.ends_with(&suffix), "{files:?}");
+    }
+
+    // With all parts in place, the track is not streamed again.
+    let summary = download().await;
+    assert_eq!(summary.skipped, 1);
+    assert_eq!(simulation.attempts(&track().id), 1);
+}
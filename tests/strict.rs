@@ -0,0 +1,108 @@
+use std::path::Path;
+
+use librespot::core::SpotifyUri;
+use spotify_dl::download::RunSummary;
+use spotify_dl::stream::simulate::Simulation;
+use spotify_dl::track::Track;
+
+mod common;
+
+const TRACK: &str = "spotify:track:4uLU6hMCjMI75M1A2tKUQC";
+
+async fn download(destination: &Path, strict: bool) -> RunSummary {
+    let mut options = common::download_options(destination);
+    options.strict = strict;
+    options.verify = strict;
+    let simulation: Simulation = "duration=6,truncations=1".parse().unwrap();
+    common::simulated_downloader(&simulation, destination)
+        .download_tracks(vec![common::track(TRACK)], &options)
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn truncated_streams_only_fail_in_strict_mode() {
+    let dir = common::scratch_dir();
+    let destination = dir.path().join("music");
+    let summary = download(&destination, false).await;
+    assert_eq!(summary.downloaded.len(), 1);
+    assert!(summary.check_complete().is_ok());
+    std::fs::remove_dir_all(&destination).unwrap();
+
+    let summary = download(&destination, true).await;
+    assert!(summary.downloaded.is_empty());
+    assert_eq!(summary.failed.len(), 1);
+    assert!(
+        summary.failed[0]
+            .error
+            .as_deref()
+            .unwrap()
+            .contains("stream ended after 3s of 6s")
+    );
+    assert!(summary.check_complete().is_err());
+}
+
+#[tokio::test]
+async fn tracks_of_an_incomplete_staged_album_count_as_failed() {
+    let dir = common::scratch_dir();
+    let destination = dir.path().join("music");
+    let mut options = common::download_options(&destination);
+    options.strict = true;
+    options.stage_albums = true;
+    let simulation: Simulation = "duration=6,truncations=1".parse().unwrap();
+    let album = SpotifyUri::from_uri("spotify:album:1DFixLWuPkv3KT3TnV35m3").unwrap();
+    let tracks = [TRACK, "spotify:track:7GhIk7Il098yCjg4BQjzvb"]
+        .map(|uri| SpotifyUri::from_uri(uri).unwrap());
+    let run = |tracks: Vec<Track>| {
+        let downloader = common::simulated_downloader(&simulation, &destination);
+        let options = options.clone();
+        async move { downloader.download_tracks(tracks, &options).await.unwrap() }
+    };
+    let flac_files = || {
+        std::fs::read_dir(&destination)
+            .map(|entries| {
+                entries
+                    .filter(|entry| {
+                        entry.as_ref().unwrap().path().extension() == Some("flac".as_ref())
+                    })
+                    .count()
+            })
+            .unwrap_or(0)
+    };
+
+    // Uses up the truncated stream of the second track on its own.
+    run(vec![Track::from_id(tracks[1].clone())]).await;
+
+    // The first track is cut off, so the second one stays staged and fails
+    // with it.
+    let album_tracks = || {
+        tracks
+            .iter()
+            .map(|track| Track::from_album(track.clone(), album.clone()))
+            .collect::<Vec<_>>()
+    };
+    let summary = run(album_tracks()).await;
+    assert!(summary.downloaded.is_empty());
+    assert_eq!(summary.failed.len(), 2);
+    assert!(summary.check_complete().is_err());
+    assert_eq!(flac_files(), 0);
+
+    let summary = run(album_tracks()).await;
+    assert_eq!(summary.downloaded.len(), 2);
+    assert!(summary.check_complete().is_ok());
+    assert_eq!(flac_files(), 2);
+}
+
+#[test]
+fn deferred_tracks_leave_the_run_incomplete() {
+    assert!(RunSummary::default().check_complete().is_ok());
+    let summary = RunSummary {
+        deferred: 2,
+        ..Default::default()
+    };
+    let err = summary.check_complete().unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "The run is incomplete: 0 tracks failed, 2 were deferred"
+    );
+}
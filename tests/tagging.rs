@@ -0,0 +1,93 @@
+use std::path::Path;
+use std::time::Duration;
+
+use librespot::core::SpotifyUri;
+use spotify_dl::encoder::{Format, Samples, get_encoder};
+use spotify_dl::tagging::{CoverOptions, TagJob, TagPool};
+use spotify_dl::track::TrackMetadata;
+
+mod common;
+
+const TRACKS: [&str; 3] = [
+    "spotify:track:4uLU6hMCjMI75M1A2tKUQC",
+    "spotify:track:7GhIk7Il098yCjg4BQjzvb",
+    "spotify:track:0VjIjW4GlUZAMYd2vXMi3b",
+];
+
+async fn write_silence(path: &Path) {
+    let samples = Samples {
+        samples: vec![0; 44100 * 2],
+        ..Default::default()
+    };
+    let stream = get_encoder(Format::Flac).encode(samples).await.unwrap();
+    stream.write_to_file(path).await.unwrap();
+}
+
+fn job(uri: &str, path: &Path, title: Option<&str>) -> TagJob {
+    TagJob {
+        metadata: TrackMetadata::simulated(
+            SpotifyUri::from_uri(uri).unwrap(),
+            Duration::from_secs(1),
+        ),
+        title: title.map(ToString::to_string),
+        path: path.to_path_buf(),
+        format: Format::Flac,
+        covers: CoverOptions::default(),
+    }
+}
+
+fn read_title(path: &Path) -> Option<String> {
+    metaflac::Tag::read_from_path(path)
+        .ok()?
+        .get_vorbis("TITLE")?
+        .next()
+        .map(ToString::to_string)
+}
+
+#[tokio::test]
+async fn a_single_thread_tags_every_queued_file() {
+    let dir = common::scratch_dir();
+    let pool = TagPool::new(1);
+    let mut paths = Vec::new();
+    for (index, _) in TRACKS.iter().enumerate() {
+        let path = dir.path().join(format!("{index}.flac"));
+        write_silence(&path).await;
+        paths.push(path);
+    }
+
+    let tagged = futures::future::join_all(
+        TRACKS
+            .iter()
+            .zip(&paths)
+            .map(|(uri, path)| pool.tag(job(uri, path, None))),
+    )
+    .await;
+
+    for ((result, uri), path) in tagged.into_iter().zip(TRACKS).zip(&paths) {
+        assert!(result.unwrap().is_empty());
+        let id = uri.rsplit(':').next().unwrap();
+        assert_eq!(read_title(path).as_deref(), Some(id));
+    }
+}
+
+#[tokio::test]
+async fn titles_can_be_replaced() {
+    let dir = common::scratch_dir();
+    let path = dir.path().join("part.flac");
+    write_silence(&path).await;
+
+    TagPool::new(2)
+        .tag(job(TRACKS[0], &path, Some("Mix (part 1 of 3)")))
+        .await
+        .unwrap();
+    assert_eq!(read_title(&path).as_deref(), Some("Mix (part 1 of 3)"));
+}
+
+#[tokio::test]
+async fn missing_files_fail_their_job() {
+    let dir = common::scratch_dir();
+    let result = TagPool::new(1)
+        .tag(job(TRACKS[0], &dir.path().join("missing.flac"), None))
+        .await;
+    assert!(result.is_err());
+}
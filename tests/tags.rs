@@ -0,0 +1,313 @@
+use std::path::Path;
+
+use bytes::Bytes;
+use spotify_dl::encoder::tags::{
+    CoverSize, Tags, convert_png_cover, cover_mime_type, mismatched_tags, read_isrc,
+    read_spotify_uri, resize_cover, store_tags, store_verified_tags,
+};
+use spotify_dl::encoder::{Format, Samples, get_encoder};
+
+mod common;
+
+const CYRILLIC_TITLE: &str = "Группа крови";
+const JAPANESE_TITLE: &str = "夜に駆ける";
+
+fn tags(title: &str, artist: &str) -> Tags {
+    Tags {
+        title: title.to_string(),
+        artists: vec![artist.to_string()],
+        album_title: format!("{title} (アルバム)"),
+        album_cover: Some(Bytes::from_static(&[0xff, 0xd8, 0xff, 0xd9])),
+        spotify_uri: Some("spotify:track:4uLU6hMCjMI75M1A2tKUQC".to_string()),
+        isrc: Some("GBARL9300135".to_string()),
+        track_number: Some(7),
+        disc_number: Some(2),
+        disc_count: Some(3),
+        chapters: Vec::new(),
+        genre: Some("Synth-pop".to_string()),
+    }
+}
+
+async fn write_silence(path: &Path, format: Format) {
+    let samples = Samples {
+        samples: vec![0; 44100 * 2],
+        ..Default::default()
+    };
+    let stream = get_encoder(format).encode(samples).await.unwrap();
+    stream.write_to_file(path).await.unwrap();
+}
+
+fn read_title(path: &Path, format: Format) -> Option<String> {
+    match format {
+        Format::Flac => metaflac::Tag::read_from_path(path)
+            .ok()?
+            .get_vorbis("TITLE")?
+            .next()
+            .map(ToString::to_string),
+        #[cfg(feature = "mp3")]
+        Format::Mp3 => {
+            use id3::TagLike;
+            id3::Tag::read_from_path(path)
+                .ok()?
+                .title()
+                .map(ToString::to_string)
+        }
+        Format::Plugin(_) => None,
+    }
+}
+
+async fn assert_round_trip(path: &Path, format: Format, title: &str, artist: &str) {
+    write_silence(path, format).await;
+    store_tags(path, &tags(title, artist), format)
+        .await
+        .unwrap();
+
+    assert_eq!(read_title(path, format).as_deref(), Some(title));
+    assert_eq!(
+        read_spotify_uri(path, format).as_deref(),
+        Some("spotify:track:4uLU6hMCjMI75M1A2tKUQC")
+    );
+    assert_eq!(read_isrc(path, format).as_deref(), Some("GBARL9300135"));
+}
+
+#[tokio::test]
+async fn flac_tags_unicode_titles_in_unicode_folders() {
+    let scratch = common::scratch_dir();
+    let dir = scratch.path().join("Кино").join("日本語");
+    assert_round_trip(
+        &dir.join(format!("Кино - {CYRILLIC_TITLE}.flac")),
+        Format::Flac,
+        CYRILLIC_TITLE,
+        "Кино",
+    )
+    .await;
+    assert_round_trip(
+        &dir.join(format!("YOASOBI - {JAPANESE_TITLE}.flac")),
+        Format::Flac,
+        JAPANESE_TITLE,
+        "YOASOBI",
+    )
+    .await;
+}
+
+#[cfg(feature = "mp3")]
+#[tokio::test]
+async fn mp3_tags_unicode_titles_in_unicode_folders() {
+    let scratch = common::scratch_dir();
+    let dir = scratch.path().join("Кино").join("日本語");
+    assert_round_trip(
+        &dir.join(format!("Кино - {CYRILLIC_TITLE}.mp3")),
+        Format::Mp3,
+        CYRILLIC_TITLE,
+        "Кино",
+    )
+    .await;
+    assert_round_trip(
+        &dir.join(format!("YOASOBI - {JAPANESE_TITLE}.mp3")),
+        Format::Mp3,
+        JAPANESE_TITLE,
+        "YOASOBI",
+    )
+    .await;
+}
+
+/// Canonical paths on Windows are verbatim (`\\?\C:\...`) wide paths, which
+/// only work when they are handed to the file system as `Path`s.
+#[cfg(windows)]
+#[tokio::test]
+async fn tags_files_under_verbatim_windows_paths() {
+    let scratch = common::scratch_dir();
+    let dir = scratch.path().join("Музыка");
+    std::fs::create_dir_all(&dir).unwrap();
+    let dir = dir.canonicalize().unwrap();
+    assert!(dir.to_string_lossy().starts_with(r"\\?\"));
+    let dir = dir.join("音楽");
+
+    assert_round_trip(
+        &dir.join(format!("{JAPANESE_TITLE}.flac")),
+        Format::Flac,
+        JAPANESE_TITLE,
+        "YOASOBI",
+    )
+    .await;
+    #[cfg(feature = "mp3")]
+    assert_round_trip(
+        &dir.join(format!("{CYRILLIC_TITLE}.mp3")),
+        Format::Mp3,
+        CYRILLIC_TITLE,
+        "Кино",
+    )
+    .await;
+}
+
+/// Windows file names are UTF-16 and may contain unpaired surrogates that
+/// have no `&str` representation at all.
+#[cfg(windows)]
+#[tokio::test]
+async fn tags_files_whose_names_are_not_valid_unicode() {
+    use std::ffi::OsString;
+    use std::os::windows::ffi::OsStringExt;
+
+    let mut name: Vec<u16> = "夜".encode_utf16().collect();
+    name.push(0xD800);
+    name.extend(".flac".encode_utf16());
+    let scratch = common::scratch_dir();
+    let path = scratch.path().join(OsString::from_wide(&name));
+    assert!(path.to_str().is_none());
+
+    assert_round_trip(&path, Format::Flac, JAPANESE_TITLE, "YOASOBI").await;
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn tags_files_whose_names_are_not_valid_utf8() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let scratch = common::scratch_dir();
+    let path = scratch.path().join(OsStr::from_bytes(b"caf\xe9.flac"));
+    assert!(path.to_str().is_none());
+
+    assert_round_trip(&path, Format::Flac, CYRILLIC_TITLE, "Кино").await;
+}
+
+fn png_cover() -> Bytes {
+    let image = image::RgbImage::from_pixel(8, 8, image::Rgb([200, 30, 30]));
+    let mut png = std::io::Cursor::new(Vec::new());
+    image.write_to(&mut png, image::ImageFormat::Png).unwrap();
+    Bytes::from(png.into_inner())
+}
+
+fn read_cover_mime_type(path: &Path, format: Format) -> Option<String> {
+    match format {
+        Format::Flac => metaflac::Tag::read_from_path(path)
+            .ok()?
+            .pictures()
+            .find(|picture| picture.picture_type == metaflac::block::PictureType::CoverFront)
+            .map(|picture| picture.mime_type.clone()),
+        #[cfg(feature = "mp3")]
+        Format::Mp3 => id3::Tag::read_from_path(path)
+            .ok()?
+            .pictures()
+            .find(|picture| picture.picture_type == id3::frame::PictureType::CoverFront)
+            .map(|picture| picture.mime_type.clone()),
+        Format::Plugin(_) => None,
+    }
+}
+
+async fn assert_cover_mime_type(path: &Path, format: Format, cover: Bytes, expected: &str) {
+    write_silence(path, format).await;
+    let mut tags = tags(JAPANESE_TITLE, "YOASOBI");
+    tags.album_cover = Some(cover);
+    store_tags(path, &tags, format).await.unwrap();
+    assert_eq!(
+        read_cover_mime_type(path, format).as_deref(),
+        Some(expected)
+    );
+}
+
+#[tokio::test]
+async fn embeds_covers_with_their_detected_mime_type() {
+    let scratch = common::scratch_dir();
+    let dir = scratch.path();
+    let jpeg = Bytes::from_static(&[0xff, 0xd8, 0xff, 0xd9]);
+
+    assert_cover_mime_type(
+        &dir.join("png.flac"),
+        Format::Flac,
+        png_cover(),
+        "image/png",
+    )
+    .await;
+    assert_cover_mime_type(
+        &dir.join("jpeg.flac"),
+        Format::Flac,
+        jpeg.clone(),
+        "image/jpeg",
+    )
+    .await;
+    #[cfg(feature = "mp3")]
+    {
+        assert_cover_mime_type(&dir.join("png.mp3"), Format::Mp3, png_cover(), "image/png").await;
+        assert_cover_mime_type(&dir.join("jpeg.mp3"), Format::Mp3, jpeg, "image/jpeg").await;
+    }
+}
+
+#[tokio::test]
+async fn skips_covers_that_are_not_images() {
+    let scratch = common::scratch_dir();
+    let path = scratch.path().join("unknown.flac");
+    write_silence(&path, Format::Flac).await;
+    let mut tags = tags(CYRILLIC_TITLE, "Кино");
+    tags.album_cover = Some(Bytes::from_static(b"<html>not found</html>"));
+    store_tags(&path, &tags, Format::Flac).await.unwrap();
+    assert_eq!(read_cover_mime_type(&path, Format::Flac), None);
+}
+
+#[test]
+fn converts_png_covers_to_jpeg() {
+    let converted = convert_png_cover(png_cover()).unwrap();
+    assert_eq!(cover_mime_type(&converted), Some("image/jpeg"));
+
+    let jpeg = Bytes::from_static(&[0xff, 0xd8, 0xff, 0xd9]);
+    assert_eq!(convert_png_cover(jpeg.clone()).unwrap(), jpeg);
+}
+
+#[test]
+fn resizes_covers_up_and_down() {
+    let size: CoverSize = "16x16".parse().unwrap();
+    assert!("16".parse::<CoverSize>().is_err());
+    assert!("0x16".parse::<CoverSize>().is_err());
+
+    let upscaled = resize_cover(png_cover(), size, 80).unwrap();
+    assert_eq!(cover_mime_type(&upscaled), Some("image/jpeg"));
+    let image = image::load_from_memory(&upscaled).unwrap();
+    assert_eq!((image.width(), image.height()), (16, 16));
+
+    let small: CoverSize = "4x4".parse().unwrap();
+    let downscaled = resize_cover(upscaled.clone(), small, 80).unwrap();
+    let image = image::load_from_memory(&downscaled).unwrap();
+    assert_eq!((image.width(), image.height()), (4, 4));
+
+    // A JPEG of the right size is embedded as it is.
+    assert_eq!(resize_cover(upscaled.clone(), size, 80).unwrap(), upscaled);
+}
+
+#[tokio::test]
+async fn verified_tags_report_fields_that_do_not_read_back() {
+    let dir = common::scratch_dir();
+    let path = dir.path().join("verified.flac");
+    write_silence(&path, Format::Flac).await;
+    let tags = tags("Title", "Artist");
+    assert!(
+        store_verified_tags(&path, &tags, Format::Flac)
+            .await
+            .unwrap()
+            .is_empty()
+    );
+
+    let mut tag = metaflac::Tag::read_from_path(&path).unwrap();
+    tag.set_vorbis("TITLE", vec!["Other"]);
+    tag.remove_picture_type(metaflac::block::PictureType::CoverFront);
+    tag.write_to_path(&path).unwrap();
+    assert_eq!(
+        mismatched_tags(&path, &tags, Format::Flac),
+        ["title", "cover"]
+    );
+}
+
+#[cfg(feature = "mp3")]
+#[tokio::test]
+async fn verified_mp3_tags_round_trip() {
+    let dir = common::scratch_dir();
+    let path = dir.path().join("verified.mp3");
+    write_silence(&path, Format::Mp3).await;
+    let tags = tags(JAPANESE_TITLE, "YOASOBI");
+    assert!(
+        store_verified_tags(&path, &tags, Format::Mp3)
+            .await
+            .unwrap()
+            .is_empty()
+    );
+    assert!(mismatched_tags(&path, &tags, Format::Mp3).is_empty());
+}
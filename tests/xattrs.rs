@@ -0,0 +1,29 @@
+use spotify_dl::xattrs::{read_provenance, write_provenance};
+
+mod common;
+
+#[test]
+fn provenance_round_trips_through_the_file_system() {
+    let dir = common::scratch_dir();
+    let path = dir.path().join("track.wav");
+    std::fs::write(&path, b"RIFF").unwrap();
+
+    let written = write_provenance(
+        &path,
+        "spotify:track:4uLU6hMCjMI75M1A2tKUQC",
+        Some("spotify:playlist:37i9dQZEVXbMDoHDwVN2tF"),
+    );
+    // Not every file system has extended attributes, e.g. tmpfs on older
+    // kernels.
+    if written.is_err() {
+        return;
+    }
+
+    assert_eq!(
+        read_provenance(&path),
+        Some((
+            "spotify:track:4uLU6hMCjMI75M1A2tKUQC".to_string(),
+            Some("spotify:playlist:37i9dQZEVXbMDoHDwVN2tF".to_string())
+        ))
+    );
+}